@@ -0,0 +1,416 @@
+// src/ab.rs
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::analysis::Monitoring;
+use crate::dsp_module::AudioProcessor;
+use crate::process_context::ProcessContext;
+
+/// Time constant of the loudness estimates used for level matching.
+const LEVEL_MATCH_SECS: f32 = 3.0;
+
+/// Largest correction level matching applies, in dB either way.
+const MAX_MATCH_DB: f32 = 24.0;
+
+/// The A/B switch as saved in a session. The level matching gain is
+/// measured again rather than saved.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AbSettings {
+    pub b_selected: bool,
+    pub level_match: bool,
+    pub crossfade_ms: f32,
+}
+
+impl Default for AbSettings {
+    fn default() -> Self {
+        AbSwitch::new().settings()
+    }
+}
+
+/// Which side of an A/B comparison is heard, shared between the GUI and
+/// `AbProcessor`.
+pub struct AbSwitch {
+    b_selected: AtomicBool,
+    level_match: AtomicBool,
+    crossfade_ms: AtomicU32,
+    /// Gain currently applied to B by level matching, written by the processor.
+    match_gain_db: AtomicU32,
+}
+
+impl AbSwitch {
+    pub fn new() -> Self {
+        Self {
+            b_selected: AtomicBool::new(true),
+            level_match: AtomicBool::new(false),
+            crossfade_ms: AtomicU32::new(20.0f32.to_bits()),
+            match_gain_db: AtomicU32::new(0.0f32.to_bits()),
+        }
+    }
+
+    pub fn is_b_selected(&self) -> bool {
+        self.b_selected.load(Ordering::Relaxed)
+    }
+
+    pub fn select_b(&self, b: bool) {
+        self.b_selected.store(b, Ordering::Relaxed);
+    }
+
+    pub fn level_match(&self) -> bool {
+        self.level_match.load(Ordering::Relaxed)
+    }
+
+    pub fn set_level_match(&self, enabled: bool) {
+        self.level_match.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn crossfade_ms(&self) -> f32 {
+        f32::from_bits(self.crossfade_ms.load(Ordering::Relaxed))
+    }
+
+    pub fn set_crossfade_ms(&self, ms: f32) {
+        self.crossfade_ms.store(ms.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn match_gain_db(&self) -> f32 {
+        f32::from_bits(self.match_gain_db.load(Ordering::Relaxed))
+    }
+
+    pub fn settings(&self) -> AbSettings {
+        AbSettings {
+            b_selected: self.is_b_selected(),
+            level_match: self.level_match(),
+            crossfade_ms: self.crossfade_ms(),
+        }
+    }
+
+    pub fn restore(&self, settings: &AbSettings) {
+        self.select_b(settings.b_selected);
+        self.set_level_match(settings.level_match);
+        self.set_crossfade_ms(settings.crossfade_ms);
+    }
+}
+
+/// Runs the chain under test (B) and a reference (A: a second chain, or the
+/// dry signal when there is none) on every block, and crossfades between them
+/// when the switch changes. Both sides keep running, so switching is sample
+/// accurate and never hears a cold filter or delay.
+pub struct AbProcessor {
+    a: Option<Box<dyn AudioProcessor>>,
+    b: Box<dyn AudioProcessor>,
+    switch: Arc<AbSwitch>,
+    a_buffers: Vec<Vec<f32>>,
+    /// 0.0 is all A, 1.0 is all B.
+    mix: f32,
+    power_a: f32,
+    power_b: f32,
+    gain: f32,
+    /// Where the time spent in B is recorded.
+    monitoring: Option<Arc<Monitoring>>,
+}
+
+impl AbProcessor {
+    pub fn new(b: Box<dyn AudioProcessor>, a: Option<Box<dyn AudioProcessor>>, switch: Arc<AbSwitch>) -> Self {
+        let mix = if switch.is_b_selected() { 1.0 } else { 0.0 };
+        Self {
+            a,
+            b,
+            switch,
+            a_buffers: Vec::new(),
+            mix,
+            power_a: 0.0,
+            power_b: 0.0,
+            gain: 1.0,
+            monitoring: None,
+        }
+    }
+
+    /// Records the load of the chain under test in `monitoring.chain_cpu`.
+    /// Only B is timed, not the reference, the copies or the level matching.
+    pub fn with_cpu_meter(mut self, monitoring: Arc<Monitoring>) -> Self {
+        self.monitoring = Some(monitoring);
+        self
+    }
+}
+
+fn mean_square(channels: &[Vec<f32>], frames: usize) -> f32 {
+    let count = (channels.len() * frames).max(1) as f32;
+    channels
+        .iter()
+        .map(|ch| ch[..frames].iter().map(|s| s * s).sum::<f32>())
+        .sum::<f32>()
+        / count
+}
+
+fn approach(value: f32, target: f32, step: f32) -> f32 {
+    if value < target {
+        (value + step).min(target)
+    } else {
+        (value - step).max(target)
+    }
+}
+
+impl AudioProcessor for AbProcessor {
+    fn prepare(&mut self, sample_rate: u32, max_block: usize, channels: usize) {
+        if let Some(ref mut a) = self.a {
+            a.prepare(sample_rate, max_block, channels);
+        }
+        self.b.prepare(sample_rate, max_block, channels);
+        self.a_buffers = vec![Vec::with_capacity(max_block); channels];
+    }
+
+    fn process(&mut self, ctx: &mut ProcessContext) {
+        let frames = ctx.block_len;
+        for (a, input) in self.a_buffers.iter_mut().zip(ctx.channels.iter()) {
+            a.clear();
+            a.extend_from_slice(&input[..frames]);
+        }
+
+        if let Some(ref mut a) = self.a {
+            let mut a_ctx = ProcessContext {
+                channels: &mut self.a_buffers,
+                sample_rate: ctx.sample_rate,
+                block_len: frames,
+                position: ctx.position,
+                params: ctx.params,
+                smoothed: ctx.smoothed,
+            };
+            a.process(&mut a_ctx);
+        }
+        let start = Instant::now();
+        self.b.process(ctx);
+        if let Some(ref monitoring) = self.monitoring {
+            monitoring.chain_cpu.record(start.elapsed(), frames, ctx.sample_rate);
+        }
+
+        // Loudness of both sides is tracked all the time so level matching
+        // is already settled when it's switched on
+        let sample_rate = ctx.sample_rate.max(1) as f32;
+        let alpha = 1.0 - (-(frames as f32) / (LEVEL_MATCH_SECS * sample_rate)).exp();
+        self.power_a += alpha * (mean_square(&self.a_buffers, frames) - self.power_a);
+        self.power_b += alpha * (mean_square(ctx.channels, frames) - self.power_b);
+
+        let max_gain = 10f32.powf(MAX_MATCH_DB / 20.0);
+        let target_gain = if !self.switch.level_match() {
+            1.0
+        } else if self.power_a > 1e-10 && self.power_b > 1e-10 {
+            (self.power_a / self.power_b).sqrt().clamp(1.0 / max_gain, max_gain)
+        } else {
+            self.gain
+        };
+        self.switch
+            .match_gain_db
+            .store((20.0 * target_gain.log10()).to_bits(), Ordering::Relaxed);
+
+        let target_mix = if self.switch.is_b_selected() { 1.0 } else { 0.0 };
+        if self.mix == 1.0 && target_mix == 1.0 && self.gain == 1.0 && target_gain == 1.0 {
+            // Plain B, leave the block untouched
+            return;
+        }
+
+        let fade_samples = (self.switch.crossfade_ms() / 1000.0 * sample_rate).max(1.0);
+        let mix_step = 1.0 / fade_samples;
+        let gain_step = (target_gain - self.gain) / frames.max(1) as f32;
+        for frame in 0..frames {
+            self.mix = approach(self.mix, target_mix, mix_step);
+            self.gain += gain_step;
+            let (a_weight, b_weight) = (1.0 - self.mix, self.mix * self.gain);
+            for (b, a) in ctx.channels.iter_mut().zip(&self.a_buffers) {
+                b[frame] = a[frame] * a_weight + b[frame] * b_weight;
+            }
+        }
+        self.gain = target_gain;
+    }
+
+    fn reset(&mut self) {
+        if let Some(ref mut a) = self.a {
+            a.reset();
+        }
+        self.b.reset();
+    }
+
+    /// What's heard when B is selected; the two sides aren't re-aligned.
+    fn latency(&self) -> usize {
+        self.b.latency()
+    }
+}
+
+/// One ABX answer.
+pub struct AbxTrial {
+    pub x_is_b: bool,
+    pub answered_b: bool,
+}
+
+impl AbxTrial {
+    pub fn correct(&self) -> bool {
+        self.x_is_b == self.answered_b
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Listening {
+    A,
+    B,
+    X,
+}
+
+/// Blind ABX test: X is randomly A or B each trial, the listener says which,
+/// and the answers are kept and can be saved as CSV.
+pub struct AbxPanel {
+    active: bool,
+    x_is_b: bool,
+    listening: Listening,
+    trials: Vec<AbxTrial>,
+    results_path: String,
+    status: String,
+}
+
+/// Chance of getting at least `correct` of `trials` right by guessing.
+pub fn guess_probability(correct: usize, trials: usize) -> f64 {
+    let binomial = |n: usize, k: usize| (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64);
+    (correct..=trials).map(|k| binomial(trials, k)).sum::<f64>() / 2f64.powi(trials as i32)
+}
+
+impl AbxPanel {
+    pub fn new() -> Self {
+        Self {
+            active: false,
+            x_is_b: false,
+            listening: Listening::X,
+            trials: Vec::new(),
+            results_path: "abx_results.csv".to_string(),
+            status: String::new(),
+        }
+    }
+
+    /// True while a test is running and the normal A/B controls should be hidden.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    fn listen(&mut self, listening: Listening, switch: &AbSwitch) {
+        self.listening = listening;
+        switch.select_b(match listening {
+            Listening::A => false,
+            Listening::B => true,
+            Listening::X => self.x_is_b,
+        });
+    }
+
+    fn next_trial(&mut self, switch: &AbSwitch) {
+        self.x_is_b = rand::random();
+        self.listen(Listening::X, switch);
+    }
+
+    fn answer(&mut self, answered_b: bool, switch: &AbSwitch) {
+        self.trials.push(AbxTrial {
+            x_is_b: self.x_is_b,
+            answered_b,
+        });
+        self.next_trial(switch);
+    }
+
+    fn summary(&self) -> String {
+        let correct = self.trials.iter().filter(|t| t.correct()).count();
+        format!(
+            "{}/{} correct, p = {:.3}",
+            correct,
+            self.trials.len(),
+            guess_probability(correct, self.trials.len())
+        )
+    }
+
+    fn to_csv(&self) -> String {
+        let side = |b: bool| if b { "B" } else { "A" };
+        let mut csv = String::from("trial,x,answer,correct\n");
+        for (i, trial) in self.trials.iter().enumerate() {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{}",
+                i + 1,
+                side(trial.x_is_b),
+                side(trial.answered_b),
+                trial.correct()
+            );
+        }
+        csv
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, switch: &AbSwitch) {
+        if !self.active {
+            ui.horizontal(|ui| {
+                if ui.button("Start ABX test").clicked() {
+                    self.active = true;
+                    self.trials.clear();
+                    self.status.clear();
+                    self.next_trial(switch);
+                }
+                if !self.trials.is_empty() {
+                    ui.label(format!("Last test: {}", self.summary()));
+                }
+            });
+            if !self.trials.is_empty() {
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.results_path).desired_width(200.0));
+                    if ui.button("Save CSV").clicked() {
+                        self.status = match fs::write(&self.results_path, self.to_csv()) {
+                            Ok(()) => format!("Saved {}", self.results_path),
+                            Err(e) => format!("Save failed: {}", e),
+                        };
+                    }
+                });
+            }
+            if !self.status.is_empty() {
+                ui.label(&self.status);
+            }
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Trial {}", self.trials.len() + 1));
+            for (listening, label) in [(Listening::A, "A"), (Listening::B, "B"), (Listening::X, "X")] {
+                if ui.selectable_label(self.listening == listening, label).clicked() {
+                    self.listen(listening, switch);
+                }
+            }
+            ui.separator();
+            if ui.button("X is A").clicked() {
+                self.answer(false, switch);
+            }
+            if ui.button("X is B").clicked() {
+                self.answer(true, switch);
+            }
+        });
+        ui.horizontal(|ui| {
+            // Results stay hidden until the end so they can't sway the answers
+            ui.label(format!("{} answered", self.trials.len()));
+            if ui.button("Finish").clicked() {
+                self.active = false;
+                switch.select_b(true);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guess_probability_is_the_binomial_tail() {
+        assert!((guess_probability(0, 10) - 1.0).abs() < 1e-12);
+        assert!((guess_probability(10, 10) - 1.0 / 1024.0).abs() < 1e-12);
+        // 9 or 10 of 10: (10 + 1) / 1024
+        assert!((guess_probability(9, 10) - 11.0 / 1024.0).abs() < 1e-12);
+        // The usual 12 of 16 threshold sits just under 5%
+        let p = guess_probability(12, 16);
+        assert!(p < 0.05 && p > 0.03, "p = {}", p);
+        assert!((guess_probability(1, 1) - 0.5).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,136 @@
+// src/analysis/cpu.rs
+
+use eframe::egui::{self, Color32, Pos2, Rect};
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Number of recent blocks the average, peak and histogram are taken over.
+pub const HISTORY_BLOCKS: usize = 256;
+
+/// 10% wide bins from 0 to 100%, plus one for blocks over their deadline.
+pub const HISTOGRAM_BINS: usize = 11;
+
+/// Real-time load of the module's process call, measured on the audio thread
+/// around every block. Load is processing time divided by the block's
+/// duration at the stream's sample rate.
+pub struct CpuMeter {
+    history: Mutex<LoadHistory>,
+    blocks: AtomicU64,
+    overruns: AtomicU64,
+}
+
+struct LoadHistory {
+    loads: [f32; HISTORY_BLOCKS],
+    write_pos: usize,
+    len: usize,
+}
+
+#[derive(Clone, Default)]
+pub struct CpuStats {
+    /// Mean load over the history, in percent.
+    pub average: f32,
+    /// Highest load over the history, in percent.
+    pub peak: f32,
+    pub histogram: [usize; HISTOGRAM_BINS],
+    /// Blocks since the stream started.
+    pub blocks: u64,
+    /// Blocks whose processing took longer than their own duration.
+    pub overruns: u64,
+}
+
+impl CpuMeter {
+    pub fn new() -> Self {
+        Self {
+            history: Mutex::new(LoadHistory {
+                loads: [0.0; HISTORY_BLOCKS],
+                write_pos: 0,
+                len: 0,
+            }),
+            blocks: AtomicU64::new(0),
+            overruns: AtomicU64::new(0),
+        }
+    }
+
+    pub fn reset(&self) {
+        let mut history = self.history.lock();
+        history.write_pos = 0;
+        history.len = 0;
+        self.blocks.store(0, Ordering::Relaxed);
+        self.overruns.store(0, Ordering::Relaxed);
+    }
+
+    /// Called from the audio thread after each processed block.
+    pub fn record(&self, elapsed: Duration, frames: usize, sample_rate: u32) {
+        if frames == 0 || sample_rate == 0 {
+            return;
+        }
+        let deadline = frames as f64 / sample_rate as f64;
+        let load = (elapsed.as_secs_f64() / deadline * 100.0) as f32;
+
+        self.blocks.fetch_add(1, Ordering::Relaxed);
+        if load > 100.0 {
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if let Some(mut history) = self.history.try_lock() {
+            let pos = history.write_pos;
+            history.loads[pos] = load;
+            history.write_pos = (pos + 1) % HISTORY_BLOCKS;
+            history.len = (history.len + 1).min(HISTORY_BLOCKS);
+        }
+    }
+
+    pub fn stats(&self) -> CpuStats {
+        let history = self.history.lock();
+        let mut stats = CpuStats {
+            blocks: self.blocks.load(Ordering::Relaxed),
+            overruns: self.overruns.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+        if history.len == 0 {
+            return stats;
+        }
+
+        // The ring is filled from index 0, so the valid entries are always
+        // the first `len` until it wraps.
+        let loads = &history.loads[..history.len];
+        stats.average = loads.iter().sum::<f32>() / loads.len() as f32;
+        stats.peak = loads.iter().cloned().fold(0.0, f32::max);
+        for load in loads {
+            let bin = ((load / 10.0) as usize).min(HISTOGRAM_BINS - 1);
+            stats.histogram[bin] += 1;
+        }
+        stats
+    }
+}
+
+/// Small bar chart of the load histogram, overruns in red.
+pub fn show_histogram(ui: &mut egui::Ui, stats: &CpuStats) {
+    let bar_width = 5.0;
+    let size = egui::vec2(bar_width * HISTOGRAM_BINS as f32, 16.0);
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 1.0, ui.visuals().extreme_bg_color);
+
+    let max_count = stats.histogram.iter().cloned().max().unwrap_or(0).max(1) as f32;
+    for (bin, count) in stats.histogram.iter().enumerate() {
+        if *count == 0 {
+            continue;
+        }
+        let height = rect.height() * *count as f32 / max_count;
+        let left = rect.left() + bin as f32 * bar_width;
+        let bar = Rect::from_min_max(
+            Pos2::new(left, rect.bottom() - height),
+            Pos2::new(left + bar_width - 1.0, rect.bottom()),
+        );
+        let color = if bin == HISTOGRAM_BINS - 1 {
+            Color32::from_rgb(230, 80, 80)
+        } else {
+            Color32::from_rgb(90, 200, 255)
+        };
+        painter.rect_filled(bar, 0.0, color);
+    }
+
+    response.on_hover_text("Block load histogram, 10% per bar. Red: over deadline.");
+}
@@ -0,0 +1,554 @@
+// src/analysis/loudness.rs
+
+use eframe::egui::{self, Pos2, Stroke};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::fmt;
+
+use super::plot;
+use super::Monitoring;
+
+/// Blocks quieter than this never count towards integrated loudness or LRA.
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+
+/// Integrated loudness ignores blocks this far below the ungated level.
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Loudness range ignores short-term values this far below their mean.
+const LRA_RELATIVE_GATE_LU: f64 = -20.0;
+
+/// Hop between gating blocks; 400 ms blocks at 75% overlap.
+const SUB_BLOCK_SECS: f64 = 0.1;
+
+const MOMENTARY_SUB_BLOCKS: usize = 4;
+const SHORT_TERM_SUB_BLOCKS: usize = 30;
+
+/// Readings kept for the history graph, one per sub-block.
+const HISTORY_LEN: usize = 600;
+
+/// Finished sub-blocks held on the audio side while the GUI has the
+/// readings locked, about five seconds' worth.
+const PENDING_SUB_BLOCKS: usize = 50;
+
+/// Resolution of the gating histograms and the loudest value they tell
+/// apart; anything louder lands in the top bin.
+const HISTOGRAM_STEP_LU: f64 = 0.1;
+const HISTOGRAM_TOP_LUFS: f64 = 10.0;
+const HISTOGRAM_BINS: usize = ((HISTOGRAM_TOP_LUFS - ABSOLUTE_GATE_LUFS) / HISTOGRAM_STEP_LU) as usize;
+
+const GRAPH_RANGE: (f32, f32) = (-60.0, 0.0);
+
+/// Direct form II transposed biquad in f64, since the K-weighting high-pass
+/// sits very close to DC.
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The two BS.1770 pre-filter stages (head shelf and RLB high-pass) for any
+/// sample rate.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f64;
+
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    [shelf, high_pass]
+}
+
+/// BS.1770 channel weights: 1.0 for front channels, 1.41 for surrounds and
+/// nothing for the LFE of a 5.1 layout.
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4) | (6, 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    if energy > 0.0 {
+        -0.691 + 10.0 * energy.log10()
+    } else {
+        f64::NEG_INFINITY
+    }
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), v| (sum + v, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// Summary of a finished or running measurement, in LUFS and LU.
+#[derive(Clone, Copy, Debug)]
+pub struct LoudnessReport {
+    pub integrated: f64,
+    pub range: f64,
+    pub max_momentary: f64,
+    pub max_short_term: f64,
+}
+
+fn format_lufs(lufs: f64) -> String {
+    if lufs.is_finite() {
+        format!("{:.1}", lufs)
+    } else {
+        "-inf".to_string()
+    }
+}
+
+impl fmt::Display for LoudnessReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "I {} LUFS, LRA {:.1} LU, max M {} LUFS, max S {} LUFS",
+            format_lufs(self.integrated),
+            self.range,
+            format_lufs(self.max_momentary),
+            format_lufs(self.max_short_term),
+        )
+    }
+}
+
+/// Count and summed energy of gating blocks in 0.1 LU bins above the
+/// absolute gate. Fixed size, so a measurement of any length never
+/// allocates, and gating is a walk over the bins instead of a sort.
+struct Histogram {
+    counts: Vec<u64>,
+    energies: Vec<f64>,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            counts: vec![0; HISTOGRAM_BINS],
+            energies: vec![0.0; HISTOGRAM_BINS],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.counts.fill(0);
+        self.energies.fill(0.0);
+    }
+
+    /// Adds one block, dropping it if it is below the absolute gate.
+    fn add(&mut self, energy: f64) {
+        let lufs = energy_to_lufs(energy);
+        if lufs <= ABSOLUTE_GATE_LUFS {
+            return;
+        }
+        let bin = (((lufs - ABSOLUTE_GATE_LUFS) / HISTOGRAM_STEP_LU) as usize).min(HISTOGRAM_BINS - 1);
+        self.counts[bin] += 1;
+        self.energies[bin] += energy;
+    }
+
+    fn bin_lufs(bin: usize) -> f64 {
+        ABSOLUTE_GATE_LUFS + (bin as f64 + 0.5) * HISTOGRAM_STEP_LU
+    }
+
+    /// First bin whose centre is above `gate_lufs`.
+    fn first_bin_above(gate_lufs: f64) -> usize {
+        (((gate_lufs - ABSOLUTE_GATE_LUFS) / HISTOGRAM_STEP_LU).round().max(0.0) as usize).min(HISTOGRAM_BINS)
+    }
+
+    /// Mean energy of the blocks from `first` up.
+    fn mean_from(&self, first: usize) -> Option<f64> {
+        let count: u64 = self.counts[first..].iter().sum();
+        (count > 0).then(|| self.energies[first..].iter().sum::<f64>() / count as f64)
+    }
+
+    /// Loudness of the block at fraction `p` of those from `first` up.
+    fn percentile_from(&self, first: usize, p: f64) -> Option<f64> {
+        let count: u64 = self.counts[first..].iter().sum();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((count - 1) as f64 * p).round() as u64;
+        let mut seen = 0;
+        for (bin, &n) in self.counts.iter().enumerate().skip(first) {
+            seen += n;
+            if seen > rank {
+                return Some(Self::bin_lufs(bin));
+            }
+        }
+        None
+    }
+}
+
+/// K-weighting filters and the sub-block being summed. Only the audio
+/// thread processes, so its lock is never contended during playback.
+struct Weighting {
+    sample_rate: u32,
+    channels: usize,
+    filters: Vec<[Biquad; 2]>,
+    sub_block_len: usize,
+    sub_block_pos: usize,
+    sub_block_sum: f64,
+    /// Mean squares of finished sub-blocks not yet handed to the readings.
+    pending: VecDeque<f64>,
+}
+
+/// The gated measurement, fed with one sub-block energy at a time. Every
+/// update is constant time and allocation free.
+struct Readings {
+    /// Mean square of the most recent sub-blocks, newest last.
+    sub_blocks: VecDeque<f64>,
+    /// Every 400 ms gating block so far.
+    blocks: Histogram,
+    /// Every 3 s short-term window so far, for LRA.
+    short_terms: Histogram,
+    max_momentary: f64,
+    max_short_term: f64,
+    /// Short-term loudness per sub-block for the graph.
+    history: VecDeque<f32>,
+}
+
+impl Readings {
+    fn new() -> Self {
+        Self {
+            sub_blocks: VecDeque::with_capacity(SHORT_TERM_SUB_BLOCKS + 1),
+            blocks: Histogram::new(),
+            short_terms: Histogram::new(),
+            max_momentary: f64::NEG_INFINITY,
+            max_short_term: f64::NEG_INFINITY,
+            history: VecDeque::with_capacity(HISTORY_LEN + 1),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.sub_blocks.clear();
+        self.blocks.clear();
+        self.short_terms.clear();
+        self.max_momentary = f64::NEG_INFINITY;
+        self.max_short_term = f64::NEG_INFINITY;
+        self.history.clear();
+    }
+
+    fn push_sub_block(&mut self, energy: f64) {
+        self.sub_blocks.push_back(energy);
+        if self.sub_blocks.len() > SHORT_TERM_SUB_BLOCKS {
+            self.sub_blocks.pop_front();
+        }
+
+        let momentary = self.window_energy(MOMENTARY_SUB_BLOCKS);
+        if let Some(energy) = momentary {
+            self.blocks.add(energy);
+            self.max_momentary = self.max_momentary.max(energy_to_lufs(energy));
+        }
+        let short_term = self.window_energy(SHORT_TERM_SUB_BLOCKS);
+        if let Some(energy) = short_term {
+            self.short_terms.add(energy);
+            self.max_short_term = self.max_short_term.max(energy_to_lufs(energy));
+        }
+
+        self.history
+            .push_back(short_term.map_or(f32::NEG_INFINITY, |e| energy_to_lufs(e) as f32));
+        if self.history.len() > HISTORY_LEN {
+            self.history.pop_front();
+        }
+    }
+
+    /// Mean energy of the last `sub_blocks` sub-blocks, once there are that many.
+    fn window_energy(&self, sub_blocks: usize) -> Option<f64> {
+        if self.sub_blocks.len() < sub_blocks {
+            return None;
+        }
+        mean(self.sub_blocks.iter().rev().take(sub_blocks).copied())
+    }
+
+    fn integrated(&self) -> f64 {
+        let Some(ungated) = self.blocks.mean_from(0) else {
+            return f64::NEG_INFINITY;
+        };
+        let relative = Histogram::first_bin_above(energy_to_lufs(ungated) + RELATIVE_GATE_LU);
+        self.blocks.mean_from(relative).map_or(f64::NEG_INFINITY, energy_to_lufs)
+    }
+
+    fn loudness_range(&self) -> f64 {
+        let Some(ungated) = self.short_terms.mean_from(0) else {
+            return 0.0;
+        };
+        let relative = Histogram::first_bin_above(energy_to_lufs(ungated) + LRA_RELATIVE_GATE_LU);
+        match (self.short_terms.percentile_from(relative, 0.10), self.short_terms.percentile_from(relative, 0.95)) {
+            (Some(low), Some(high)) => high - low,
+            _ => 0.0,
+        }
+    }
+
+    fn report(&self) -> LoudnessReport {
+        LoudnessReport {
+            integrated: self.integrated(),
+            range: self.loudness_range(),
+            max_momentary: self.max_momentary,
+            max_short_term: self.max_short_term,
+        }
+    }
+}
+
+/// What the GUI shows of one meter, copied out in one short lock.
+pub struct LoudnessSnapshot {
+    pub momentary: f64,
+    pub short_term: f64,
+    pub report: LoudnessReport,
+    /// Short-term loudness per sub-block, oldest first.
+    pub history: Vec<f32>,
+}
+
+/// Momentary, short-term and integrated loudness and loudness range per
+/// EBU R128 / ITU-R BS.1770. The audio thread filters every block and hands
+/// finished 100 ms sub-blocks to the readings when it can take their lock,
+/// so a GUI read delays them instead of losing them or blocking the audio.
+pub struct LoudnessMeter {
+    weighting: Mutex<Weighting>,
+    readings: Mutex<Readings>,
+}
+
+impl LoudnessMeter {
+    pub fn new() -> Self {
+        Self {
+            weighting: Mutex::new(Weighting {
+                sample_rate: 0,
+                channels: 0,
+                filters: Vec::new(),
+                sub_block_len: 0,
+                sub_block_pos: 0,
+                sub_block_sum: 0.0,
+                pending: VecDeque::with_capacity(PENDING_SUB_BLOCKS),
+            }),
+            readings: Mutex::new(Readings::new()),
+        }
+    }
+
+    /// Starts a new measurement for a stream of this format. Not real-time safe.
+    pub fn prepare(&self, sample_rate: u32, channels: usize) {
+        let mut weighting = self.weighting.lock();
+        weighting.sample_rate = sample_rate;
+        weighting.channels = channels;
+        weighting.filters = vec![k_weighting(sample_rate.max(1)); channels];
+        weighting.sub_block_len = ((sample_rate as f64 * SUB_BLOCK_SECS) as usize).max(1);
+        weighting.sub_block_pos = 0;
+        weighting.sub_block_sum = 0.0;
+        weighting.pending.clear();
+        self.readings.lock().reset();
+    }
+
+    /// Clears the readings. The filters keep running, so the next sub-block
+    /// may still carry a little of what came before.
+    pub fn reset(&self) {
+        self.readings.lock().reset();
+    }
+
+    /// Feeds one block of planar audio. Called from the audio thread.
+    pub fn process(&self, channels: &[Vec<f32>], frames: usize) {
+        let mut guard = self.weighting.lock();
+        let weighting = &mut *guard;
+        if weighting.sample_rate == 0 {
+            return;
+        }
+        let count = weighting.channels.min(channels.len());
+        // Runs up to each sub-block boundary, one channel at a time
+        let mut start = 0;
+        while start < frames {
+            let len = (weighting.sub_block_len - weighting.sub_block_pos).min(frames - start);
+            for (ch, filters) in weighting.filters.iter_mut().enumerate().take(count) {
+                let weight = channel_weight(ch, weighting.channels);
+                let [shelf, high_pass] = filters;
+                for &x in &channels[ch][start..start + len] {
+                    let y = high_pass.process(shelf.process(x as f64));
+                    weighting.sub_block_sum += weight * y * y;
+                }
+            }
+            start += len;
+            weighting.sub_block_pos += len;
+            if weighting.sub_block_pos == weighting.sub_block_len {
+                if weighting.pending.len() == PENDING_SUB_BLOCKS {
+                    weighting.pending.pop_front();
+                }
+                weighting.pending.push_back(weighting.sub_block_sum / weighting.sub_block_len as f64);
+                weighting.sub_block_sum = 0.0;
+                weighting.sub_block_pos = 0;
+            }
+        }
+
+        if weighting.pending.is_empty() {
+            return;
+        }
+        if let Some(mut readings) = self.readings.try_lock() {
+            for energy in weighting.pending.drain(..) {
+                readings.push_sub_block(energy);
+            }
+        }
+    }
+
+    pub fn report(&self) -> LoudnessReport {
+        self.readings.lock().report()
+    }
+
+    pub fn snapshot(&self) -> LoudnessSnapshot {
+        let readings = self.readings.lock();
+        LoudnessSnapshot {
+            momentary: readings.window_energy(MOMENTARY_SUB_BLOCKS).map_or(f64::NEG_INFINITY, energy_to_lufs),
+            short_term: readings.window_energy(SHORT_TERM_SUB_BLOCKS).map_or(f64::NEG_INFINITY, energy_to_lufs),
+            report: readings.report(),
+            history: readings.history.iter().copied().collect(),
+        }
+    }
+}
+
+/// Readings of the input and output loudness meters and their history.
+pub fn show_loudness(ui: &mut egui::Ui, monitoring: &Monitoring) {
+    let input = monitoring.input_loudness.snapshot();
+    let output = monitoring.output_loudness.snapshot();
+
+    egui::Grid::new("loudness_grid").striped(true).show(ui, |ui| {
+        for heading in ["", "Momentary", "Short-term", "Integrated", "LRA", "Max M"] {
+            ui.label(heading);
+        }
+        ui.end_row();
+        for (label, meter) in [("In", &input), ("Out", &output)] {
+            ui.label(label);
+            ui.label(format!("{} LUFS", format_lufs(meter.momentary)));
+            ui.label(format!("{} LUFS", format_lufs(meter.short_term)));
+            ui.label(format!("{} LUFS", format_lufs(meter.report.integrated)));
+            ui.label(format!("{:.1} LU", meter.report.range));
+            ui.label(format!("{} LUFS", format_lufs(meter.report.max_momentary)));
+            ui.end_row();
+        }
+    });
+    let shift = output.report.integrated - input.report.integrated;
+    if shift.is_finite() {
+        ui.label(format!("Integrated shift: {:+.1} LU", shift));
+    }
+
+    // Short-term loudness over the last minute, input behind output
+    let (_, painter, rect) = plot::allocate(ui, 120.0);
+    plot::draw_value_grid(&painter, rect, GRAPH_RANGE.0, GRAPH_RANGE.1, 10.0, " LUFS");
+    for (meter, color) in [(&input, plot::INPUT_COLOR), (&output, plot::OUTPUT_COLOR)] {
+        let offset = HISTORY_LEN - meter.history.len();
+        let points: Vec<Pos2> = meter
+            .history
+            .iter()
+            .enumerate()
+            .filter(|(_, short_term)| short_term.is_finite())
+            .map(|(i, short_term)| {
+                let x = rect.left() + rect.width() * (offset + i) as f32 / HISTORY_LEN as f32;
+                Pos2::new(x, plot::value_to_y(*short_term, GRAPH_RANGE.0, GRAPH_RANGE.1, rect))
+            })
+            .collect();
+        if points.len() > 1 {
+            painter.add(egui::Shape::line(points, Stroke::new(1.0, color)));
+        }
+    }
+
+    if ui.button("Reset").clicked() {
+        monitoring.input_loudness.reset();
+        monitoring.output_loudness.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+
+    /// Feeds `secs` of a stereo 1 kHz sine at `dbfs` on both channels.
+    fn feed_sine(meter: &LoudnessMeter, dbfs: f64, secs: f64, phase: &mut usize) {
+        let amplitude = 10f64.powf(dbfs / 20.0);
+        let block = RATE as usize / 10;
+        for _ in 0..(secs * 10.0).round() as usize {
+            let samples: Vec<f32> = (*phase..*phase + block)
+                .map(|n| (amplitude * (2.0 * PI * 1000.0 * (n % RATE as usize) as f64 / RATE as f64).sin()) as f32)
+                .collect();
+            *phase += block;
+            meter.process(&[samples.clone(), samples], block);
+        }
+    }
+
+    fn measure(segments: &[(f64, f64)]) -> LoudnessMeter {
+        let meter = LoudnessMeter::new();
+        meter.prepare(RATE, 2);
+        let mut phase = 0;
+        for &(dbfs, secs) in segments {
+            feed_sine(&meter, dbfs, secs, &mut phase);
+        }
+        meter
+    }
+
+    fn assert_near(actual: f64, expected: f64, tolerance: f64, what: &str) {
+        assert!((actual - expected).abs() <= tolerance, "{} {:.2}, expected {}", what, actual, expected);
+    }
+
+    #[test]
+    fn tech_3341_constant_sines() {
+        // Cases 1 and 2: M, S and I all read the sine level
+        for level in [-23.0, -33.0] {
+            let meter = measure(&[(level, 20.0)]);
+            let snapshot = meter.snapshot();
+            assert_near(snapshot.momentary, level, 0.1, "momentary");
+            assert_near(snapshot.short_term, level, 0.1, "short-term");
+            assert_near(snapshot.report.integrated, level, 0.1, "integrated");
+        }
+    }
+
+    #[test]
+    fn tech_3341_gating() {
+        // Case 3: the relative gate drops the quiet parts
+        let meter = measure(&[(-36.0, 10.0), (-23.0, 60.0), (-36.0, 10.0)]);
+        assert_near(meter.report().integrated, -23.0, 0.1, "integrated");
+        // Case 4: and the absolute gate the silent-ish ones
+        let meter = measure(&[(-72.0, 10.0), (-36.0, 10.0), (-23.0, 60.0), (-36.0, 10.0), (-72.0, 10.0)]);
+        assert_near(meter.report().integrated, -23.0, 0.1, "integrated");
+    }
+
+    #[test]
+    fn tech_3342_loudness_range() {
+        for (quiet, loud, range) in [(-30.0, -20.0, 10.0), (-20.0, -15.0, 5.0), (-40.0, -20.0, 20.0)] {
+            let meter = measure(&[(quiet, 20.0), (loud, 20.0)]);
+            assert_near(meter.report().range, range, 1.0, "LRA");
+        }
+    }
+
+    #[test]
+    fn held_readings_delay_sub_blocks_without_losing_them() {
+        let meter = LoudnessMeter::new();
+        meter.prepare(RATE, 2);
+        let mut phase = 0;
+        feed_sine(&meter, -23.0, 1.0, &mut phase);
+        let held = meter.readings.lock();
+        feed_sine(&meter, -23.0, 2.0, &mut phase);
+        drop(held);
+        feed_sine(&meter, -23.0, 0.1, &mut phase);
+        let readings = meter.readings.lock();
+        assert_eq!(readings.history.len(), 31);
+        assert_near(readings.integrated(), -23.0, 0.1, "integrated");
+    }
+}
@@ -0,0 +1,344 @@
+// src/analysis/meter.rs
+
+use eframe::egui::{self, Color32, Pos2, Rect, Stroke};
+use parking_lot::Mutex;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::spectrum::amplitude_to_db;
+
+/// Window of the RMS average.
+const RMS_SECS: f32 = 0.3;
+
+/// How long the peak hold marker stays before it falls back.
+const HOLD_SECS: f32 = 2.0;
+
+/// Fall rate of the peak bar once the signal drops.
+const PEAK_RELEASE_DB_PER_SEC: f32 = 20.0;
+
+/// True-peak is estimated at this many times the sample rate.
+const OVERSAMPLING: usize = 4;
+
+/// Taps per phase of the interpolation filter.
+const TAPS: usize = 8;
+
+/// Displayed range of the bars in dBFS.
+const METER_RANGE: (f32, f32) = (-60.0, 6.0);
+
+/// Levels of one channel, as read by the GUI.
+#[derive(Clone, Copy, Default)]
+pub struct ChannelLevels {
+    /// Sample peak with a fast attack and slow release, linear.
+    pub peak: f32,
+    /// Highest sample peak over the last `HOLD_SECS`, linear.
+    pub hold: f32,
+    /// Windowed RMS, linear.
+    pub rms: f32,
+    /// Highest inter-sample peak since the last reset, linear.
+    pub true_peak: f32,
+    /// Samples outside -1.0..=1.0 since the last reset, which the output
+    /// clamps.
+    pub clips: u64,
+}
+
+/// Envelope and interpolator state of one channel.
+struct ChannelState {
+    peak: f32,
+    hold: f32,
+    hold_age: f32,
+    power: f32,
+    /// Most recent input samples for the true-peak interpolator, oldest first.
+    history: [f32; TAPS],
+}
+
+/// `ChannelLevels` as atomics, the levels as `f32` bits, so the audio thread
+/// counts every block whatever the GUI is doing.
+#[derive(Default)]
+struct SharedLevels {
+    peak: AtomicU32,
+    hold: AtomicU32,
+    rms: AtomicU32,
+    true_peak: AtomicU32,
+    clips: AtomicU64,
+}
+
+fn load_f32(value: &AtomicU32) -> f32 {
+    f32::from_bits(value.load(Ordering::Relaxed))
+}
+
+fn store_f32(value: &AtomicU32, v: f32) {
+    value.store(v.to_bits(), Ordering::Relaxed);
+}
+
+/// Everything `record` works on. Only the audio thread processes, so its
+/// lock is never contended during playback.
+struct Processing {
+    sample_rate: u32,
+    channels: Vec<ChannelState>,
+    /// Windowed-sinc phases at 1/4, 2/4 and 3/4 of a sample.
+    phases: [[f32; TAPS]; OVERSAMPLING - 1],
+    levels: Arc<[SharedLevels]>,
+}
+
+/// Peak, RMS, true-peak and clip counts per channel, fed by `BlockProcessor`
+/// after every block and read by the GUI. The audio thread keeps the filter
+/// state to itself and publishes through atomics, so a GUI read never costs
+/// it a block.
+pub struct LevelMeter {
+    processing: Mutex<Processing>,
+    /// The GUI's handle on the levels `processing` writes.
+    levels: Mutex<Arc<[SharedLevels]>>,
+}
+
+fn interpolation_phases() -> [[f32; TAPS]; OVERSAMPLING - 1] {
+    let mut phases = [[0.0; TAPS]; OVERSAMPLING - 1];
+    for (p, phase) in phases.iter_mut().enumerate() {
+        let fraction = (p + 1) as f32 / OVERSAMPLING as f32;
+        for (k, tap) in phase.iter_mut().enumerate() {
+            // Interpolates between the two middle samples of the history
+            let x = k as f32 - (TAPS / 2 - 1) as f32 - fraction;
+            let sinc = if x.abs() < 1e-6 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            let window = 0.5 + 0.5 * (PI * x / (TAPS / 2) as f32).cos();
+            *tap = sinc * window;
+        }
+    }
+    phases
+}
+
+impl LevelMeter {
+    pub fn new() -> Self {
+        let levels: Arc<[SharedLevels]> = Arc::new([]);
+        Self {
+            processing: Mutex::new(Processing {
+                sample_rate: 0,
+                channels: Vec::new(),
+                phases: interpolation_phases(),
+                levels: Arc::clone(&levels),
+            }),
+            levels: Mutex::new(levels),
+        }
+    }
+
+    /// Clears the levels and sizes the meter for a new stream. Not real-time safe.
+    pub fn prepare(&self, sample_rate: u32, channels: usize) {
+        let levels: Arc<[SharedLevels]> = (0..channels).map(|_| SharedLevels::default()).collect();
+        let mut processing = self.processing.lock();
+        processing.sample_rate = sample_rate;
+        processing.channels = (0..channels)
+            .map(|_| ChannelState {
+                peak: 0.0,
+                hold: 0.0,
+                hold_age: 0.0,
+                power: 0.0,
+                history: [0.0; TAPS],
+            })
+            .collect();
+        processing.levels = Arc::clone(&levels);
+        *self.levels.lock() = levels;
+    }
+
+    /// Called from the audio thread with the planar channels of one block.
+    pub fn record(&self, channels: &[Vec<f32>], frames: usize) {
+        let mut guard = self.processing.lock();
+        let processing = &mut *guard;
+        if frames == 0 || processing.sample_rate == 0 {
+            return;
+        }
+        let block_secs = frames as f32 / processing.sample_rate as f32;
+        let rms_alpha = 1.0 - (-1.0 / (RMS_SECS * processing.sample_rate as f32)).exp();
+        let release = 10f32.powf(-PEAK_RELEASE_DB_PER_SEC * block_secs / 20.0);
+
+        for ((channel, shared), samples) in processing.channels.iter_mut().zip(processing.levels.iter()).zip(channels) {
+            let mut block_peak = 0.0f32;
+            let mut true_peak = 0.0f32;
+            let mut clips = 0;
+            for &sample in &samples[..frames] {
+                let magnitude = sample.abs();
+                block_peak = block_peak.max(magnitude);
+                if magnitude > 1.0 {
+                    clips += 1;
+                }
+                channel.power += rms_alpha * (sample * sample - channel.power);
+
+                channel.history.rotate_left(1);
+                channel.history[TAPS - 1] = sample;
+                for phase in &processing.phases {
+                    let interpolated: f32 = phase.iter().zip(&channel.history).map(|(c, s)| c * s).sum();
+                    true_peak = true_peak.max(interpolated.abs());
+                }
+            }
+            true_peak = true_peak.max(block_peak);
+
+            channel.peak = block_peak.max(channel.peak * release);
+            channel.hold_age += block_secs;
+            if block_peak >= channel.hold || channel.hold_age > HOLD_SECS {
+                channel.hold = block_peak.max(channel.peak);
+                channel.hold_age = 0.0;
+            }
+            store_f32(&shared.peak, channel.peak);
+            store_f32(&shared.hold, channel.hold);
+            store_f32(&shared.rms, channel.power.sqrt());
+            // Bits of non-negative floats order like the floats
+            shared.true_peak.fetch_max(true_peak.to_bits(), Ordering::Relaxed);
+            shared.clips.fetch_add(clips, Ordering::Relaxed);
+        }
+    }
+
+    pub fn levels(&self) -> Vec<ChannelLevels> {
+        self.levels
+            .lock()
+            .iter()
+            .map(|shared| ChannelLevels {
+                peak: load_f32(&shared.peak),
+                hold: load_f32(&shared.hold),
+                rms: load_f32(&shared.rms),
+                true_peak: load_f32(&shared.true_peak),
+                clips: shared.clips.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Clears the true-peak maximum and the clip counts.
+    pub fn reset_clips(&self) {
+        for shared in self.levels.lock().iter() {
+            store_f32(&shared.true_peak, 0.0);
+            shared.clips.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+fn level_to_x(db: f32, rect: Rect) -> f32 {
+    let t = ((db - METER_RANGE.0) / (METER_RANGE.1 - METER_RANGE.0)).clamp(0.0, 1.0);
+    rect.left() + t * rect.width()
+}
+
+/// One horizontal bar: RMS filled, peak as a lighter bar over it, hold as a
+/// tick, red past 0 dBFS.
+fn show_bar(ui: &mut egui::Ui, levels: &ChannelLevels) {
+    let (response, painter) = ui.allocate_painter(egui::vec2(120.0, 8.0), egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 1.0, ui.visuals().extreme_bg_color);
+
+    let zero = level_to_x(0.0, rect);
+    let span = |db: f32| Rect::from_min_max(rect.left_top(), Pos2::new(level_to_x(db, rect), rect.bottom()));
+    painter.rect_filled(span(amplitude_to_db(levels.peak)), 0.0, Color32::from_rgb(40, 110, 150));
+    painter.rect_filled(span(amplitude_to_db(levels.rms)), 0.0, Color32::from_rgb(90, 200, 255));
+    if levels.peak > 1.0 {
+        let over = Rect::from_min_max(Pos2::new(zero, rect.top()), Pos2::new(level_to_x(amplitude_to_db(levels.peak), rect), rect.bottom()));
+        painter.rect_filled(over, 0.0, Color32::from_rgb(230, 80, 80));
+    }
+    painter.line_segment([Pos2::new(zero, rect.top()), Pos2::new(zero, rect.bottom())], Stroke::new(1.0, Color32::from_gray(120)));
+
+    let hold = level_to_x(amplitude_to_db(levels.hold), rect);
+    painter.line_segment(
+        [Pos2::new(hold, rect.top()), Pos2::new(hold, rect.bottom())],
+        Stroke::new(1.5, Color32::from_rgb(255, 210, 90)),
+    );
+}
+
+/// A row per channel with the bar and the numbers. Clicking the clip count
+/// resets it along with the true-peak maximum.
+pub fn show_meters(ui: &mut egui::Ui, label: &str, meter: &LevelMeter) {
+    let levels = meter.levels();
+    let format_db = |amplitude: f32| {
+        let db = amplitude_to_db(amplitude);
+        if db <= METER_RANGE.0 {
+            "-inf".to_string()
+        } else {
+            format!("{:.1}", db)
+        }
+    };
+    for (channel, levels) in levels.iter().enumerate() {
+        ui.horizontal(|ui| {
+            ui.label(format!("{} {}", label, channel + 1));
+            show_bar(ui, levels);
+            ui.label(format!(
+                "pk {} rms {} tp {} dB",
+                format_db(levels.hold),
+                format_db(levels.rms),
+                format_db(levels.true_peak)
+            ));
+            let clip_text = format!("clips {}", levels.clips);
+            let clip_label = if levels.clips > 0 {
+                egui::RichText::new(clip_text).color(Color32::from_rgb(230, 80, 80))
+            } else {
+                egui::RichText::new(clip_text)
+            };
+            if ui
+                .add(egui::Label::new(clip_label).sense(egui::Sense::click()))
+                .on_hover_text("Click to reset clips and true-peak")
+                .clicked()
+            {
+                meter.reset_clips();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+    const BLOCK: usize = 1024;
+
+    fn record_blocks(meter: &LevelMeter, blocks: usize, sample: impl Fn(usize) -> f32) {
+        for block in 0..blocks {
+            let samples = (0..BLOCK).map(|i| sample(block * BLOCK + i)).collect();
+            meter.record(&[samples], BLOCK);
+        }
+    }
+
+    #[test]
+    fn clips_are_counted_while_the_gui_reads() {
+        let meter = LevelMeter::new();
+        meter.prepare(RATE, 1);
+        let reading = meter.levels.lock();
+        record_blocks(&meter, 2, |i| match i % 256 {
+            0 => 1.5,
+            1 => -1.01,
+            _ => 0.5,
+        });
+        drop(reading);
+        // 2 blocks of 4 runs of 256 samples, two clips per run
+        assert_eq!(meter.levels()[0].clips, 16);
+
+        meter.reset_clips();
+        assert_eq!(meter.levels()[0].clips, 0);
+        assert_eq!(meter.levels()[0].true_peak, 0.0);
+    }
+
+    #[test]
+    fn peak_holds_then_falls_at_the_release_rate() {
+        let meter = LevelMeter::new();
+        meter.prepare(RATE, 1);
+        record_blocks(&meter, 1, |i| if i == 0 { 1.0 } else { 0.0 });
+
+        // About one second of silence: down 20 dB, hold still up
+        let second = RATE as usize / BLOCK;
+        record_blocks(&meter, second, |_| 0.0);
+        let levels = meter.levels()[0];
+        let expected_db = -PEAK_RELEASE_DB_PER_SEC * (second * BLOCK) as f32 / RATE as f32;
+        assert!((amplitude_to_db(levels.peak) - expected_db).abs() < 0.1, "peak {} dB", amplitude_to_db(levels.peak));
+        assert_eq!(levels.hold, 1.0);
+
+        // Past the hold time the marker drops to the decayed peak
+        record_blocks(&meter, second + 2, |_| 0.0);
+        let levels = meter.levels()[0];
+        assert!(levels.hold < 0.1, "hold {}", levels.hold);
+        assert!(levels.hold >= levels.peak);
+    }
+
+    #[test]
+    fn quarter_rate_sine_reads_3_db_over_its_sample_peak() {
+        let meter = LevelMeter::new();
+        meter.prepare(RATE, 1);
+        // Sampled at 45, 135, 225 and 315 degrees, so every sample is at 0.707
+        record_blocks(&meter, 4, |i| (PI / 2.0 * i as f32 + PI / 4.0).sin());
+        let levels = meter.levels()[0];
+        let over_db = amplitude_to_db(levels.true_peak) - amplitude_to_db(levels.peak);
+        assert!((over_db - 3.01).abs() < 0.5, "true-peak {:.2} dB over the sample peak", over_db);
+        assert_eq!(levels.clips, 0);
+    }
+}
@@ -0,0 +1,127 @@
+// src/analysis/mod.rs
+
+pub mod cpu;
+pub mod loudness;
+pub mod meter;
+pub mod null_test;
+pub mod plot;
+pub mod response;
+pub mod scope;
+pub mod spectrogram;
+pub mod spectrum;
+pub mod stereo;
+pub mod tap;
+pub mod thd;
+
+use eframe::egui;
+use std::sync::atomic::Ordering;
+
+use crate::chain::{ChainProcessor, ChainSlot, ChainSnapshot};
+use cpu::CpuMeter;
+use loudness::LoudnessMeter;
+use meter::LevelMeter;
+use tap::SampleTap;
+
+/// Everything the audio thread publishes for the GUI to display. One instance
+/// lives for the whole session and is handed to every `BlockProcessor`.
+pub struct Monitoring {
+    /// Samples entering the module, before processing.
+    pub input_tap: SampleTap,
+    /// Samples leaving the module, after processing.
+    pub output_tap: SampleTap,
+    /// Real-time load of the whole processing call, recorded by
+    /// `BlockProcessor` around every block; bypassed blocks count as idle.
+    pub cpu: CpuMeter,
+    /// Load of the chain under test alone, recorded by `AbProcessor`
+    /// without the A/B reference and crossfade.
+    pub chain_cpu: CpuMeter,
+    /// Levels entering the chain.
+    pub input_meter: LevelMeter,
+    /// Levels leaving the chain.
+    pub output_meter: LevelMeter,
+    /// R128 loudness before the chain. Never skips a block, since a missed
+    /// one would bias the integrated value, and never waits for the GUI.
+    pub input_loudness: LoudnessMeter,
+    /// R128 loudness after the chain.
+    pub output_loudness: LoudnessMeter,
+}
+
+impl Monitoring {
+    pub fn new() -> Self {
+        Self {
+            input_tap: SampleTap::new(),
+            output_tap: SampleTap::new(),
+            cpu: CpuMeter::new(),
+            chain_cpu: CpuMeter::new(),
+            input_meter: LevelMeter::new(),
+            output_meter: LevelMeter::new(),
+            input_loudness: LoudnessMeter::new(),
+            output_loudness: LoudnessMeter::new(),
+        }
+    }
+
+    /// Copies one block into both taps or neither, so the two histories
+    /// always cover the same frames and can be compared sample for sample.
+    /// `input` is interleaved, `output` planar.
+    pub fn push_taps(&self, input: &[f32], output: &[Vec<f32>], frames: usize) {
+        let (Some(mut input_tap), Some(mut output_tap)) = (self.input_tap.try_writer(), self.output_tap.try_writer()) else {
+            return;
+        };
+        input_tap.push_interleaved(input, frames);
+        output_tap.push(output, frames);
+    }
+
+    /// Sizes every tap for a new stream format and clears the statistics.
+    /// Not real-time safe.
+    pub fn prepare(&self, sample_rate: u32, channels: usize) {
+        self.cpu.reset();
+        self.chain_cpu.reset();
+        self.input_tap.prepare(sample_rate, channels);
+        self.output_tap.prepare(sample_rate, channels);
+        self.input_meter.prepare(sample_rate, channels);
+        self.output_meter.prepare(sample_rate, channels);
+        self.input_loudness.prepare(sample_rate, channels);
+        self.output_loudness.prepare(sample_rate, channels);
+    }
+}
+
+/// Combo box choosing what an offline measurement runs on: the whole chain
+/// (`None`) or a single slot.
+pub fn show_target_selector(ui: &mut egui::Ui, id: &str, target: &mut Option<usize>, slots: &[ChainSlot]) {
+    if target.is_some_and(|t| t >= slots.len()) {
+        *target = None;
+    }
+    let label = |target: Option<usize>| match target {
+        Some(index) => slots[index].title.clone(),
+        None => "Whole chain".to_string(),
+    };
+    egui::ComboBox::from_id_source(id)
+        .selected_text(label(*target))
+        .show_ui(ui, |cb| {
+            cb.selectable_value(target, None, label(None));
+            for index in 0..slots.len() {
+                cb.selectable_value(target, Some(index), label(Some(index)));
+            }
+        });
+}
+
+/// A processor for `target` frozen at the current parameter values.
+pub fn target_processor(slots: &[ChainSlot], target: Option<usize>) -> ChainProcessor {
+    let slots = match target {
+        Some(index) => &slots[index..=index],
+        None => slots,
+    };
+    ChainProcessor::from_snapshot(slots, &ChainSnapshot::capture(slots))
+}
+
+/// Everything about the chain an offline measurement depends on, flattened
+/// so it can be compared cheaply on every frame to see if it needs redoing.
+pub fn chain_signature(slots: &[ChainSlot]) -> Vec<u64> {
+    let mut signature = Vec::new();
+    for slot in slots {
+        signature.push(slot.id);
+        signature.push(slot.bypass.load(Ordering::SeqCst) as u64);
+        signature.extend(slot.params.iter().map(|p| p.value.load().as_f32().to_bits() as u64));
+    }
+    signature
+}
@@ -0,0 +1,708 @@
+// src/analysis/null_test.rs
+
+use eframe::egui::{self, Align2, Color32, FontId, Pos2, Stroke};
+use rodio::buffer::SamplesBuffer;
+use rodio::{OutputStream, Sink, Source};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+
+use super::{chain_signature, plot, target_processor};
+use crate::chain::{ChainProcessor, ChainSlot, ChainSnapshot};
+use crate::dsp::{decode_file, InputSource};
+use crate::dsp_module::AudioProcessor;
+use crate::error::DspError;
+use crate::generators::Generator;
+use crate::render::process_interleaved;
+
+/// Length of a test signal taken from the generator.
+const GENERATOR_SECS: f32 = 10.0;
+
+/// Furthest the correlation search looks either side of the reported latency.
+const SEARCH_RANGE: usize = 2048;
+
+/// Frames the correlation search compares at each lag.
+const SEARCH_WINDOW: usize = 16384;
+
+/// Points in the residual level graph.
+const LEVEL_BUCKETS: usize = 1024;
+
+/// Range of the residual level graph in dBFS.
+const LEVEL_RANGE: (f32, f32) = (-160.0, 0.0);
+
+/// One side of a null test.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum NullSide {
+    /// The input untouched.
+    Dry,
+    /// The whole chain at its current settings.
+    Chain,
+    /// The whole chain at the settings snapshotted as A in the A/B panel.
+    Reference,
+    /// A single slot on its own at its current settings.
+    Slot(usize),
+}
+
+impl NullSide {
+    fn label(&self, slots: &[ChainSlot]) -> String {
+        match self {
+            NullSide::Dry => "Dry".to_string(),
+            NullSide::Chain => "Whole chain".to_string(),
+            NullSide::Reference => "A/B snapshot".to_string(),
+            NullSide::Slot(index) => slots.get(*index).map_or_else(|| "Missing slot".to_string(), |s| s.title.clone()),
+        }
+    }
+
+    /// A processor frozen at this side's settings, or `None` when there's no
+    /// snapshot to use.
+    fn processor(&self, slots: &[ChainSlot], reference: Option<&ChainSnapshot>) -> Option<ChainProcessor> {
+        match self {
+            NullSide::Dry => Some(ChainProcessor::new()),
+            NullSide::Chain => Some(target_processor(slots, None)),
+            NullSide::Reference => reference.map(|snapshot| ChainProcessor::from_snapshot(slots, snapshot)),
+            NullSide::Slot(index) => Some(target_processor(slots, Some(*index))),
+        }
+    }
+}
+
+/// Audio held in memory so both sides of a test get exactly the same samples,
+/// including from the noise generators.
+pub struct NullTestInput {
+    pub samples: Vec<f32>,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+impl NullTestInput {
+    /// Decodes a file, or takes `GENERATOR_SECS` of a test signal.
+    pub fn load(input: &InputSource) -> Result<Self, DspError> {
+        match input {
+            InputSource::File(path) => Self::from_file(path),
+            InputSource::Generator(waveform, controls) => {
+                let generator = Generator::new(*waveform, controls.clone());
+                let (channels, sample_rate) = (generator.channels(), generator.sample_rate());
+                let len = (GENERATOR_SECS * sample_rate as f32) as usize * channels as usize;
+                Ok(Self {
+                    samples: generator.take(len).collect(),
+                    channels,
+                    sample_rate,
+                })
+            }
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, DspError> {
+        let source = decode_file(path)?;
+        let (channels, sample_rate) = (source.channels(), source.sample_rate());
+        Ok(Self {
+            samples: source.convert_samples::<f32>().collect(),
+            channels,
+            sample_rate,
+        })
+    }
+}
+
+/// How a residual compares with the tolerance.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    BitExact,
+    WithinTolerance,
+    OutsideTolerance,
+}
+
+impl Verdict {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Verdict::BitExact => "Bit-exact",
+            Verdict::WithinTolerance => "Within tolerance",
+            Verdict::OutsideTolerance => "Outside tolerance",
+        }
+    }
+}
+
+/// B minus A after lining the two up, with its level.
+pub struct NullTestResult {
+    pub channels: u16,
+    pub sample_rate: u32,
+    /// Interleaved B minus A over the frames both cover, on A's timeline.
+    pub residual: Vec<f32>,
+    pub latency_a: usize,
+    pub latency_b: usize,
+    /// Frames B was moved earlier by to line up with A: the latency
+    /// difference plus whatever the correlation search found.
+    pub offset: isize,
+    /// Part of `offset` found by the correlation search rather than reported.
+    pub searched_offset: isize,
+    pub peak_db: f32,
+    pub rms_db: f32,
+    /// RMS of A over the same frames, for the depth of the null.
+    pub reference_rms_db: f32,
+    /// Samples where A and B aren't identical.
+    pub differing: usize,
+    /// Residual and A peak levels over time, in dBFS.
+    pub residual_levels: Vec<f32>,
+    pub reference_levels: Vec<f32>,
+}
+
+fn level_db(amplitude: f64) -> f32 {
+    if amplitude > 0.0 {
+        (20.0 * amplitude.log10()) as f32
+    } else {
+        f32::NEG_INFINITY
+    }
+}
+
+/// dB with one decimal, or "-inf" for silence.
+pub fn format_db(db: f32) -> String {
+    if db.is_finite() {
+        format!("{:.1}", db)
+    } else {
+        "-inf".to_string()
+    }
+}
+
+/// Mono mix of `frames` frames of interleaved `samples` from `start`.
+fn mono(samples: &[f32], channels: usize, start: usize, frames: usize) -> Vec<f32> {
+    samples[start * channels..(start + frames) * channels]
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
+
+/// Extra lag of `b` against `a`, within `SEARCH_RANGE` of `offset`, at which
+/// the two correlate best. Searched over the loudest stretch of `a`.
+fn find_offset(a: &[f32], b: &[f32], channels: usize, offset: isize) -> isize {
+    let (frames_a, frames_b) = ((a.len() / channels) as isize, (b.len() / channels) as isize);
+    let range = SEARCH_RANGE as isize;
+    // A stretch of A whose lagged copy in B stays inside B at every lag
+    let first = (range - offset).max(0);
+    let last = (frames_b - range - offset).min(frames_a);
+    if last <= first {
+        return 0;
+    }
+    let (first, last) = (first as usize, last as usize);
+    let window = SEARCH_WINDOW.min(last - first);
+    let start = (first..=last - window)
+        .step_by(window / 2 + 1)
+        .map(|start| (start, mono(a, channels, start, window).iter().map(|v| v * v).sum::<f32>()))
+        .max_by(|x, y| x.1.total_cmp(&y.1))
+        .map_or(first, |(start, _)| start);
+
+    let reference = mono(a, channels, start, window);
+    let lagged_start = (start as isize + offset - range) as usize;
+    let lagged = mono(b, channels, lagged_start, window + 2 * SEARCH_RANGE);
+    let correlation = |lag: isize| -> f64 {
+        let lagged = &lagged[(lag + range) as usize..];
+        reference.iter().zip(lagged).map(|(x, y)| (*x as f64) * (*y as f64)).sum()
+    };
+    // Only moves off the reported latency for a strictly better match, so
+    // silence or a flat correlation leaves it alone
+    (-range..=range).fold((0, correlation(0)), |best, lag| {
+        let c = correlation(lag);
+        if c > best.1 { (lag, c) } else { best }
+    }).0
+}
+
+/// Peak of each of `buckets` equal slices of interleaved `samples`, in dBFS.
+fn bucket_levels(samples: &[f32], channels: usize, buckets: usize) -> Vec<f32> {
+    let frames = samples.len() / channels;
+    let buckets = buckets.min(frames);
+    (0..buckets)
+        .map(|bucket| {
+            let from = bucket * frames / buckets * channels;
+            let to = (bucket + 1) * frames / buckets * channels;
+            let peak = samples[from..to].iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+            level_db(peak as f64)
+        })
+        .collect()
+}
+
+impl NullTestResult {
+    /// Processes `input` through `a` and `b` in blocks of `block_size`, lines
+    /// the outputs up using the latency each reports and, with `search`, a
+    /// cross-correlation for any delay that isn't reported.
+    pub fn run(
+        input: &NullTestInput,
+        a: &mut dyn AudioProcessor,
+        b: &mut dyn AudioProcessor,
+        block_size: usize,
+        search: bool,
+    ) -> Self {
+        let channels = input.channels.max(1) as usize;
+        let out_a = process_interleaved(a, &input.samples, channels, input.sample_rate, block_size);
+        let out_b = process_interleaved(b, &input.samples, channels, input.sample_rate, block_size);
+        let (latency_a, latency_b) = (a.latency(), b.latency());
+
+        let reported = latency_b as isize - latency_a as isize;
+        let searched_offset = if search { find_offset(&out_a, &out_b, channels, reported) } else { 0 };
+        let offset = reported + searched_offset;
+
+        // Frames of A whose partner in B exists
+        let (frames_a, frames_b) = ((out_a.len() / channels) as isize, (out_b.len() / channels) as isize);
+        let first = (-offset).max(0);
+        let last = frames_a.min(frames_b - offset).max(first);
+        let a = &out_a[first as usize * channels..last as usize * channels];
+        let b = &out_b[(first + offset) as usize * channels..(last + offset) as usize * channels];
+
+        let mut residual = Vec::with_capacity(a.len());
+        let (mut peak, mut power, mut reference_power, mut differing) = (0.0f64, 0.0f64, 0.0f64, 0);
+        for (x, y) in a.iter().zip(b) {
+            let difference = y - x;
+            if y != x {
+                differing += 1;
+            }
+            residual.push(difference);
+            peak = peak.max(difference.abs() as f64);
+            power += (difference as f64).powi(2);
+            reference_power += (*x as f64).powi(2);
+        }
+        let count = a.len().max(1) as f64;
+
+        Self {
+            channels: channels as u16,
+            sample_rate: input.sample_rate,
+            residual_levels: bucket_levels(&residual, channels, LEVEL_BUCKETS),
+            reference_levels: bucket_levels(a, channels, LEVEL_BUCKETS),
+            residual,
+            latency_a,
+            latency_b,
+            offset,
+            searched_offset,
+            peak_db: level_db(peak),
+            rms_db: level_db((power / count).sqrt()),
+            reference_rms_db: level_db((reference_power / count).sqrt()),
+            differing,
+        }
+    }
+
+    /// Residual RMS relative to A's; the depth of the null.
+    pub fn depth_db(&self) -> f32 {
+        self.rms_db - self.reference_rms_db
+    }
+
+    /// Bit-exact, or whether the residual peak stays at or under `tolerance_db`.
+    pub fn verdict(&self, tolerance_db: f32) -> Verdict {
+        if self.differing == 0 {
+            Verdict::BitExact
+        } else if self.peak_db <= tolerance_db {
+            Verdict::WithinTolerance
+        } else {
+            Verdict::OutsideTolerance
+        }
+    }
+
+    /// Writes the residual as 32-bit float, so levels far below 16 or 24-bit
+    /// resolution survive.
+    pub fn write_wav(&self, path: &Path) -> Result<(), DspError> {
+        let write_error = |source| DspError::WriteWav {
+            path: path.to_path_buf(),
+            source,
+        };
+        let spec = hound::WavSpec {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).map_err(write_error)?;
+        for sample in &self.residual {
+            writer.write_sample(*sample).map_err(write_error)?;
+        }
+        writer.finalize().map_err(write_error)
+    }
+}
+
+impl fmt::Display for NullTestResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "residual peak {} dBFS, RMS {} dBFS ({} dB below A), {} of {} samples differ, offset {} frames",
+            format_db(self.peak_db),
+            format_db(self.rms_db),
+            format_db(-self.depth_db()),
+            self.differing,
+            self.residual.len(),
+            self.offset,
+        )
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NullTestSettings {
+    pub side_a: NullSide,
+    pub side_b: NullSide,
+    /// Also search for a delay the sides don't report.
+    pub search: bool,
+    /// Residual peak at or under this passes.
+    pub tolerance_db: f32,
+    /// Boost applied when listening, since residuals are usually very quiet.
+    pub listen_gain_db: f32,
+}
+
+impl Default for NullTestSettings {
+    fn default() -> Self {
+        Self {
+            side_a: NullSide::Dry,
+            side_b: NullSide::Chain,
+            search: false,
+            tolerance_db: -120.0,
+            listen_gain_db: 0.0,
+        }
+    }
+}
+
+/// Null test panel: picks the two sides, runs the test on a background
+/// thread, and shows, plays and saves the residual.
+pub struct NullTestPanel {
+    pub settings: NullTestSettings,
+    wav_path: String,
+    job: Option<JoinHandle<Result<NullTestResult, DspError>>>,
+    result: Option<NullTestResult>,
+    /// Chain the last result was taken with, to flag it once edited.
+    tested: Vec<u64>,
+    player: Option<(OutputStream, Sink)>,
+    status: String,
+}
+
+impl NullTestPanel {
+    pub fn new() -> Self {
+        Self {
+            settings: NullTestSettings::default(),
+            wav_path: "residual.wav".to_string(),
+            job: None,
+            result: None,
+            tested: Vec::new(),
+            player: None,
+            status: String::new(),
+        }
+    }
+
+    fn show_side_selector(ui: &mut egui::Ui, id: &str, side: &mut NullSide, slots: &[ChainSlot], has_reference: bool) {
+        if matches!(side, NullSide::Slot(index) if *index >= slots.len()) || (*side == NullSide::Reference && !has_reference) {
+            *side = NullSide::Chain;
+        }
+        egui::ComboBox::from_id_source(id)
+            .selected_text(side.label(slots))
+            .show_ui(ui, |cb| {
+                cb.selectable_value(side, NullSide::Dry, NullSide::Dry.label(slots));
+                cb.selectable_value(side, NullSide::Chain, NullSide::Chain.label(slots));
+                if has_reference {
+                    cb.selectable_value(side, NullSide::Reference, NullSide::Reference.label(slots));
+                }
+                for index in 0..slots.len() {
+                    cb.selectable_value(side, NullSide::Slot(index), NullSide::Slot(index).label(slots));
+                }
+            });
+    }
+
+    fn start(
+        &mut self,
+        slots: &[ChainSlot],
+        reference: Option<&ChainSnapshot>,
+        input: Option<&InputSource>,
+        block_size: usize,
+    ) {
+        let Some(input) = input.cloned() else {
+            self.status = "Play a file or test signal first.".to_string();
+            return;
+        };
+        let (Some(mut a), Some(mut b)) = (
+            self.settings.side_a.processor(slots, reference),
+            self.settings.side_b.processor(slots, reference),
+        ) else {
+            self.status = "No A/B snapshot to test against.".to_string();
+            return;
+        };
+        let search = self.settings.search;
+        self.stop_playback();
+        self.tested = chain_signature(slots);
+        self.status = "Running...".to_string();
+        self.job = Some(thread::spawn(move || {
+            let input = NullTestInput::load(&input)?;
+            Ok(NullTestResult::run(&input, &mut a, &mut b, block_size, search))
+        }));
+    }
+
+    fn poll(&mut self) {
+        if !self.job.as_ref().is_some_and(|job| job.is_finished()) {
+            return;
+        }
+        let Some(job) = self.job.take() else {
+            return;
+        };
+        match job.join() {
+            Ok(Ok(result)) => {
+                self.status.clear();
+                self.result = Some(result);
+            }
+            Ok(Err(e)) => self.status = format!("Null test failed: {}", e),
+            Err(_) => self.status = "Null test failed: a processor panicked".to_string(),
+        }
+    }
+
+    fn play(&mut self) -> Result<(), DspError> {
+        let Some(ref result) = self.result else {
+            return Ok(());
+        };
+        let gain = 10f32.powf(self.settings.listen_gain_db / 20.0);
+        let samples: Vec<f32> = result.residual.iter().map(|s| s * gain).collect();
+        let (stream, handle) = OutputStream::try_default()?;
+        let sink = Sink::try_new(&handle)?;
+        sink.append(SamplesBuffer::new(result.channels, result.sample_rate, samples));
+        self.player = Some((stream, sink));
+        Ok(())
+    }
+
+    fn stop_playback(&mut self) {
+        if let Some((_, sink)) = self.player.take() {
+            sink.stop();
+        }
+    }
+
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        slots: &[ChainSlot],
+        reference: Option<&ChainSnapshot>,
+        input: Option<&InputSource>,
+        block_size: usize,
+    ) {
+        self.poll();
+        let running = self.job.is_some();
+        if running {
+            ui.ctx().request_repaint();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("A");
+            Self::show_side_selector(ui, "null_test_a", &mut self.settings.side_a, slots, reference.is_some());
+            ui.label("B");
+            Self::show_side_selector(ui, "null_test_b", &mut self.settings.side_b, slots, reference.is_some());
+            ui.checkbox(&mut self.settings.search, "Find unreported delay")
+                .on_hover_text("Search ±2048 frames around the reported latency for the best match");
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut self.settings.tolerance_db)
+                    .clamp_range(-200.0..=0.0)
+                    .speed(1.0)
+                    .suffix(" dBFS"),
+            );
+            ui.label("tolerance");
+            if ui.add_enabled(!running, egui::Button::new("Run null test")).clicked() {
+                self.start(slots, reference, input, block_size);
+            }
+            if !self.status.is_empty() {
+                ui.label(&self.status);
+            }
+        });
+
+        let Some(ref result) = self.result else {
+            return;
+        };
+        if chain_signature(slots) != self.tested {
+            ui.label("The chain has changed since this test.");
+        }
+
+        let verdict = result.verdict(self.settings.tolerance_db);
+        let color = match verdict {
+            Verdict::BitExact | Verdict::WithinTolerance => Color32::from_rgb(90, 200, 120),
+            Verdict::OutsideTolerance => Color32::from_rgb(230, 80, 80),
+        };
+        ui.label(egui::RichText::new(verdict.label()).color(color).strong());
+        egui::Grid::new("null_test_result").num_columns(2).show(ui, |ui| {
+            ui.label("Residual peak");
+            ui.label(format!("{} dBFS", format_db(result.peak_db)));
+            ui.end_row();
+            ui.label("Residual RMS");
+            ui.label(format!("{} dBFS ({} dB below A)", format_db(result.rms_db), format_db(-result.depth_db())));
+            ui.end_row();
+            ui.label("Differing samples");
+            ui.label(format!("{} of {}", result.differing, result.residual.len()));
+            ui.end_row();
+            ui.label("Latency A / B");
+            ui.label(format!("{} / {} frames", result.latency_a, result.latency_b));
+            ui.end_row();
+            ui.label("Offset applied");
+            ui.label(if result.searched_offset != 0 {
+                format!("{} frames ({:+} found by search)", result.offset, result.searched_offset)
+            } else {
+                format!("{} frames", result.offset)
+            });
+            ui.end_row();
+        });
+
+        let (response, painter, rect) = plot::allocate(ui, 160.0);
+        plot::draw_value_grid(&painter, rect, LEVEL_RANGE.0, LEVEL_RANGE.1, 20.0, " dB");
+        let line = |levels: &[f32]| -> Vec<Pos2> {
+            let step = rect.width() / (levels.len() - 1).max(1) as f32;
+            levels
+                .iter()
+                .enumerate()
+                .map(|(i, db)| {
+                    let db = db.clamp(LEVEL_RANGE.0, LEVEL_RANGE.1);
+                    Pos2::new(rect.left() + i as f32 * step, plot::value_to_y(db, LEVEL_RANGE.0, LEVEL_RANGE.1, rect))
+                })
+                .collect()
+        };
+        painter.add(egui::Shape::line(line(&result.reference_levels), Stroke::new(1.0, plot::INPUT_COLOR)));
+        painter.add(egui::Shape::line(line(&result.residual_levels), Stroke::new(1.0, plot::OUTPUT_COLOR)));
+        let tolerance_y = plot::value_to_y(self.settings.tolerance_db, LEVEL_RANGE.0, LEVEL_RANGE.1, rect);
+        painter.line_segment(
+            [Pos2::new(rect.left(), tolerance_y), Pos2::new(rect.right(), tolerance_y)],
+            Stroke::new(0.5, plot::PEAK_COLOR),
+        );
+        painter.text(
+            rect.left_top() + egui::vec2(4.0, 4.0),
+            Align2::LEFT_TOP,
+            "peak over time: residual (blue), A (orange)",
+            FontId::proportional(10.0),
+            plot::LABEL_COLOR,
+        );
+        if let Some(pos) = response.hover_pos() {
+            let fraction = ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+            let frames = result.residual.len() / result.channels.max(1) as usize;
+            let secs = fraction * frames as f32 / result.sample_rate.max(1) as f32;
+            let bucket = ((fraction * result.residual_levels.len() as f32) as usize).min(result.residual_levels.len().saturating_sub(1));
+            let level = result.residual_levels.get(bucket).copied().unwrap_or(f32::NEG_INFINITY);
+            painter.text(
+                rect.right_top() + egui::vec2(-4.0, 4.0),
+                Align2::RIGHT_TOP,
+                format!("{:.2} s: {} dBFS", secs, format_db(level)),
+                FontId::proportional(10.0),
+                plot::LABEL_COLOR,
+            );
+        }
+
+        ui.horizontal(|ui| {
+            let playing = self.player.as_ref().is_some_and(|(_, sink)| !sink.empty());
+            if playing {
+                if ui.button("Stop residual").clicked() {
+                    self.stop_playback();
+                }
+            } else if ui.button("Play residual").clicked() {
+                if let Err(e) = self.play() {
+                    self.status = format!("Can't play the residual: {}", e);
+                }
+            }
+            let gain = egui::Slider::new(&mut self.settings.listen_gain_db, 0.0..=96.0);
+            ui.add(gain.text("Listen gain").suffix(" dB"));
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.wav_path).desired_width(200.0));
+            if ui.button("Save residual").clicked() {
+                if let Some(ref result) = self.result {
+                    self.status = match result.write_wav(Path::new(&self.wav_path)) {
+                        Ok(()) => format!("Saved {}", self.wav_path),
+                        Err(e) => format!("Save failed: {}", e),
+                    };
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_context::ProcessContext;
+
+    /// Delays every channel by `frames`, reporting `reported` as its latency.
+    struct Delay {
+        frames: usize,
+        reported: usize,
+        lines: Vec<Vec<f32>>,
+    }
+
+    impl Delay {
+        fn new(frames: usize, reported: usize) -> Self {
+            Self { frames, reported, lines: Vec::new() }
+        }
+    }
+
+    impl AudioProcessor for Delay {
+        fn prepare(&mut self, _sample_rate: u32, _max_block: usize, channels: usize) {
+            self.lines = vec![vec![0.0; self.frames]; channels];
+        }
+
+        fn process(&mut self, ctx: &mut ProcessContext) {
+            for (ch, line) in self.lines.iter_mut().enumerate() {
+                for sample in ctx.channel_mut(ch) {
+                    line.push(*sample);
+                    *sample = line.remove(0);
+                }
+            }
+        }
+
+        fn latency(&self) -> usize {
+            self.reported
+        }
+    }
+
+    /// Stereo noise, different on each channel.
+    fn noise(frames: usize) -> NullTestInput {
+        let mut state = 1u32;
+        let samples = (0..frames * 2)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+            })
+            .collect();
+        NullTestInput {
+            samples,
+            channels: 2,
+            sample_rate: 48000,
+        }
+    }
+
+    #[test]
+    fn identical_sides_are_bit_exact() {
+        let input = noise(8000);
+        let result = NullTestResult::run(&input, &mut ChainProcessor::new(), &mut ChainProcessor::new(), 512, true);
+        assert_eq!(result.differing, 0);
+        assert_eq!(result.offset, 0);
+        assert!(result.verdict(-120.0) == Verdict::BitExact);
+    }
+
+    #[test]
+    fn reported_latency_is_compensated() {
+        let input = noise(20000);
+        let result = NullTestResult::run(&input, &mut ChainProcessor::new(), &mut Delay::new(100, 100), 512, false);
+        assert_eq!((result.latency_b, result.offset, result.searched_offset), (100, 100, 0));
+        assert_eq!(result.differing, 0);
+        assert_eq!(result.residual.len(), (20000 - 100) * 2);
+    }
+
+    #[test]
+    fn search_finds_unreported_delay() {
+        let input = noise(8000);
+        let result = NullTestResult::run(&input, &mut ChainProcessor::new(), &mut Delay::new(37, 10), 1000, true);
+        assert_eq!((result.offset, result.searched_offset), (37, 27));
+        assert_eq!(result.differing, 0);
+
+        // Without the search the residual is as loud as the signal itself
+        let result = NullTestResult::run(&input, &mut ChainProcessor::new(), &mut Delay::new(37, 10), 1000, false);
+        assert!(result.depth_db() > -1.0, "depth {}", result.depth_db());
+        assert!(result.verdict(-120.0) == Verdict::OutsideTolerance);
+    }
+
+    #[test]
+    fn find_offset_searches_both_ways_and_stays_put_on_silence() {
+        let a = noise(8000).samples;
+        let shifted = |lag: isize| -> Vec<f32> {
+            (0..a.len() as isize / 2)
+                .flat_map(|frame| {
+                    let source = (frame - lag).clamp(0, a.len() as isize / 2 - 1) as usize;
+                    [a[source * 2], a[source * 2 + 1]]
+                })
+                .collect()
+        };
+        assert_eq!(find_offset(&a, &shifted(250), 2, 0), 250);
+        assert_eq!(find_offset(&a, &shifted(-300), 2, 0), -300);
+        assert_eq!(find_offset(&a, &shifted(1000), 2, 900), 100);
+        let silence = vec![0.0; a.len()];
+        assert_eq!(find_offset(&silence, &shifted(250), 2, 0), 0);
+    }
+}
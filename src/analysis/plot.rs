@@ -0,0 +1,131 @@
+// src/analysis/plot.rs
+
+use eframe::egui::{self, Align2, Color32, FontId, Painter, Pos2, Rect, Stroke};
+
+/// Lowest frequency shown on log-frequency axes.
+pub const MIN_FREQ: f32 = 20.0;
+
+pub const GRID_COLOR: Color32 = Color32::from_gray(60);
+pub const LABEL_COLOR: Color32 = Color32::from_gray(150);
+pub const OUTPUT_COLOR: Color32 = Color32::from_rgb(90, 200, 255);
+pub const INPUT_COLOR: Color32 = Color32::from_rgb(255, 170, 60);
+pub const PEAK_COLOR: Color32 = Color32::from_rgb(230, 80, 80);
+
+/// Allocates a plot area of `height` spanning the available width and paints its background.
+pub fn allocate(ui: &mut egui::Ui, height: f32) -> (egui::Response, Painter, Rect) {
+    let (response, painter) =
+        ui.allocate_painter(egui::vec2(ui.available_width(), height), egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    (response, painter, rect)
+}
+
+pub fn freq_to_x(freq: f32, max_freq: f32, rect: Rect) -> f32 {
+    let t = (freq.max(MIN_FREQ) / MIN_FREQ).ln() / (max_freq / MIN_FREQ).ln();
+    rect.left() + t * rect.width()
+}
+
+pub fn x_to_freq(x: f32, max_freq: f32, rect: Rect) -> f32 {
+    let t = ((x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+    MIN_FREQ * (max_freq / MIN_FREQ).powf(t)
+}
+
+pub fn value_to_y(value: f32, min: f32, max: f32, rect: Rect) -> f32 {
+    let t = ((value - min) / (max - min)).clamp(0.0, 1.0);
+    rect.bottom() - t * rect.height()
+}
+
+/// Vertical lines at 1-2-5 steps per decade, labelled at each decade.
+pub fn draw_log_freq_grid(painter: &Painter, rect: Rect, max_freq: f32) {
+    let mut decade = 10.0;
+    while decade < max_freq {
+        for step in [1.0, 2.0, 5.0] {
+            let freq = decade * step;
+            if freq < MIN_FREQ || freq > max_freq {
+                continue;
+            }
+            let x = freq_to_x(freq, max_freq, rect);
+            painter.line_segment([Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())], Stroke::new(0.5, GRID_COLOR));
+            if step == 1.0 {
+                painter.text(
+                    Pos2::new(x + 2.0, rect.bottom() - 2.0),
+                    Align2::LEFT_BOTTOM,
+                    format_freq(freq),
+                    FontId::proportional(9.0),
+                    LABEL_COLOR,
+                );
+            }
+        }
+        decade *= 10.0;
+    }
+}
+
+/// Horizontal lines every `step` between `min` and `max`, labelled with `unit`.
+pub fn draw_value_grid(painter: &Painter, rect: Rect, min: f32, max: f32, step: f32, unit: &str) {
+    let mut value = (min / step).ceil() * step;
+    while value <= max {
+        let y = value_to_y(value, min, max, rect);
+        painter.line_segment([Pos2::new(rect.left(), y), Pos2::new(rect.right(), y)], Stroke::new(0.5, GRID_COLOR));
+        painter.text(
+            Pos2::new(rect.left() + 2.0, y),
+            Align2::LEFT_BOTTOM,
+            format!("{}{}", value, unit),
+            FontId::proportional(9.0),
+            LABEL_COLOR,
+        );
+        value += step;
+    }
+}
+
+/// Draws `values` (bins 0 to Nyquist of a real FFT) on a log-frequency axis,
+/// keeping the largest value per pixel column so narrow peaks survive.
+pub fn draw_spectrum_line(
+    painter: &Painter,
+    rect: Rect,
+    values: &[f32],
+    sample_rate: f32,
+    range: (f32, f32),
+    color: Color32,
+) {
+    if values.len() < 2 {
+        return;
+    }
+    let max_freq = sample_rate / 2.0;
+    let bin_hz = max_freq / (values.len() - 1) as f32;
+
+    let mut points: Vec<Pos2> = Vec::with_capacity(rect.width() as usize + 1);
+    let mut column = f32::NEG_INFINITY;
+    let mut column_max = f32::NEG_INFINITY;
+
+    for (bin, value) in values.iter().enumerate().skip(1) {
+        let freq = bin as f32 * bin_hz;
+        if freq < MIN_FREQ {
+            continue;
+        }
+        if freq > max_freq {
+            break;
+        }
+        let x = freq_to_x(freq, max_freq, rect).floor();
+        if x != column && column.is_finite() {
+            points.push(Pos2::new(column, value_to_y(column_max, range.0, range.1, rect)));
+            column_max = f32::NEG_INFINITY;
+        }
+        column = x;
+        column_max = column_max.max(*value);
+    }
+    if column.is_finite() {
+        points.push(Pos2::new(column, value_to_y(column_max, range.0, range.1, rect)));
+    }
+
+    if points.len() > 1 {
+        painter.add(egui::Shape::line(points, Stroke::new(1.0, color)));
+    }
+}
+
+pub fn format_freq(freq: f32) -> String {
+    if freq >= 1000.0 {
+        format!("{}k", freq / 1000.0)
+    } else {
+        format!("{}", freq)
+    }
+}
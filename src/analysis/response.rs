@@ -0,0 +1,405 @@
+// src/analysis/response.rs
+
+use eframe::egui;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::fmt::Write as _;
+use std::fs;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{chain_signature, plot, show_target_selector, target_processor};
+use crate::chain::ChainSlot;
+use crate::dsp_module::AudioProcessor;
+use crate::render::process_signal;
+
+pub const RESPONSE_SIZES: [usize; 4] = [4096, 8192, 16384, 32768];
+
+/// Rate measurements run at, independent of the file playing.
+const MEASURE_SAMPLE_RATE: u32 = 48000;
+
+const MEASURE_CHANNELS: usize = 2;
+
+const MEASURE_BLOCK: usize = 1024;
+
+/// Peak level of the excitation. Kept below full scale so modules with gain
+/// don't clip and make the measurement meaningless.
+const EXCITATION_LEVEL: f32 = 0.5;
+
+const MAGNITUDE_RANGE: (f32, f32) = (-48.0, 24.0);
+
+/// How long the chain has to stay unchanged before it's measured again, so
+/// dragging a slider doesn't start a measurement every frame.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Stimulus {
+    Impulse,
+    LogSweep,
+}
+
+impl Stimulus {
+    pub const ALL: [Stimulus; 2] = [Stimulus::Impulse, Stimulus::LogSweep];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Stimulus::Impulse => "Impulse",
+            Stimulus::LogSweep => "Log sweep",
+        }
+    }
+
+    /// `size` samples of excitation. The sweep fills the first half and
+    /// leaves the rest silent for the processor's tail.
+    fn signal(&self, size: usize, sample_rate: u32) -> Vec<f32> {
+        let mut signal = vec![0.0; size];
+        match self {
+            Stimulus::Impulse => signal[0] = EXCITATION_LEVEL,
+            Stimulus::LogSweep => {
+                let length = size / 2;
+                let (f0, f1) = (plot::MIN_FREQ, sample_rate as f32 / 2.0);
+                let duration = length as f32 / sample_rate as f32;
+                let rate = (f1 / f0).ln();
+                for (n, sample) in signal[..length].iter_mut().enumerate() {
+                    let t = n as f32 / sample_rate as f32;
+                    let phase = 2.0 * PI * f0 * duration / rate * ((t / duration * rate).exp() - 1.0);
+                    *sample = EXCITATION_LEVEL * phase.sin();
+                }
+            }
+        }
+        signal
+    }
+}
+
+/// Magnitude, phase and group delay per FFT bin, from DC to Nyquist.
+pub struct FrequencyResponse {
+    pub sample_rate: u32,
+    pub magnitude_db: Vec<f32>,
+    /// Wrapped to -180..180 degrees.
+    pub phase_deg: Vec<f32>,
+    pub group_delay_ms: Vec<f32>,
+}
+
+impl FrequencyResponse {
+    /// Feeds `stimulus` through `processor` and divides the output spectrum
+    /// by the input spectrum.
+    pub fn measure(processor: &mut dyn AudioProcessor, stimulus: Stimulus, size: usize) -> Self {
+        let sample_rate = MEASURE_SAMPLE_RATE;
+        let input = stimulus.signal(size, sample_rate);
+        let output = process_signal(processor, &input, MEASURE_CHANNELS, sample_rate, MEASURE_BLOCK);
+
+        let mut planner = FftPlanner::<f32>::new();
+        let fft = planner.plan_fft_forward(size);
+        let mut x: Vec<Complex<f32>> = input.iter().map(|&s| Complex::new(s, 0.0)).collect();
+        let mut y: Vec<Complex<f32>> = output.iter().map(|&s| Complex::new(s, 0.0)).collect();
+        fft.process(&mut x);
+        fft.process(&mut y);
+
+        // Regularized division so bins the sweep barely reaches don't blow up
+        let bins = size / 2 + 1;
+        let max_power = x[..bins].iter().map(|c| c.norm_sqr()).fold(0.0, f32::max);
+        let epsilon = max_power * 1e-8 + f32::MIN_POSITIVE;
+        let response: Vec<Complex<f32>> = x[..bins]
+            .iter()
+            .zip(&y[..bins])
+            .map(|(x, y)| y * x.conj() / (x.norm_sqr() + epsilon))
+            .collect();
+
+        let magnitude_db = response.iter().map(|h| super::spectrum::amplitude_to_db(h.norm())).collect();
+        let phase: Vec<f32> = response.iter().map(|h| h.arg()).collect();
+        let phase_deg = phase.iter().map(|p| p.to_degrees()).collect();
+
+        // Group delay is -dphi/domega over the unwrapped phase
+        let mut unwrapped = phase.clone();
+        for i in 1..unwrapped.len() {
+            let mut delta = phase[i] - phase[i - 1];
+            delta -= 2.0 * PI * (delta / (2.0 * PI)).round();
+            unwrapped[i] = unwrapped[i - 1] + delta;
+        }
+        let bin_omega = 2.0 * PI * sample_rate as f32 / size as f32;
+        let group_delay_ms = (0..bins)
+            .map(|i| {
+                let (lo, hi) = (i.saturating_sub(1), (i + 1).min(bins - 1));
+                if hi == lo {
+                    return 0.0;
+                }
+                -(unwrapped[hi] - unwrapped[lo]) / ((hi - lo) as f32 * bin_omega) * 1000.0
+            })
+            .collect();
+
+        Self {
+            sample_rate,
+            magnitude_db,
+            phase_deg,
+            group_delay_ms,
+        }
+    }
+
+    pub fn bin_freq(&self, bin: usize) -> f32 {
+        bin as f32 * self.sample_rate as f32 / 2.0 / (self.magnitude_db.len() - 1).max(1) as f32
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("freq_hz,magnitude_db,phase_deg,group_delay_ms\n");
+        for bin in 0..self.magnitude_db.len() {
+            let _ = writeln!(
+                csv,
+                "{:.3},{:.4},{:.3},{:.5}",
+                self.bin_freq(bin),
+                self.magnitude_db[bin],
+                self.phase_deg[bin],
+                self.group_delay_ms[bin]
+            );
+        }
+        csv
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ResponseView {
+    Magnitude,
+    Phase,
+    GroupDelay,
+}
+
+impl ResponseView {
+    const ALL: [ResponseView; 3] = [ResponseView::Magnitude, ResponseView::Phase, ResponseView::GroupDelay];
+
+    fn label(&self) -> &'static str {
+        match self {
+            ResponseView::Magnitude => "Magnitude",
+            ResponseView::Phase => "Phase",
+            ResponseView::GroupDelay => "Group delay",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResponseSettings {
+    pub stimulus: Stimulus,
+    pub size: usize,
+    /// Index of the slot to measure, or `None` for the whole chain.
+    pub target: Option<usize>,
+    pub view: ResponseView,
+}
+
+impl Default for ResponseSettings {
+    fn default() -> Self {
+        Self {
+            stimulus: Stimulus::LogSweep,
+            size: 16384,
+            target: None,
+            view: ResponseView::Magnitude,
+        }
+    }
+}
+
+/// Frequency response panel for the whole chain or one of its modules.
+/// Measures again on a worker thread whenever a parameter, bypass switch or
+/// the chain changes and then stays put for a moment.
+pub struct ResponseAnalyzer {
+    pub settings: ResponseSettings,
+    response: Option<FrequencyResponse>,
+    /// What the last measurement was started with; a mismatch triggers a new one.
+    measured: Vec<u64>,
+    /// A signature that differs from `measured`, and when it was first seen.
+    pending: Option<(Vec<u64>, Instant)>,
+    job: Option<JoinHandle<FrequencyResponse>>,
+    csv_path: String,
+    status: String,
+}
+
+impl ResponseAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            settings: ResponseSettings::default(),
+            response: None,
+            measured: Vec::new(),
+            pending: None,
+            job: None,
+            csv_path: "response.csv".to_string(),
+            status: String::new(),
+        }
+    }
+
+    fn signature(&self, slots: &[ChainSlot]) -> Vec<u64> {
+        let mut signature = vec![
+            self.settings.stimulus as u64,
+            self.settings.size as u64,
+            self.settings.target.map_or(u64::MAX, |t| t as u64),
+        ];
+        signature.extend(chain_signature(slots));
+        signature
+    }
+
+    /// Collects a finished measurement and starts a new one once the chain
+    /// has settled on a signature that hasn't been measured.
+    fn update_measurement(&mut self, ctx: &egui::Context, slots: &[ChainSlot]) {
+        if self.job.as_ref().is_some_and(|job| job.is_finished()) {
+            if let Some(Ok(response)) = self.job.take().map(JoinHandle::join) {
+                self.response = Some(response);
+            }
+        }
+
+        let signature = self.signature(slots);
+        if signature == self.measured {
+            self.pending = None;
+        } else if self.pending.as_ref().is_none_or(|(pending, _)| *pending != signature) {
+            self.pending = Some((signature, Instant::now()));
+        }
+
+        let settled = self.pending.as_ref().is_some_and(|(_, since)| since.elapsed() >= DEBOUNCE);
+        if settled && self.job.is_none() {
+            if let Some((signature, _)) = self.pending.take() {
+                let mut processor = target_processor(slots, self.settings.target);
+                let (stimulus, size) = (self.settings.stimulus, self.settings.size);
+                self.job = Some(thread::spawn(move || FrequencyResponse::measure(&mut processor, stimulus, size)));
+                self.measured = signature;
+            }
+        }
+
+        if self.job.is_some() || self.pending.is_some() {
+            ctx.request_repaint_after(Duration::from_millis(50));
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, slots: &[ChainSlot]) {
+        ui.horizontal(|ui| {
+            show_target_selector(ui, "response_target", &mut self.settings.target, slots);
+            egui::ComboBox::from_id_source("response_stimulus")
+                .selected_text(self.settings.stimulus.label())
+                .show_ui(ui, |cb| {
+                    for stimulus in Stimulus::ALL {
+                        cb.selectable_value(&mut self.settings.stimulus, stimulus, stimulus.label());
+                    }
+                });
+            egui::ComboBox::from_id_source("response_size")
+                .selected_text(format!("{} samples", self.settings.size))
+                .show_ui(ui, |cb| {
+                    for size in RESPONSE_SIZES {
+                        cb.selectable_value(&mut self.settings.size, size, size.to_string());
+                    }
+                });
+            for view in ResponseView::ALL {
+                ui.selectable_value(&mut self.settings.view, view, view.label());
+            }
+        });
+
+        self.update_measurement(ui.ctx(), slots);
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.csv_path).desired_width(200.0));
+            if ui.button("Export CSV").clicked() {
+                if let Some(ref response) = self.response {
+                    self.status = match fs::write(&self.csv_path, response.to_csv()) {
+                        Ok(()) => format!("Saved {}", self.csv_path),
+                        Err(e) => format!("Save failed: {}", e),
+                    };
+                }
+            }
+            if self.job.is_some() || self.pending.is_some() {
+                ui.label("Measuring...");
+            } else if !self.status.is_empty() {
+                ui.label(&self.status);
+            }
+        });
+
+        let (response, painter, rect) = plot::allocate(ui, 220.0);
+        let Some(ref measured) = self.response else {
+            return;
+        };
+        let sample_rate = measured.sample_rate as f32;
+        plot::draw_log_freq_grid(&painter, rect, sample_rate / 2.0);
+
+        let (values, range, step, unit) = match self.settings.view {
+            ResponseView::Magnitude => (&measured.magnitude_db, MAGNITUDE_RANGE, 12.0, " dB"),
+            ResponseView::Phase => (&measured.phase_deg, (-180.0, 180.0), 90.0, "°"),
+            ResponseView::GroupDelay => {
+                let max = measured.group_delay_ms.iter().skip(1).fold(1.0f32, |m, v| m.max(*v));
+                let max = (max * 1.2).min(1000.0);
+                (&measured.group_delay_ms, (0.0, max), nice_step(max), " ms")
+            }
+        };
+        plot::draw_value_grid(&painter, rect, range.0, range.1, step, unit);
+        plot::draw_spectrum_line(&painter, rect, values, sample_rate, range, plot::OUTPUT_COLOR);
+
+        if let Some(pos) = response.hover_pos() {
+            let freq = plot::x_to_freq(pos.x, sample_rate / 2.0, rect);
+            let bin = ((freq / sample_rate * 2.0) * (values.len() - 1) as f32).round() as usize;
+            let value = values.get(bin).copied().unwrap_or(0.0);
+            painter.text(
+                rect.right_top() + egui::vec2(-4.0, 4.0),
+                egui::Align2::RIGHT_TOP,
+                format!("{:.0} Hz: {:.2}{}", freq, value, unit),
+                egui::FontId::proportional(10.0),
+                plot::LABEL_COLOR,
+            );
+        }
+    }
+}
+
+/// A 1-2-5 grid step giving roughly four lines up to `max`.
+fn nice_step(max: f32) -> f32 {
+    let rough = max / 4.0;
+    let decade = 10f32.powf(rough.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * decade)
+        .find(|step| *step >= rough)
+        .unwrap_or(decade * 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::ChainProcessor;
+    use crate::process_context::ProcessContext;
+
+    /// Delays every channel by a whole number of samples.
+    struct Delay {
+        samples: usize,
+        lines: Vec<Vec<f32>>,
+    }
+
+    impl AudioProcessor for Delay {
+        fn prepare(&mut self, _sample_rate: u32, _max_block: usize, channels: usize) {
+            self.lines = vec![vec![0.0; self.samples]; channels];
+        }
+
+        fn process(&mut self, ctx: &mut ProcessContext) {
+            for ch in 0..ctx.num_channels() {
+                let line = &mut self.lines[ch];
+                for sample in ctx.channel_mut(ch) {
+                    line.push(*sample);
+                    *sample = line.remove(0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn identity_is_flat() {
+        for stimulus in Stimulus::ALL {
+            let response = FrequencyResponse::measure(&mut ChainProcessor::new(), stimulus, 8192);
+            // The sweep starts at MIN_FREQ, so skip the bins below it
+            let first = (plot::MIN_FREQ / response.bin_freq(1)).ceil() as usize;
+            for bin in first..response.magnitude_db.len() - 1 {
+                assert!(response.magnitude_db[bin].abs() < 0.1, "{:?} bin {}", stimulus, bin);
+                assert!(response.phase_deg[bin].abs() < 1.0, "{:?} bin {}", stimulus, bin);
+            }
+        }
+    }
+
+    #[test]
+    fn delay_shows_as_group_delay() {
+        let mut delay = Delay { samples: 48, lines: Vec::new() };
+        let response = FrequencyResponse::measure(&mut delay, Stimulus::Impulse, 8192);
+        // 48 samples at 48 kHz
+        for bin in 10..response.group_delay_ms.len() - 10 {
+            assert!((response.group_delay_ms[bin] - 1.0).abs() < 0.01, "bin {}", bin);
+            assert!(response.magnitude_db[bin].abs() < 0.01, "bin {}", bin);
+        }
+    }
+}
@@ -0,0 +1,298 @@
+// src/analysis/scope.rs
+
+use eframe::egui::{self, Align2, Color32, FontId, Painter, Pos2, Rect, Sense, Stroke};
+use serde::{Deserialize, Serialize};
+
+use super::plot;
+use super::tap::{SampleTap, TAP_CAPACITY};
+use super::Monitoring;
+use crate::transport::Transport;
+
+/// Horizontal divisions across the scope.
+const DIVISIONS: usize = 10;
+
+/// Share of the trace shown before the trigger point.
+const PRE_TRIGGER: f32 = 0.1;
+
+/// Time bases offered, in milliseconds per division.
+const TIME_BASES_MS: [f32; 10] = [0.1, 0.2, 0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0];
+
+/// Min/max pairs kept for the whole-file overview.
+pub const OVERVIEW_BUCKETS: usize = 2048;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScopeSettings {
+    pub ms_per_div: f32,
+    pub trigger_level: f32,
+    /// Wait for a rising edge through `trigger_level`; free-runs when off or
+    /// when no edge is found.
+    pub triggered: bool,
+    /// Channel to show, or `None` for the mono mix.
+    pub channel: Option<usize>,
+    pub show_input: bool,
+    pub zoom: f32,
+}
+
+impl Default for ScopeSettings {
+    fn default() -> Self {
+        Self {
+            ms_per_div: 1.0,
+            trigger_level: 0.0,
+            triggered: true,
+            channel: None,
+            show_input: false,
+            zoom: 1.0,
+        }
+    }
+}
+
+/// Triggered oscilloscope over the live output, with the input optionally
+/// drawn behind it.
+pub struct Oscilloscope {
+    pub settings: ScopeSettings,
+    output: Vec<f32>,
+    input: Vec<f32>,
+    /// Start of the displayed window within the read buffers.
+    start: usize,
+    window: usize,
+    trigger_found: bool,
+    sample_rate: u32,
+}
+
+/// The frames of `tap` ending at `end`, from `channel` or the mono mix.
+fn read(tap: &SampleTap, channel: Option<usize>, out: &mut [f32], end: u64) -> Option<u32> {
+    match channel {
+        Some(channel) => tap.read_channel_ending_at(channel, out, end),
+        None => tap.read_mono_ending_at(out, end),
+    }
+}
+
+impl Oscilloscope {
+    pub fn new() -> Self {
+        Self {
+            settings: ScopeSettings::default(),
+            output: Vec::new(),
+            input: Vec::new(),
+            start: 0,
+            window: 0,
+            trigger_found: false,
+            sample_rate: 0,
+        }
+    }
+
+    /// Reads the latest audio and finds the most recent trigger point that
+    /// still leaves a full window after it.
+    pub fn update(&mut self, monitoring: &Monitoring) {
+        let sample_rate = monitoring.output_tap.sample_rate();
+        if sample_rate == 0 {
+            return;
+        }
+        let window_secs = self.settings.ms_per_div * DIVISIONS as f32 / 1000.0;
+        let window = ((window_secs * sample_rate as f32) as usize).clamp(2, TAP_CAPACITY / 2);
+        // Twice the window, so there's a full window to search for an edge in
+        self.output.resize(window * 2, 0.0);
+        let end = monitoring.output_tap.total_frames();
+        let Some(sample_rate) = read(&monitoring.output_tap, self.settings.channel, &mut self.output, end) else {
+            return;
+        };
+        if self.settings.show_input {
+            // The same frames as the output, even if a block arrived in between
+            self.input.resize(window * 2, 0.0);
+            if read(&monitoring.input_tap, self.settings.channel, &mut self.input, end).is_none() {
+                self.input.clear();
+            }
+        }
+        self.sample_rate = sample_rate;
+        self.window = window;
+
+        let pre = (window as f32 * PRE_TRIGGER) as usize;
+        let latest = self.output.len() - window + pre;
+        let level = self.settings.trigger_level;
+        let edge = (pre.max(1)..=latest)
+            .rev()
+            .find(|&i| self.output[i - 1] < level && self.output[i] >= level);
+        self.trigger_found = self.settings.triggered && edge.is_some();
+        self.start = match edge {
+            Some(i) if self.settings.triggered => i - pre,
+            _ => self.output.len() - window,
+        };
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, channels: usize) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("scope_time_base")
+                .selected_text(format!("{} ms/div", self.settings.ms_per_div))
+                .show_ui(ui, |cb| {
+                    for ms in TIME_BASES_MS {
+                        cb.selectable_value(&mut self.settings.ms_per_div, ms, format!("{} ms/div", ms));
+                    }
+                });
+            let channel_label = |channel: Option<usize>| match channel {
+                Some(c) => format!("Ch {}", c + 1),
+                None => "Mix".to_string(),
+            };
+            egui::ComboBox::from_id_source("scope_channel")
+                .selected_text(channel_label(self.settings.channel))
+                .show_ui(ui, |cb| {
+                    cb.selectable_value(&mut self.settings.channel, None, channel_label(None));
+                    for c in 0..channels {
+                        cb.selectable_value(&mut self.settings.channel, Some(c), channel_label(Some(c)));
+                    }
+                });
+            ui.checkbox(&mut self.settings.triggered, "Trigger");
+            ui.add_enabled(
+                self.settings.triggered,
+                egui::Slider::new(&mut self.settings.trigger_level, -1.0..=1.0).text("Level"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut self.settings.zoom, 1.0..=16.0).logarithmic(true).text("Zoom"));
+            ui.checkbox(&mut self.settings.show_input, "Show input");
+            if self.settings.triggered && self.sample_rate != 0 && !self.trigger_found {
+                ui.label("No trigger, free-running");
+            }
+        });
+
+        let (_, painter, rect) = plot::allocate(ui, 200.0);
+        for i in 1..DIVISIONS {
+            let x = rect.left() + rect.width() * i as f32 / DIVISIONS as f32;
+            painter.line_segment([Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())], Stroke::new(0.5, plot::GRID_COLOR));
+        }
+        let center = rect.center().y;
+        painter.line_segment([Pos2::new(rect.left(), center), Pos2::new(rect.right(), center)], Stroke::new(0.5, plot::GRID_COLOR));
+        painter.text(
+            rect.left_bottom() + egui::vec2(2.0, -2.0),
+            Align2::LEFT_BOTTOM,
+            format!("{} ms/div", self.settings.ms_per_div),
+            FontId::proportional(9.0),
+            plot::LABEL_COLOR,
+        );
+
+        if self.window == 0 || self.output.len() < self.start + self.window {
+            return;
+        }
+        let range = (-1.0 / self.settings.zoom, 1.0 / self.settings.zoom);
+        if self.settings.triggered {
+            let y = plot::value_to_y(self.settings.trigger_level, range.0, range.1, rect);
+            painter.line_segment([Pos2::new(rect.left(), y), Pos2::new(rect.right(), y)], Stroke::new(0.5, plot::PEAK_COLOR));
+        }
+        let window = self.start..self.start + self.window;
+        if self.settings.show_input && self.input.len() >= window.end {
+            draw_trace(&painter, rect, &self.input[window.clone()], range, plot::INPUT_COLOR);
+        }
+        draw_trace(&painter, rect, &self.output[window], range, plot::OUTPUT_COLOR);
+    }
+}
+
+/// Draws `samples` across `rect`. With more samples than pixels each column
+/// becomes a min-max line so peaks aren't lost.
+fn draw_trace(painter: &Painter, rect: Rect, samples: &[f32], range: (f32, f32), color: Color32) {
+    let columns = rect.width().max(1.0) as usize;
+    let stroke = Stroke::new(1.0, color);
+    if samples.len() <= columns * 2 {
+        let step = rect.width() / (samples.len() - 1).max(1) as f32;
+        let points: Vec<Pos2> = samples
+            .iter()
+            .enumerate()
+            .map(|(i, s)| Pos2::new(rect.left() + i as f32 * step, plot::value_to_y(*s, range.0, range.1, rect)))
+            .collect();
+        painter.add(egui::Shape::line(points, stroke));
+        return;
+    }
+    for column in 0..columns {
+        let from = column * samples.len() / columns;
+        let to = ((column + 1) * samples.len() / columns).max(from + 1);
+        let (min, max) = samples[from..to]
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), s| (lo.min(*s), hi.max(*s)));
+        let x = rect.left() + column as f32;
+        painter.line_segment(
+            [
+                Pos2::new(x, plot::value_to_y(max, range.0, range.1, rect)),
+                Pos2::new(x, plot::value_to_y(min, range.0, range.1, rect) + 0.5),
+            ],
+            stroke,
+        );
+    }
+}
+
+/// Min/max of interleaved `samples` over all channels in `buckets` equal
+/// slices of the file.
+pub fn waveform_overview(samples: &[f32], channels: usize, buckets: usize) -> Vec<(f32, f32)> {
+    let frames = samples.len() / channels.max(1);
+    if frames == 0 {
+        return Vec::new();
+    }
+    let buckets = buckets.min(frames);
+    (0..buckets)
+        .map(|bucket| {
+            let from = bucket * frames / buckets * channels;
+            let to = (bucket + 1) * frames / buckets * channels;
+            samples[from..to]
+                .iter()
+                .fold((0.0f32, 0.0f32), |(lo, hi), s| (lo.min(*s), hi.max(*s)))
+        })
+        .collect()
+}
+
+/// The whole file with the loop region and playhead. Click or drag to seek;
+/// returns the frame to seek to.
+pub fn show_waveform_overview(ui: &mut egui::Ui, transport: &Transport) -> Option<u64> {
+    let size = egui::vec2(ui.available_width(), 80.0);
+    let (response, painter) = ui.allocate_painter(size, Sense::click_and_drag());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+    let total = transport.total_frames();
+    let overview = transport.overview();
+    if total == 0 || overview.is_empty() {
+        painter.text(
+            rect.center(),
+            Align2::CENTER_CENTER,
+            "No file loaded",
+            FontId::proportional(10.0),
+            plot::LABEL_COLOR,
+        );
+        return None;
+    }
+    let frame_to_x = |frame: u64| rect.left() + rect.width() * (frame as f32 / total as f32);
+
+    let (start, end) = transport.loop_points();
+    if end > start {
+        let alpha = if transport.loop_enabled() { 60 } else { 20 };
+        let region = Rect::from_min_max(Pos2::new(frame_to_x(start), rect.top()), Pos2::new(frame_to_x(end), rect.bottom()));
+        painter.rect_filled(region, 0.0, Color32::from_rgba_unmultiplied(90, 200, 255, alpha));
+    }
+
+    let columns = rect.width().max(1.0) as usize;
+    let stroke = Stroke::new(1.0, plot::OUTPUT_COLOR);
+    for column in 0..columns {
+        let from = column * overview.len() / columns;
+        let to = ((column + 1) * overview.len() / columns).max(from + 1).min(overview.len());
+        let (min, max) = overview[from..to]
+            .iter()
+            .fold((0.0f32, 0.0f32), |(lo, hi), (min, max)| (lo.min(*min), hi.max(*max)));
+        let x = rect.left() + column as f32;
+        painter.line_segment(
+            [
+                Pos2::new(x, plot::value_to_y(max, -1.0, 1.0, rect)),
+                Pos2::new(x, plot::value_to_y(min, -1.0, 1.0, rect) + 0.5),
+            ],
+            stroke,
+        );
+    }
+
+    let x = frame_to_x(transport.position());
+    painter.line_segment(
+        [Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())],
+        Stroke::new(2.0, Color32::from_rgb(255, 210, 90)),
+    );
+
+    if response.clicked() || response.dragged() {
+        let pos = response.interact_pointer_pos()?;
+        let fraction = ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+        return Some((fraction as f64 * total as f64) as u64);
+    }
+    None
+}
@@ -0,0 +1,285 @@
+// src/analysis/spectrogram.rs
+
+use eframe::egui::{self, Align2, Color32, FontId, Pos2, Rect, Stroke};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use super::plot;
+use super::spectrum::{SpectrumCalculator, WindowFunction, FFT_SIZES};
+use super::Monitoring;
+
+/// Time columns kept; the newest is drawn at the right edge.
+const COLUMNS: usize = 400;
+
+/// Log-spaced frequency rows from `plot::MIN_FREQ` to Nyquist.
+const ROWS: usize = 256;
+
+/// Most columns added in one update, so a stalled GUI doesn't spend a frame
+/// catching up on audio it will scroll past anyway.
+const MAX_COLUMNS_PER_UPDATE: u64 = 16;
+
+/// Colour range of the difference view, in dB either side of zero.
+const DIFFERENCE_RANGE_DB: f32 = 24.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum SpectrogramMode {
+    Output,
+    Input,
+    /// Output minus input per bin: blue where the module removed energy,
+    /// red where it added some.
+    Difference,
+}
+
+impl SpectrogramMode {
+    pub const ALL: [SpectrogramMode; 3] = [SpectrogramMode::Output, SpectrogramMode::Input, SpectrogramMode::Difference];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SpectrogramMode::Output => "Output",
+            SpectrogramMode::Input => "Input",
+            SpectrogramMode::Difference => "Difference",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpectrogramSettings {
+    pub fft_size: usize,
+    pub mode: SpectrogramMode,
+    /// Level drawn black, in dB.
+    pub floor_db: f32,
+}
+
+impl Default for SpectrogramSettings {
+    fn default() -> Self {
+        Self {
+            fft_size: 2048,
+            mode: SpectrogramMode::Output,
+            floor_db: -120.0,
+        }
+    }
+}
+
+/// Scrolling STFT of the live signal, one column per hop of half an FFT.
+pub struct Spectrogram {
+    pub settings: SpectrogramSettings,
+    calculator: SpectrumCalculator,
+    samples: Vec<f32>,
+    output_db: Vec<f32>,
+    input_db: Vec<f32>,
+    /// Row values per column, in dB or dB difference depending on the mode
+    /// they were taken in.
+    columns: VecDeque<Vec<f32>>,
+    /// Tap position up to which columns have been taken.
+    consumed: u64,
+    sample_rate: u32,
+    texture: Option<egui::TextureHandle>,
+}
+
+/// Highest value of `spectrum_db` within each log-spaced row.
+fn to_rows(spectrum_db: &[f32], sample_rate: u32, rows: &mut Vec<f32>) {
+    rows.clear();
+    let bins = spectrum_db.len();
+    let nyquist = sample_rate as f32 / 2.0;
+    let bin_hz = nyquist / (bins - 1).max(1) as f32;
+    let row_freq = |row: f32| plot::MIN_FREQ * (nyquist / plot::MIN_FREQ).powf(row / ROWS as f32);
+    for row in 0..ROWS {
+        let lo = ((row_freq(row as f32) / bin_hz).round() as usize).min(bins - 1);
+        let hi = ((row_freq(row as f32 + 1.0) / bin_hz).round() as usize).clamp(lo + 1, bins);
+        rows.push(spectrum_db[lo..hi].iter().cloned().fold(f32::NEG_INFINITY, f32::max));
+    }
+}
+
+/// Black through purple and orange to pale yellow.
+fn heat_color(t: f32) -> Color32 {
+    const STOPS: [(f32, [f32; 3]); 5] = [
+        (0.0, [0.0, 0.0, 0.0]),
+        (0.3, [60.0, 15.0, 110.0]),
+        (0.6, [190.0, 55.0, 80.0]),
+        (0.85, [250.0, 150.0, 40.0]),
+        (1.0, [255.0, 250.0, 190.0]),
+    ];
+    let t = t.clamp(0.0, 1.0);
+    let upper = STOPS.iter().position(|(stop, _)| *stop >= t).unwrap_or(STOPS.len() - 1).max(1);
+    let (t0, c0) = STOPS[upper - 1];
+    let (t1, c1) = STOPS[upper];
+    let f = (t - t0) / (t1 - t0);
+    let channel = |i: usize| (c0[i] + (c1[i] - c0[i]) * f) as u8;
+    Color32::from_rgb(channel(0), channel(1), channel(2))
+}
+
+/// Blue for negative, black at zero, red for positive.
+fn difference_color(db: f32) -> Color32 {
+    let t = (db / DIFFERENCE_RANGE_DB).clamp(-1.0, 1.0);
+    if t < 0.0 {
+        Color32::from_rgb(0, (-t * 120.0) as u8, (-t * 255.0) as u8)
+    } else {
+        Color32::from_rgb((t * 255.0) as u8, (t * 90.0) as u8, 0)
+    }
+}
+
+impl Spectrogram {
+    pub fn new() -> Self {
+        Self {
+            settings: SpectrogramSettings::default(),
+            calculator: SpectrumCalculator::new(),
+            samples: Vec::new(),
+            output_db: Vec::new(),
+            input_db: Vec::new(),
+            columns: VecDeque::with_capacity(COLUMNS + 1),
+            consumed: 0,
+            sample_rate: 0,
+            texture: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.columns.clear();
+    }
+
+    /// Adds a column for every hop of audio that has arrived since the last
+    /// update.
+    pub fn update(&mut self, monitoring: &Monitoring) {
+        let tap = &monitoring.output_tap;
+        let total = tap.total_frames();
+        let sample_rate = tap.sample_rate();
+        if total < self.consumed || sample_rate != self.sample_rate {
+            // New stream
+            self.consumed = 0;
+            self.sample_rate = sample_rate;
+            self.clear();
+        }
+
+        let hop = (self.settings.fft_size / 2) as u64;
+        let pending = (total - self.consumed) / hop;
+        if pending == 0 {
+            return;
+        }
+        let count = pending.min(MAX_COLUMNS_PER_UPDATE);
+        let mut rows = Vec::with_capacity(ROWS);
+        for k in (0..count).rev() {
+            // Both taps are read up to the same frame, which `push_taps`
+            // keeps identical on each side
+            let end = total - k * hop;
+            self.samples.resize(self.settings.fft_size, 0.0);
+            if tap.read_mono_ending_at(&mut self.samples, end).is_none() {
+                continue;
+            }
+            self.calculator.magnitudes_db(&self.samples, WindowFunction::Hann, &mut self.output_db);
+
+            if self.settings.mode != SpectrogramMode::Output {
+                if monitoring.input_tap.read_mono_ending_at(&mut self.samples, end).is_none() {
+                    continue;
+                }
+                self.calculator.magnitudes_db(&self.samples, WindowFunction::Hann, &mut self.input_db);
+            }
+
+            match self.settings.mode {
+                SpectrogramMode::Output => to_rows(&self.output_db, sample_rate, &mut rows),
+                SpectrogramMode::Input => to_rows(&self.input_db, sample_rate, &mut rows),
+                SpectrogramMode::Difference => {
+                    // Bins where both sides are below the floor are noise, not a difference
+                    let floor = self.settings.floor_db;
+                    let difference: Vec<f32> = self
+                        .output_db
+                        .iter()
+                        .zip(&self.input_db)
+                        .map(|(out, inp)| if out.max(*inp) < floor { 0.0 } else { out.max(floor) - inp.max(floor) })
+                        .collect();
+                    to_rows(&difference, sample_rate, &mut rows);
+                }
+            }
+            self.columns.push_back(rows.clone());
+            if self.columns.len() > COLUMNS {
+                self.columns.pop_front();
+            }
+        }
+        self.consumed += pending * hop;
+    }
+
+    fn image(&self) -> egui::ColorImage {
+        let mut image = egui::ColorImage::new([COLUMNS, ROWS], Color32::BLACK);
+        let offset = COLUMNS - self.columns.len();
+        for (x, column) in self.columns.iter().enumerate() {
+            for (row, value) in column.iter().enumerate() {
+                let color = match self.settings.mode {
+                    SpectrogramMode::Difference => difference_color(*value),
+                    _ => heat_color((value - self.settings.floor_db) / -self.settings.floor_db),
+                };
+                // Row 0 is the lowest frequency, drawn at the bottom
+                image[(offset + x, ROWS - 1 - row)] = color;
+            }
+        }
+        image
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let previous = (self.settings.fft_size, self.settings.mode);
+            egui::ComboBox::from_id_source("spectrogram_fft_size")
+                .selected_text(format!("FFT {}", self.settings.fft_size))
+                .show_ui(ui, |cb| {
+                    for size in FFT_SIZES {
+                        cb.selectable_value(&mut self.settings.fft_size, size, size.to_string());
+                    }
+                });
+            for mode in SpectrogramMode::ALL {
+                ui.selectable_value(&mut self.settings.mode, mode, mode.label());
+            }
+            ui.add(egui::Slider::new(&mut self.settings.floor_db, -160.0..=-40.0).text("Floor").suffix(" dB"));
+            if previous != (self.settings.fft_size, self.settings.mode) {
+                // Old columns are in different units or time resolution
+                self.clear();
+            }
+        });
+
+        let image = self.image();
+        let texture = match self.texture {
+            Some(ref mut texture) => {
+                texture.set(image, egui::TextureOptions::LINEAR);
+                texture
+            }
+            None => self
+                .texture
+                .insert(ui.ctx().load_texture("spectrogram", image, egui::TextureOptions::LINEAR)),
+        };
+
+        let (_, painter, rect) = plot::allocate(ui, 220.0);
+        painter.image(
+            texture.id(),
+            rect,
+            Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+            Color32::WHITE,
+        );
+        if self.sample_rate == 0 {
+            return;
+        }
+
+        // Frequency labels up the left edge, at each decade
+        let nyquist = self.sample_rate as f32 / 2.0;
+        let mut freq = 100.0;
+        while freq < nyquist {
+            let t = (freq / plot::MIN_FREQ).ln() / (nyquist / plot::MIN_FREQ).ln();
+            let y = rect.bottom() - t * rect.height();
+            painter.line_segment([Pos2::new(rect.left(), y), Pos2::new(rect.left() + 6.0, y)], Stroke::new(1.0, plot::LABEL_COLOR));
+            painter.text(
+                Pos2::new(rect.left() + 8.0, y),
+                Align2::LEFT_CENTER,
+                plot::format_freq(freq),
+                FontId::proportional(9.0),
+                plot::LABEL_COLOR,
+            );
+            freq *= 10.0;
+        }
+        if self.settings.mode == SpectrogramMode::Difference {
+            painter.text(
+                rect.right_top() + egui::vec2(-4.0, 4.0),
+                Align2::RIGHT_TOP,
+                format!("blue: removed, red: added (±{} dB)", DIFFERENCE_RANGE_DB),
+                FontId::proportional(10.0),
+                plot::LABEL_COLOR,
+            );
+        }
+    }
+}
@@ -0,0 +1,268 @@
+// src/analysis/spectrum.rs
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::f32::consts::PI;
+
+use super::plot;
+use super::tap::SampleTap;
+use super::Monitoring;
+
+pub const FFT_SIZES: [usize; 6] = [512, 1024, 2048, 4096, 8192, 16384];
+
+/// Floor used for silent bins so the dB values stay finite.
+pub const MIN_DB: f32 = -160.0;
+
+const DISPLAY_RANGE: (f32, f32) = (-120.0, 0.0);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum WindowFunction {
+    Rectangular,
+    Hann,
+    Hamming,
+    BlackmanHarris,
+}
+
+impl WindowFunction {
+    pub const ALL: [WindowFunction; 4] = [
+        WindowFunction::Rectangular,
+        WindowFunction::Hann,
+        WindowFunction::Hamming,
+        WindowFunction::BlackmanHarris,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WindowFunction::Rectangular => "Rectangular",
+            WindowFunction::Hann => "Hann",
+            WindowFunction::Hamming => "Hamming",
+            WindowFunction::BlackmanHarris => "Blackman-Harris",
+        }
+    }
+
+    pub fn coefficients(&self, size: usize) -> Vec<f32> {
+        let n = size.max(2) as f32 - 1.0;
+        (0..size)
+            .map(|i| {
+                let x = 2.0 * PI * i as f32 / n;
+                match self {
+                    WindowFunction::Rectangular => 1.0,
+                    WindowFunction::Hann => 0.5 - 0.5 * x.cos(),
+                    WindowFunction::Hamming => 0.54 - 0.46 * x.cos(),
+                    WindowFunction::BlackmanHarris => {
+                        0.35875 - 0.48829 * x.cos() + 0.14128 * (2.0 * x).cos() - 0.01168 * (3.0 * x).cos()
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpectrumSettings {
+    pub fft_size: usize,
+    pub window: WindowFunction,
+    /// Exponential averaging factor, 0 for none.
+    pub averaging: f32,
+    pub peak_hold: bool,
+    /// Also draw the spectrum of the signal entering the module.
+    pub show_input: bool,
+}
+
+impl Default for SpectrumSettings {
+    fn default() -> Self {
+        Self {
+            fft_size: 4096,
+            window: WindowFunction::Hann,
+            averaging: 0.7,
+            peak_hold: false,
+            show_input: false,
+        }
+    }
+}
+
+/// Windowed FFT of a block of samples, with each bin in dB relative to a
+/// full-scale sine.
+pub struct SpectrumCalculator {
+    planner: FftPlanner<f32>,
+    window: WindowFunction,
+    coefficients: Vec<f32>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl SpectrumCalculator {
+    pub fn new() -> Self {
+        Self {
+            planner: FftPlanner::new(),
+            window: WindowFunction::Rectangular,
+            coefficients: Vec::new(),
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Writes `samples.len() / 2 + 1` dB values into `out`.
+    pub fn magnitudes_db(&mut self, samples: &[f32], window: WindowFunction, out: &mut Vec<f32>) {
+        self.magnitudes(samples, window, out);
+        for value in out.iter_mut() {
+            *value = amplitude_to_db(*value);
+        }
+    }
+
+    /// Writes `samples.len() / 2 + 1` linear amplitudes into `out`, scaled so
+    /// a full-scale sine on a bin reads 1.0.
+    pub fn magnitudes(&mut self, samples: &[f32], window: WindowFunction, out: &mut Vec<f32>) {
+        let size = samples.len();
+        if self.coefficients.len() != size || self.window != window {
+            self.coefficients = window.coefficients(size);
+            self.window = window;
+        }
+
+        self.scratch.clear();
+        self.scratch.extend(
+            samples
+                .iter()
+                .zip(&self.coefficients)
+                .map(|(s, w)| Complex::new(s * w, 0.0)),
+        );
+        self.planner.plan_fft_forward(size).process(&mut self.scratch);
+
+        let scale = 2.0 / self.coefficients.iter().sum::<f32>().max(f32::EPSILON);
+        out.clear();
+        out.extend(self.scratch[..size / 2 + 1].iter().map(|c| c.norm() * scale));
+    }
+}
+
+pub fn amplitude_to_db(amplitude: f32) -> f32 {
+    (20.0 * amplitude.max(1e-12).log10()).max(MIN_DB)
+}
+
+/// Live FFT view of the post-DSP (and optionally pre-DSP) signal.
+pub struct SpectrumAnalyzer {
+    pub settings: SpectrumSettings,
+    calculator: SpectrumCalculator,
+    samples: Vec<f32>,
+    frame: Vec<f32>,
+    output_db: Vec<f32>,
+    input_db: Vec<f32>,
+    peak_db: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            settings: SpectrumSettings::default(),
+            calculator: SpectrumCalculator::new(),
+            samples: Vec::new(),
+            frame: Vec::new(),
+            output_db: Vec::new(),
+            input_db: Vec::new(),
+            peak_db: Vec::new(),
+            sample_rate: 0,
+        }
+    }
+
+    pub fn reset_peaks(&mut self) {
+        self.peak_db.clear();
+    }
+
+    /// Reads the latest audio from the taps and folds it into the averages.
+    pub fn update(&mut self, monitoring: &Monitoring) {
+        if let Some(sample_rate) = self.analyze(&monitoring.output_tap, false) {
+            self.sample_rate = sample_rate;
+        }
+        if self.settings.show_input {
+            self.analyze(&monitoring.input_tap, true);
+        }
+
+        if self.settings.peak_hold {
+            if self.peak_db.len() != self.output_db.len() {
+                self.peak_db = self.output_db.clone();
+            }
+            for (peak, value) in self.peak_db.iter_mut().zip(&self.output_db) {
+                *peak = peak.max(*value);
+            }
+        }
+    }
+
+    fn analyze(&mut self, tap: &SampleTap, input: bool) -> Option<u32> {
+        self.samples.resize(self.settings.fft_size, 0.0);
+        let sample_rate = tap.read_mono(&mut self.samples)?;
+        self.calculator.magnitudes_db(&self.samples, self.settings.window, &mut self.frame);
+
+        let averaged = if input { &mut self.input_db } else { &mut self.output_db };
+        if averaged.len() != self.frame.len() {
+            // FFT size changed, start the average over
+            averaged.clone_from(&self.frame);
+            if !input {
+                self.peak_db.clear();
+            }
+        } else {
+            let a = self.settings.averaging;
+            for (avg, new) in averaged.iter_mut().zip(&self.frame) {
+                *avg = a * *avg + (1.0 - a) * new;
+            }
+        }
+        Some(sample_rate)
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("spectrum_fft_size")
+                .selected_text(format!("FFT {}", self.settings.fft_size))
+                .show_ui(ui, |cb| {
+                    for size in FFT_SIZES {
+                        cb.selectable_value(&mut self.settings.fft_size, size, size.to_string());
+                    }
+                });
+            egui::ComboBox::from_id_source("spectrum_window")
+                .selected_text(self.settings.window.label())
+                .show_ui(ui, |cb| {
+                    for window in WindowFunction::ALL {
+                        cb.selectable_value(&mut self.settings.window, window, window.label());
+                    }
+                });
+            ui.add(egui::Slider::new(&mut self.settings.averaging, 0.0..=0.95).text("Avg"));
+        });
+        ui.horizontal(|ui| {
+            if ui.checkbox(&mut self.settings.peak_hold, "Peak hold").changed() {
+                self.reset_peaks();
+            }
+            if ui.button("Reset peaks").clicked() {
+                self.reset_peaks();
+            }
+            ui.checkbox(&mut self.settings.show_input, "Show input");
+        });
+
+        let (response, painter, rect) = plot::allocate(ui, 220.0);
+        if self.sample_rate == 0 {
+            return;
+        }
+
+        let sample_rate = self.sample_rate as f32;
+        plot::draw_log_freq_grid(&painter, rect, sample_rate / 2.0);
+        plot::draw_value_grid(&painter, rect, DISPLAY_RANGE.0, DISPLAY_RANGE.1, 20.0, " dB");
+
+        if self.settings.show_input {
+            plot::draw_spectrum_line(&painter, rect, &self.input_db, sample_rate, DISPLAY_RANGE, plot::INPUT_COLOR);
+        }
+        if self.settings.peak_hold {
+            plot::draw_spectrum_line(&painter, rect, &self.peak_db, sample_rate, DISPLAY_RANGE, plot::PEAK_COLOR);
+        }
+        plot::draw_spectrum_line(&painter, rect, &self.output_db, sample_rate, DISPLAY_RANGE, plot::OUTPUT_COLOR);
+
+        if let Some(pos) = response.hover_pos() {
+            let freq = plot::x_to_freq(pos.x, sample_rate / 2.0, rect);
+            painter.text(
+                rect.right_top() + egui::vec2(-4.0, 4.0),
+                egui::Align2::RIGHT_TOP,
+                format!("{:.0} Hz", freq),
+                egui::FontId::proportional(10.0),
+                plot::LABEL_COLOR,
+            );
+        }
+    }
+}
@@ -0,0 +1,273 @@
+// src/analysis/stereo.rs
+
+use eframe::egui::{self, Align2, Color32, FontId, Pos2, Rect, Stroke};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use super::plot;
+use super::tap::SampleTap;
+use super::Monitoring;
+
+/// Frames per update the readings are taken over, about 85 ms at 48 kHz.
+const WINDOW: usize = 4096;
+
+/// Most points plotted per trace.
+const MAX_POINTS: usize = 2048;
+
+/// Smoothing of the correlation and balance readouts per update.
+const READOUT_SMOOTHING: f32 = 0.8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ScopeMode {
+    /// Mid up, side across: mono is a vertical line, out of phase horizontal.
+    Goniometer,
+    /// Left across, right up.
+    Lissajous,
+}
+
+impl ScopeMode {
+    pub const ALL: [ScopeMode; 2] = [ScopeMode::Goniometer, ScopeMode::Lissajous];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ScopeMode::Goniometer => "Goniometer",
+            ScopeMode::Lissajous => "Lissajous",
+        }
+    }
+}
+
+/// Correlation, balance and width of one window of left/right audio.
+#[derive(Clone, Copy, Default)]
+pub struct StereoReadings {
+    /// Phase correlation, -1 (out of phase) to +1 (mono).
+    pub correlation: f32,
+    /// Left minus right level in dB.
+    pub balance_db: f32,
+    /// Side minus mid level in dB; very negative for mono.
+    pub width_db: f32,
+}
+
+impl StereoReadings {
+    pub fn measure(left: &[f32], right: &[f32]) -> Self {
+        let (mut ll, mut rr, mut lr) = (0.0f64, 0.0f64, 0.0f64);
+        for (l, r) in left.iter().zip(right) {
+            let (l, r) = (*l as f64, *r as f64);
+            ll += l * l;
+            rr += r * r;
+            lr += l * r;
+        }
+        let tiny = 1e-20;
+        let correlation = if ll > tiny && rr > tiny { lr / (ll * rr).sqrt() } else { 0.0 };
+        // Mid and side energies follow from the same sums
+        let mid = (ll + rr + 2.0 * lr) / 2.0;
+        let side = (ll + rr - 2.0 * lr) / 2.0;
+        let ratio_db = |a: f64, b: f64| (10.0 * ((a + tiny) / (b + tiny)).log10()).clamp(-60.0, 60.0) as f32;
+        Self {
+            correlation: correlation as f32,
+            balance_db: ratio_db(ll, rr),
+            width_db: ratio_db(side.max(0.0), mid.max(0.0)),
+        }
+    }
+}
+
+struct StereoTrace {
+    left: Vec<f32>,
+    right: Vec<f32>,
+    readings: StereoReadings,
+    valid: bool,
+}
+
+impl StereoTrace {
+    fn new() -> Self {
+        Self {
+            left: vec![0.0; WINDOW],
+            right: vec![0.0; WINDOW],
+            readings: StereoReadings::default(),
+            valid: false,
+        }
+    }
+
+    fn update(&mut self, tap: &SampleTap) {
+        // One read, so left and right end on the same frame
+        self.valid = tap.read_channels(&[0, 1], &mut [&mut self.left, &mut self.right]).is_some();
+        if !self.valid {
+            return;
+        }
+        let new = StereoReadings::measure(&self.left, &self.right);
+        let smooth = |old: f32, new: f32| READOUT_SMOOTHING * old + (1.0 - READOUT_SMOOTHING) * new;
+        self.readings = StereoReadings {
+            correlation: smooth(self.readings.correlation, new.correlation),
+            balance_db: smooth(self.readings.balance_db, new.balance_db),
+            width_db: smooth(self.readings.width_db, new.width_db),
+        };
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StereoSettings {
+    pub mode: ScopeMode,
+    pub zoom: f32,
+    pub show_input: bool,
+}
+
+impl Default for StereoSettings {
+    fn default() -> Self {
+        Self {
+            mode: ScopeMode::Goniometer,
+            zoom: 1.0,
+            show_input: false,
+        }
+    }
+}
+
+/// Vectorscope with correlation, balance and width readouts for the first
+/// two channels of the output, and optionally the input.
+pub struct StereoAnalyzer {
+    pub settings: StereoSettings,
+    output: StereoTrace,
+    input: StereoTrace,
+}
+
+impl StereoAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            settings: StereoSettings::default(),
+            output: StereoTrace::new(),
+            input: StereoTrace::new(),
+        }
+    }
+
+    pub fn update(&mut self, monitoring: &Monitoring) {
+        self.output.update(&monitoring.output_tap);
+        if self.settings.show_input {
+            self.input.update(&monitoring.input_tap);
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for mode in ScopeMode::ALL {
+                ui.selectable_value(&mut self.settings.mode, mode, mode.label());
+            }
+            ui.add(egui::Slider::new(&mut self.settings.zoom, 1.0..=8.0).logarithmic(true).text("Zoom"));
+            ui.checkbox(&mut self.settings.show_input, "Show input");
+        });
+
+        if !self.output.valid {
+            ui.label("Needs a stereo signal.");
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            let size = 200.0;
+            let (response, painter) = ui.allocate_painter(egui::vec2(size, size), egui::Sense::hover());
+            let rect = response.rect;
+            painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+            self.draw_axes(&painter, rect);
+            if self.settings.show_input && self.input.valid {
+                self.draw_trace(&painter, rect, &self.input, plot::INPUT_COLOR);
+            }
+            self.draw_trace(&painter, rect, &self.output, plot::OUTPUT_COLOR);
+
+            ui.vertical(|ui| {
+                let traces: &[(&str, &StereoTrace)] = if self.settings.show_input && self.input.valid {
+                    &[("Out", &self.output), ("In", &self.input)]
+                } else {
+                    &[("Out", &self.output)]
+                };
+                for (label, trace) in traces {
+                    let readings = trace.readings;
+                    ui.label(format!("{} correlation {:+.2}", label, readings.correlation));
+                    show_correlation_bar(ui, readings.correlation);
+                    let side = if readings.balance_db.abs() < 0.05 {
+                        "centre".to_string()
+                    } else if readings.balance_db > 0.0 {
+                        format!("{:.1} dB left", readings.balance_db)
+                    } else {
+                        format!("{:.1} dB right", -readings.balance_db)
+                    };
+                    ui.label(format!("{} balance {}", label, side));
+                    ui.label(format!("{} side/mid {:.1} dB", label, readings.width_db));
+                    ui.add_space(6.0);
+                }
+            });
+        });
+    }
+
+    fn draw_axes(&self, painter: &egui::Painter, rect: Rect) {
+        let stroke = Stroke::new(0.5, plot::GRID_COLOR);
+        let c = rect.center();
+        let horizontal = [Pos2::new(rect.left(), c.y), Pos2::new(rect.right(), c.y)];
+        let vertical = [Pos2::new(c.x, rect.top()), Pos2::new(c.x, rect.bottom())];
+        let (lines, labels): (Vec<[Pos2; 2]>, Vec<(&str, Pos2)>) = match self.settings.mode {
+            ScopeMode::Goniometer => (
+                vec![
+                    horizontal,
+                    vertical,
+                    [rect.left_top(), rect.right_bottom()],
+                    [rect.right_top(), rect.left_bottom()],
+                ],
+                vec![
+                    ("M", Pos2::new(c.x + 8.0, rect.top() + 8.0)),
+                    ("S", Pos2::new(rect.right() - 8.0, c.y - 8.0)),
+                    ("L", Pos2::new(rect.left() + 8.0, rect.top() + 18.0)),
+                    ("R", Pos2::new(rect.right() - 8.0, rect.top() + 18.0)),
+                ],
+            ),
+            ScopeMode::Lissajous => (
+                vec![horizontal, vertical, [rect.left_bottom(), rect.right_top()]],
+                vec![
+                    ("R", Pos2::new(c.x + 8.0, rect.top() + 8.0)),
+                    ("L", Pos2::new(rect.right() - 8.0, c.y - 8.0)),
+                ],
+            ),
+        };
+        for line in lines {
+            painter.line_segment(line, stroke);
+        }
+        for (label, pos) in labels {
+            painter.text(pos, Align2::CENTER_CENTER, label, FontId::proportional(9.0), plot::LABEL_COLOR);
+        }
+    }
+
+    fn draw_trace(&self, painter: &egui::Painter, rect: Rect, trace: &StereoTrace, color: Color32) {
+        let half = rect.width() / 2.0 * self.settings.zoom;
+        let center = rect.center();
+        let step = (trace.left.len() / MAX_POINTS).max(1);
+        let color = color.gamma_multiply(0.6);
+        for (l, r) in trace.left.iter().zip(&trace.right).step_by(step) {
+            let (x, y) = match self.settings.mode {
+                ScopeMode::Goniometer => ((r - l) * FRAC_1_SQRT_2, (l + r) * FRAC_1_SQRT_2),
+                ScopeMode::Lissajous => (*l, *r),
+            };
+            let pos = Pos2::new(center.x + x * half, center.y - y * half);
+            if rect.contains(pos) {
+                painter.rect_filled(Rect::from_center_size(pos, egui::vec2(1.5, 1.5)), 0.0, color);
+            }
+        }
+    }
+}
+
+/// -1 to +1 bar, filled from the centre, red when negative.
+fn show_correlation_bar(ui: &mut egui::Ui, correlation: f32) {
+    let (response, painter) = ui.allocate_painter(egui::vec2(140.0, 8.0), egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 1.0, ui.visuals().extreme_bg_color);
+    let to_x = |c: f32| rect.center().x + c.clamp(-1.0, 1.0) * rect.width() / 2.0;
+    let (from, to) = (to_x(0.0), to_x(correlation));
+    let color = if correlation < 0.0 {
+        Color32::from_rgb(230, 80, 80)
+    } else {
+        Color32::from_rgb(90, 200, 255)
+    };
+    painter.rect_filled(
+        Rect::from_min_max(Pos2::new(from.min(to), rect.top()), Pos2::new(from.max(to), rect.bottom())),
+        0.0,
+        color,
+    );
+    painter.line_segment(
+        [Pos2::new(from, rect.top()), Pos2::new(from, rect.bottom())],
+        Stroke::new(1.0, Color32::from_gray(120)),
+    );
+}
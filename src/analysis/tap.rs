@@ -0,0 +1,201 @@
+// src/analysis/tap.rs
+
+use parking_lot::{Mutex, MutexGuard};
+
+/// Frames of history kept per channel. Enough for the largest FFT size and
+/// a few seconds of scope at 48 kHz.
+pub const TAP_CAPACITY: usize = 1 << 17;
+
+/// A ring buffer the audio thread copies blocks into so the GUI can look at
+/// recent audio. Writes use `try_lock` and drop the block rather than wait for
+/// a reader.
+pub struct SampleTap {
+    inner: Mutex<TapBuffer>,
+}
+
+struct TapBuffer {
+    channels: Vec<Vec<f32>>,
+    write_pos: usize,
+    total_frames: u64,
+    sample_rate: u32,
+}
+
+/// A tap locked for writing, so several taps can be written together or
+/// not at all (see `Monitoring::push_taps`).
+pub struct TapWriter<'a>(MutexGuard<'a, TapBuffer>);
+
+impl TapWriter<'_> {
+    /// Appends the first `frames` frames of each planar channel buffer.
+    pub fn push(&mut self, buffers: &[Vec<f32>], frames: usize) {
+        let inner = &mut *self.0;
+        if inner.channels.len() != buffers.len() {
+            return;
+        }
+        let start = inner.write_pos;
+        for (ring, buffer) in inner.channels.iter_mut().zip(buffers) {
+            for (i, sample) in buffer[..frames].iter().enumerate() {
+                ring[(start + i) % TAP_CAPACITY] = *sample;
+            }
+        }
+        inner.write_pos = (start + frames) % TAP_CAPACITY;
+        inner.total_frames += frames as u64;
+    }
+
+    /// Appends `frames` frames of interleaved audio.
+    pub fn push_interleaved(&mut self, block: &[f32], frames: usize) {
+        let inner = &mut *self.0;
+        let channels = inner.channels.len();
+        if channels == 0 || block.len() < frames * channels {
+            return;
+        }
+        let start = inner.write_pos;
+        for (ch, ring) in inner.channels.iter_mut().enumerate() {
+            for (i, sample) in block[..frames * channels].iter().skip(ch).step_by(channels).enumerate() {
+                ring[(start + i) % TAP_CAPACITY] = *sample;
+            }
+        }
+        inner.write_pos = (start + frames) % TAP_CAPACITY;
+        inner.total_frames += frames as u64;
+    }
+}
+
+impl SampleTap {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(TapBuffer {
+                channels: Vec::new(),
+                write_pos: 0,
+                total_frames: 0,
+                sample_rate: 0,
+            }),
+        }
+    }
+
+    /// Allocates the ring for `channels` channels and clears any history.
+    pub fn prepare(&self, sample_rate: u32, channels: usize) {
+        let mut inner = self.inner.lock();
+        inner.channels = vec![vec![0.0; TAP_CAPACITY]; channels];
+        inner.write_pos = 0;
+        inner.total_frames = 0;
+        inner.sample_rate = sample_rate;
+    }
+
+    /// Locks the tap for writing, or `None` if a reader holds it.
+    pub fn try_writer(&self) -> Option<TapWriter<'_>> {
+        self.inner.try_lock().map(TapWriter)
+    }
+
+    /// Fills `out` with the most recent `out.len()` frames mixed down to mono.
+    /// Returns the sample rate, or `None` if not enough audio has been seen yet.
+    pub fn read_mono(&self, out: &mut [f32]) -> Option<u32> {
+        Self::read_before(&self.inner.lock(), None, out, 0)
+    }
+
+    /// Like `read_mono`, but the frames read end at frame `end` counted from
+    /// the last `prepare`, so taps written together can be read in step.
+    pub fn read_mono_ending_at(&self, out: &mut [f32], end: u64) -> Option<u32> {
+        self.read_ending_at(None, out, end)
+    }
+
+    /// Like `read_mono_ending_at` for one channel. Also `None` if the channel
+    /// doesn't exist.
+    pub fn read_channel_ending_at(&self, channel: usize, out: &mut [f32], end: u64) -> Option<u32> {
+        self.read_ending_at(Some(channel), out, end)
+    }
+
+    fn read_ending_at(&self, channel: Option<usize>, out: &mut [f32], end: u64) -> Option<u32> {
+        let inner = self.inner.lock();
+        let skip = inner.total_frames.checked_sub(end)?;
+        Self::read_before(&inner, channel, out, usize::try_from(skip).ok()?)
+    }
+
+    /// Frames ending `skip` frames before the newest, of `channel` or of the
+    /// mono mix when that's `None`.
+    fn read_before(inner: &TapBuffer, channel: Option<usize>, out: &mut [f32], skip: usize) -> Option<u32> {
+        let frames = out.len();
+        let span = frames.checked_add(skip)?;
+        if inner.channels.is_empty() || span > TAP_CAPACITY || inner.total_frames < span as u64 {
+            return None;
+        }
+        let ring = match channel {
+            Some(channel) => Some(inner.channels.get(channel)?),
+            None => None,
+        };
+
+        let scale = 1.0 / inner.channels.len() as f32;
+        let start = (inner.write_pos + TAP_CAPACITY - span) % TAP_CAPACITY;
+        for (i, sample) in out.iter_mut().enumerate() {
+            let index = (start + i) % TAP_CAPACITY;
+            *sample = match ring {
+                Some(ring) => ring[index],
+                None => inner.channels.iter().map(|ring| ring[index]).sum::<f32>() * scale,
+            };
+        }
+        Some(inner.sample_rate)
+    }
+
+    /// Fills each `out` buffer with the most recent frames of the matching
+    /// channel, copied under one lock so they all end on the same frame. Each
+    /// buffer may have its own length. Returns the sample rate, or `None` if a
+    /// channel doesn't exist or not enough audio has been seen yet.
+    pub fn read_channels(&self, channels: &[usize], out: &mut [&mut [f32]]) -> Option<u32> {
+        let inner = self.inner.lock();
+        let rings = channels
+            .iter()
+            .map(|&channel| inner.channels.get(channel))
+            .collect::<Option<Vec<_>>>()?;
+        let longest = out.iter().map(|buffer| buffer.len()).max().unwrap_or(0);
+        if rings.len() != out.len() || longest > TAP_CAPACITY || inner.total_frames < longest as u64 {
+            return None;
+        }
+
+        for (ring, buffer) in rings.iter().zip(out.iter_mut()) {
+            let start = (inner.write_pos + TAP_CAPACITY - buffer.len()) % TAP_CAPACITY;
+            for (i, sample) in buffer.iter_mut().enumerate() {
+                *sample = ring[(start + i) % TAP_CAPACITY];
+            }
+        }
+        Some(inner.sample_rate)
+    }
+
+    /// Frames pushed since the last `prepare`.
+    pub fn total_frames(&self) -> u64 {
+        self.inner.lock().total_frames
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.inner.lock().sample_rate
+    }
+
+    pub fn channels(&self) -> usize {
+        self.inner.lock().channels.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_end_at_an_absolute_frame() {
+        let tap = SampleTap::new();
+        tap.prepare(48000, 2);
+        // Frame n is n on the left and 3n on the right
+        let block: Vec<f32> = (0..100).flat_map(|n| [n as f32, 3.0 * n as f32]).collect();
+        tap.try_writer().unwrap().push_interleaved(&block, 100);
+
+        let mut out = [0.0; 4];
+        assert_eq!(tap.read_mono_ending_at(&mut out, 60), Some(48000));
+        assert_eq!(out, [112.0, 114.0, 116.0, 118.0]);
+        let mut right = [0.0; 2];
+        assert!(tap.read_channels(&[1, 0], &mut [&mut right, &mut out]).is_some());
+        assert_eq!(right, [294.0, 297.0]);
+        assert_eq!(out, [96.0, 97.0, 98.0, 99.0]);
+        assert!(tap.read_channels(&[0, 2], &mut [&mut right, &mut out]).is_none());
+        assert_eq!(tap.read_channel_ending_at(1, &mut right, 60), Some(48000));
+        assert_eq!(right, [174.0, 177.0]);
+        assert!(tap.read_channel_ending_at(2, &mut right, 60).is_none());
+        assert!(tap.read_mono_ending_at(&mut out, 101).is_none());
+        assert!(tap.read_mono_ending_at(&mut out, 3).is_none());
+    }
+}
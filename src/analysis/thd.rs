@@ -0,0 +1,440 @@
+// src/analysis/thd.rs
+
+use eframe::egui::{self, Align2, FontId, Painter, Pos2, Rect, Stroke};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::plot;
+use super::spectrum::{amplitude_to_db, SpectrumCalculator, WindowFunction};
+use super::{chain_signature, show_target_selector, target_processor};
+use crate::chain::ChainSlot;
+use crate::dsp_module::AudioProcessor;
+use crate::render::process_signal;
+
+const MEASURE_SAMPLE_RATE: u32 = 48000;
+
+const MEASURE_CHANNELS: usize = 2;
+
+const MEASURE_BLOCK: usize = 1024;
+
+/// Analysis length. The test frequency is snapped to a bin of this FFT so
+/// every harmonic lands exactly on a bin and no window is needed.
+const FFT_SIZE: usize = 16384;
+
+/// Harmonics reported individually, starting at the 2nd.
+const MAX_HARMONIC: usize = 10;
+
+/// Input levels stepped through for the transfer curve, in dBFS.
+const SWEEP_RANGE: (f32, f32) = (-60.0, 6.0);
+const SWEEP_STEP_DB: f32 = 2.0;
+
+/// Band THD+N is integrated over.
+const NOISE_BAND: (f32, f32) = (20.0, 20000.0);
+
+const SPECTRUM_RANGE: (f32, f32) = (-160.0, 0.0);
+
+/// How long the settings have to stay unchanged before measuring again.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Distortion figures for one sine at one level.
+pub struct Distortion {
+    /// Test frequency after snapping to an FFT bin.
+    pub frequency: f32,
+    pub input_db: f32,
+    /// Output level of the fundamental in dBFS.
+    pub fundamental_db: f32,
+    /// Level of the 2nd to 10th harmonic in dB relative to the fundamental.
+    /// Harmonics above Nyquist are left out.
+    pub harmonics_dbc: Vec<f32>,
+    pub thd_percent: f32,
+    pub thd_n_percent: f32,
+    /// Output spectrum in dBFS, DC to Nyquist.
+    pub spectrum_db: Vec<f32>,
+}
+
+fn percent_to_db(percent: f32) -> f32 {
+    amplitude_to_db(percent / 100.0)
+}
+
+impl Distortion {
+    pub fn thd_db(&self) -> f32 {
+        percent_to_db(self.thd_percent)
+    }
+
+    pub fn thd_n_db(&self) -> f32 {
+        percent_to_db(self.thd_n_percent)
+    }
+
+    /// Drives `processor` with a sine of `frequency` Hz at `level_db` dBFS.
+    /// The first FFT length of output is thrown away so attack and release
+    /// stages have settled before the analysis.
+    pub fn measure(
+        processor: &mut dyn AudioProcessor,
+        calculator: &mut SpectrumCalculator,
+        frequency: f32,
+        level_db: f32,
+    ) -> Self {
+        let sample_rate = MEASURE_SAMPLE_RATE as f32;
+        let bin_hz = sample_rate / FFT_SIZE as f32;
+        let bin = ((frequency / bin_hz).round() as usize).clamp(1, FFT_SIZE / 2 - 1);
+        let frequency = bin as f32 * bin_hz;
+        let amplitude = 10f32.powf(level_db / 20.0);
+
+        // The phase index wraps so the f32 argument stays small and exact
+        let input: Vec<f32> = (0..FFT_SIZE * 2)
+            .map(|n| amplitude * (2.0 * PI * ((bin * n) % FFT_SIZE) as f32 / FFT_SIZE as f32).sin())
+            .collect();
+        processor.reset();
+        let output = process_signal(processor, &input, MEASURE_CHANNELS, MEASURE_SAMPLE_RATE, MEASURE_BLOCK);
+
+        // Powers come from the raw magnitudes: the dB spectrum is floored
+        // per bin, which summed over the band would swamp a clean chain
+        let mut magnitudes = Vec::new();
+        calculator.magnitudes(&output[FFT_SIZE..], WindowFunction::Rectangular, &mut magnitudes);
+        let power = |bin: usize| (magnitudes[bin] as f64).powi(2);
+
+        let fundamental = power(bin).max(f64::MIN_POSITIVE);
+        let harmonics: Vec<f64> = (2..=MAX_HARMONIC)
+            .map(|h| h * bin)
+            .take_while(|&b| b < magnitudes.len())
+            .map(power)
+            .collect();
+        let harmonic_power: f64 = harmonics.iter().sum();
+
+        let band = ((NOISE_BAND.0 / bin_hz).ceil() as usize).max(1)..((NOISE_BAND.1 / bin_hz) as usize).min(magnitudes.len());
+        let residual_power: f64 = band.filter(|&b| b != bin).map(power).sum();
+
+        let ratio = |p: f64| (100.0 * (p / fundamental).sqrt()) as f32;
+        Self {
+            frequency,
+            input_db: level_db,
+            fundamental_db: amplitude_to_db(magnitudes[bin]),
+            harmonics_dbc: harmonics
+                .iter()
+                .map(|p| (10.0 * (p / fundamental).max(1e-16).log10()) as f32)
+                .collect(),
+            thd_percent: ratio(harmonic_power),
+            thd_n_percent: ratio(residual_power),
+            spectrum_db: magnitudes.iter().map(|m| amplitude_to_db(*m)).collect(),
+        }
+    }
+}
+
+/// Output level and THD+N at one input level of the sweep.
+pub struct TransferPoint {
+    pub input_db: f32,
+    pub output_db: f32,
+    pub thd_n_db: f32,
+}
+
+/// Steps the input level through `SWEEP_RANGE` at `frequency`.
+pub fn measure_transfer(
+    processor: &mut dyn AudioProcessor,
+    calculator: &mut SpectrumCalculator,
+    frequency: f32,
+) -> Vec<TransferPoint> {
+    let steps = ((SWEEP_RANGE.1 - SWEEP_RANGE.0) / SWEEP_STEP_DB) as usize;
+    (0..=steps)
+        .map(|i| {
+            let level = SWEEP_RANGE.0 + i as f32 * SWEEP_STEP_DB;
+            let distortion = Distortion::measure(processor, calculator, frequency, level);
+            TransferPoint {
+                input_db: level,
+                output_db: distortion.fundamental_db,
+                thd_n_db: distortion.thd_n_db(),
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum DistortionView {
+    Spectrum,
+    Transfer,
+    ThdNVsLevel,
+}
+
+impl DistortionView {
+    const ALL: [DistortionView; 3] = [DistortionView::Spectrum, DistortionView::Transfer, DistortionView::ThdNVsLevel];
+
+    fn label(&self) -> &'static str {
+        match self {
+            DistortionView::Spectrum => "Spectrum",
+            DistortionView::Transfer => "Transfer curve",
+            DistortionView::ThdNVsLevel => "THD+N vs level",
+        }
+    }
+}
+
+/// A finished measurement. The transfer curve is only redone when something
+/// other than the level changed.
+type DistortionJob = JoinHandle<(Distortion, Option<Vec<TransferPoint>>)>;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DistortionSettings {
+    /// Test tone frequency in Hz.
+    pub frequency: f32,
+    /// Test tone level in dBFS.
+    pub level_db: f32,
+    /// Index of the slot to measure, or `None` for the whole chain.
+    pub target: Option<usize>,
+    pub view: DistortionView,
+}
+
+impl Default for DistortionSettings {
+    fn default() -> Self {
+        Self {
+            frequency: 1000.0,
+            level_db: -6.0,
+            target: None,
+            view: DistortionView::Spectrum,
+        }
+    }
+}
+
+/// THD, THD+N and linearity panel. Like the frequency response it measures
+/// again on a worker thread once the chain or its parameters have changed
+/// and settled.
+pub struct DistortionAnalyzer {
+    pub settings: DistortionSettings,
+    result: Option<Distortion>,
+    transfer: Vec<TransferPoint>,
+    /// What the last measurement and transfer curve were started with.
+    measured: Vec<u64>,
+    transfer_measured: Vec<u64>,
+    /// A signature that differs from `measured`, and when it was first seen.
+    pending: Option<(Vec<u64>, Instant)>,
+    job: Option<DistortionJob>,
+}
+
+impl DistortionAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            settings: DistortionSettings::default(),
+            result: None,
+            transfer: Vec::new(),
+            measured: Vec::new(),
+            transfer_measured: Vec::new(),
+            pending: None,
+            job: None,
+        }
+    }
+
+    /// Everything the transfer curve depends on; it sweeps the level itself.
+    fn transfer_signature(&self, slots: &[ChainSlot]) -> Vec<u64> {
+        let settings = &self.settings;
+        let mut signature = vec![settings.frequency.to_bits() as u64, settings.target.map_or(u64::MAX, |t| t as u64)];
+        signature.extend(chain_signature(slots));
+        signature
+    }
+
+    fn signature(&self, slots: &[ChainSlot]) -> Vec<u64> {
+        let mut signature = self.transfer_signature(slots);
+        signature.push(self.settings.level_db.to_bits() as u64);
+        signature
+    }
+
+    /// Collects a finished measurement and starts a new one once the
+    /// settings have stayed on an unmeasured signature for `DEBOUNCE`.
+    fn update_measurement(&mut self, ctx: &egui::Context, slots: &[ChainSlot]) {
+        if self.job.as_ref().is_some_and(|job| job.is_finished()) {
+            if let Some(Ok((result, transfer))) = self.job.take().map(JoinHandle::join) {
+                self.result = Some(result);
+                if let Some(transfer) = transfer {
+                    self.transfer = transfer;
+                }
+            }
+        }
+
+        let signature = self.signature(slots);
+        if signature == self.measured {
+            self.pending = None;
+        } else if self.pending.as_ref().is_none_or(|(pending, _)| *pending != signature) {
+            self.pending = Some((signature, Instant::now()));
+        }
+
+        let settled = self.pending.as_ref().is_some_and(|(_, since)| since.elapsed() >= DEBOUNCE);
+        if settled && self.job.is_none() {
+            if let Some((signature, _)) = self.pending.take() {
+                let transfer_signature = self.transfer_signature(slots);
+                let with_transfer = transfer_signature != self.transfer_measured;
+                let mut processor = target_processor(slots, self.settings.target);
+                let (frequency, level_db) = (self.settings.frequency, self.settings.level_db);
+                self.job = Some(thread::spawn(move || {
+                    let mut calculator = SpectrumCalculator::new();
+                    let result = Distortion::measure(&mut processor, &mut calculator, frequency, level_db);
+                    let transfer = with_transfer.then(|| measure_transfer(&mut processor, &mut calculator, frequency));
+                    (result, transfer)
+                }));
+                self.measured = signature;
+                self.transfer_measured = transfer_signature;
+            }
+        }
+
+        if self.job.is_some() || self.pending.is_some() {
+            ctx.request_repaint_after(Duration::from_millis(50));
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, slots: &[ChainSlot]) {
+        ui.horizontal(|ui| {
+            show_target_selector(ui, "thd_target", &mut self.settings.target, slots);
+            for view in DistortionView::ALL {
+                ui.selectable_value(&mut self.settings.view, view, view.label());
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::Slider::new(&mut self.settings.frequency, 20.0..=20000.0)
+                    .logarithmic(true)
+                    .text("Frequency")
+                    .suffix(" Hz"),
+            );
+            ui.add(
+                egui::Slider::new(&mut self.settings.level_db, SWEEP_RANGE.0..=SWEEP_RANGE.1)
+                    .text("Level")
+                    .suffix(" dBFS"),
+            );
+        });
+
+        self.update_measurement(ui.ctx(), slots);
+        if self.job.is_some() || self.pending.is_some() {
+            ui.label("Measuring...");
+        }
+        let Some(ref result) = self.result else {
+            return;
+        };
+
+        ui.label(format!(
+            "{:.1} Hz at {:.1} dBFS: output {:.2} dBFS, THD {:.4} % ({:.1} dB), THD+N {:.4} % ({:.1} dB)",
+            result.frequency,
+            result.input_db,
+            result.fundamental_db,
+            result.thd_percent,
+            result.thd_db(),
+            result.thd_n_percent,
+            result.thd_n_db(),
+        ));
+        ui.horizontal_wrapped(|ui| {
+            for (i, level) in result.harmonics_dbc.iter().enumerate() {
+                ui.label(format!("H{} {:.1} dBc", i + 2, level));
+            }
+        });
+
+        let (_, painter, rect) = plot::allocate(ui, 220.0);
+        let sample_rate = MEASURE_SAMPLE_RATE as f32;
+        match self.settings.view {
+            DistortionView::Spectrum => {
+                plot::draw_log_freq_grid(&painter, rect, sample_rate / 2.0);
+                plot::draw_value_grid(&painter, rect, SPECTRUM_RANGE.0, SPECTRUM_RANGE.1, 20.0, " dB");
+                plot::draw_spectrum_line(
+                    &painter,
+                    rect,
+                    &result.spectrum_db,
+                    sample_rate,
+                    SPECTRUM_RANGE,
+                    plot::OUTPUT_COLOR,
+                );
+            }
+            DistortionView::Transfer => {
+                let range = (SWEEP_RANGE.0, SWEEP_RANGE.1 + 12.0);
+                plot::draw_value_grid(&painter, rect, range.0, range.1, 12.0, " dB");
+                // Unity gain for reference
+                draw_level_line(&painter, rect, range, [(SWEEP_RANGE.0, SWEEP_RANGE.0), (SWEEP_RANGE.1, SWEEP_RANGE.1)], plot::GRID_COLOR);
+                let points: Vec<(f32, f32)> = self.transfer.iter().map(|p| (p.input_db, p.output_db)).collect();
+                draw_level_line(&painter, rect, range, points, plot::OUTPUT_COLOR);
+                draw_level_axis(&painter, rect);
+            }
+            DistortionView::ThdNVsLevel => {
+                let range = (-140.0, 0.0);
+                plot::draw_value_grid(&painter, rect, range.0, range.1, 20.0, " dB");
+                let points: Vec<(f32, f32)> = self.transfer.iter().map(|p| (p.input_db, p.thd_n_db)).collect();
+                draw_level_line(&painter, rect, range, points, plot::PEAK_COLOR);
+                draw_level_axis(&painter, rect);
+            }
+        }
+    }
+}
+
+fn level_to_x(level: f32, rect: Rect) -> f32 {
+    let t = (level - SWEEP_RANGE.0) / (SWEEP_RANGE.1 - SWEEP_RANGE.0);
+    rect.left() + t.clamp(0.0, 1.0) * rect.width()
+}
+
+/// Line over an input-level x axis.
+fn draw_level_line(
+    painter: &Painter,
+    rect: Rect,
+    range: (f32, f32),
+    points: impl IntoIterator<Item = (f32, f32)>,
+    color: egui::Color32,
+) {
+    let points: Vec<Pos2> = points
+        .into_iter()
+        .map(|(x, y)| Pos2::new(level_to_x(x, rect), plot::value_to_y(y, range.0, range.1, rect)))
+        .collect();
+    if points.len() > 1 {
+        painter.add(egui::Shape::line(points, Stroke::new(1.0, color)));
+    }
+}
+
+/// Input level labels along the bottom edge.
+fn draw_level_axis(painter: &Painter, rect: Rect) {
+    let mut level = SWEEP_RANGE.0;
+    while level <= SWEEP_RANGE.1 {
+        let x = level_to_x(level, rect);
+        painter.line_segment([Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())], Stroke::new(0.5, plot::GRID_COLOR));
+        painter.text(
+            Pos2::new(x + 2.0, rect.bottom() - 2.0),
+            Align2::LEFT_BOTTOM,
+            format!("{} in", level),
+            FontId::proportional(9.0),
+            plot::LABEL_COLOR,
+        );
+        level += 12.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::ChainProcessor;
+    use crate::process_context::ProcessContext;
+
+    /// `x + k * x^2`: a known amount of 2nd harmonic.
+    struct SquareLaw(f32);
+
+    impl AudioProcessor for SquareLaw {
+        fn process(&mut self, ctx: &mut ProcessContext) {
+            for ch in 0..ctx.num_channels() {
+                for sample in ctx.channel_mut(ch) {
+                    *sample += self.0 * *sample * *sample;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn identity_has_no_floor_at_low_level() {
+        let mut calculator = SpectrumCalculator::new();
+        let result = Distortion::measure(&mut ChainProcessor::new(), &mut calculator, 1000.0, -60.0);
+        assert!((result.fundamental_db + 60.0).abs() < 0.01, "fundamental {}", result.fundamental_db);
+        assert!(result.thd_n_percent < 1e-3, "THD+N {} %", result.thd_n_percent);
+    }
+
+    #[test]
+    fn second_harmonic_is_measured() {
+        // A sin(wt) squared is A^2/2 (1 - cos(2wt)), so H2 is k * A / 2 of the fundamental
+        let (k, level_db) = (0.1, -6.0206);
+        let amplitude = 10f32.powf(level_db / 20.0);
+        let mut calculator = SpectrumCalculator::new();
+        let result = Distortion::measure(&mut SquareLaw(k), &mut calculator, 1000.0, level_db);
+        let expected = 100.0 * k * amplitude / 2.0;
+        assert!((result.thd_percent - expected).abs() < 0.01, "THD {} %, expected {}", result.thd_percent, expected);
+        assert!((result.harmonics_dbc[0] - 20.0 * (expected / 100.0).log10()).abs() < 0.05);
+        assert!(result.harmonics_dbc[1] < -100.0, "H3 {}", result.harmonics_dbc[1]);
+    }
+}
@@ -4,27 +4,143 @@ use eframe::{egui, App, NativeOptions};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 
-use crate::dsp::DspProcessor;
-use std::fs;
+use crate::ab::{AbProcessor, AbSwitch, AbxPanel};
+use crate::analysis::loudness::show_loudness;
+use crate::analysis::null_test::NullTestPanel;
+use crate::analysis::response::ResponseAnalyzer;
+use crate::analysis::scope::{show_waveform_overview, Oscilloscope};
+use crate::analysis::spectrogram::Spectrogram;
+use crate::analysis::spectrum::SpectrumAnalyzer;
+use crate::analysis::stereo::StereoAnalyzer;
+use crate::analysis::thd::DistortionAnalyzer;
+use crate::analysis::Monitoring;
+use crate::chain::{ChainEdit, ChainProcessor, ChainSlot, ChainSnapshot};
+use crate::dsp::{DspProcessor, InputSource, ProcessorControl, BLOCK_SIZES};
+use crate::library::Library;
+use crate::error::{show_error_banner, DspError};
+use crate::generators::GeneratorPanel;
+use crate::dsp_module::{AudioProcessor, FnProcessor, ProcessorFactory};
+use crate::process_context::{adapt_legacy_process_fn, ProcessContext};
+use crate::param_cell::ParamCell;
+use crate::presets::{Preset, PresetBrowser, PresetValue};
+use crate::render::{render_to_wav, WavFormat};
+use crate::session::{Session, SessionSlot};
+use crate::transport::{format_time, show_position_bar, Transport};
+use crate::smoothing::Smoothing;
+use std::io;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub enum ParamValue {
     Number(f32),
     Boolean(bool),
+    Integer(i32),
+    /// One of a fixed list of options, shown as a combo box.
+    Choice { options: Arc<[String]>, index: usize },
+}
+
+impl ParamValue {
+    /// A choice of `options` with `index` selected, clamped to the last option.
+    pub fn choice(options: &[&str], index: usize) -> Self {
+        assert!(!options.is_empty(), "a choice needs at least one option");
+        ParamValue::Choice {
+            options: options.iter().map(|o| o.to_string()).collect(),
+            index: index.min(options.len() - 1),
+        }
+    }
+
+    /// The value as a number, booleans as 0.0 or 1.0 and choices as their index.
+    pub fn as_f32(&self) -> f32 {
+        match self {
+            ParamValue::Number(v) => *v,
+            ParamValue::Boolean(v) => *v as u8 as f32,
+            ParamValue::Integer(v) => *v as f32,
+            ParamValue::Choice { index, .. } => *index as f32,
+        }
+    }
+}
+
+/// Unit a numeric parameter is displayed and entered in.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ParamUnit {
+    None,
+    Hz,
+    Db,
+    Ms,
+    /// A 0.0..=1.0 value shown as 0-100%.
+    Percent,
+}
+
+impl ParamUnit {
+    pub fn format(&self, value: f32) -> String {
+        match self {
+            ParamUnit::None => value.to_string(),
+            ParamUnit::Hz if value.abs() >= 1000.0 => format!("{:.2} kHz", value / 1000.0),
+            ParamUnit::Hz => format!("{:.1} Hz", value),
+            ParamUnit::Db => format!("{:.1} dB", value),
+            ParamUnit::Ms if value.abs() >= 1000.0 => format!("{:.2} s", value / 1000.0),
+            ParamUnit::Ms => format!("{:.1} ms", value),
+            ParamUnit::Percent => format!("{:.0}%", value * 100.0),
+        }
+    }
+
+    /// Reads a value typed in this unit, with or without the suffix
+    /// ("1.2k", "1.2 kHz", "250ms", "0.5 s", "50%").
+    pub fn parse(&self, text: &str) -> Option<f32> {
+        let text = text.trim().to_ascii_lowercase();
+        let (number, scale) = match self {
+            ParamUnit::None => (text.as_str(), 1.0),
+            ParamUnit::Hz => {
+                let text = text.trim_end_matches("hz").trim_end();
+                match text.strip_suffix('k') {
+                    Some(number) => (number, 1000.0),
+                    None => (text, 1.0),
+                }
+            }
+            ParamUnit::Db => (text.trim_end_matches("db"), 1.0),
+            ParamUnit::Ms => match text.strip_suffix("ms") {
+                Some(number) => (number, 1.0),
+                None => match text.strip_suffix('s') {
+                    Some(number) => (number, 1000.0),
+                    None => (text.as_str(), 1.0),
+                },
+            },
+            ParamUnit::Percent => (text.trim_end_matches('%'), 0.01),
+        };
+        number.trim().parse::<f32>().ok().map(|v| v * scale)
+    }
+}
+
+/// How a numeric parameter's range is spread along its slider.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ParamScale {
+    Linear,
+    /// Equal slider distance per octave/decade, for frequencies and times.
+    Logarithmic,
+    /// `value = min + (max - min) * t^skew`; above 1 gives more room to the low end.
+    Skewed(f32),
 }
 
 #[derive(Clone)]
 pub struct AudioParam {
     pub name: String,
-    pub value: Arc<Mutex<ParamValue>>,
+    pub value: Arc<ParamCell>,
     pub min: f32,
     pub max: f32,
+    pub smoothing: Smoothing,
+    pub unit: ParamUnit,
+    pub scale: ParamScale,
 }
 
 pub struct AudioAppBuilder {
     params: Vec<AudioParam>,
-    process_fn: Option<Arc<dyn Fn(&mut [i16], &[ParamValue]) + Send + Sync + 'static>>,
+    processor_factory: Option<ProcessorFactory>,
+    factory_presets: Vec<Preset>,
     window_title: String,
+    // Kept for modules built outside this tree; the manager owns the window
+    #[allow(dead_code)]
     native_options: NativeOptions,
 }
 
@@ -32,152 +148,511 @@ impl AudioAppBuilder {
     pub fn new() -> Self {
         Self {
             params: Vec::new(),
-            process_fn: None,
+            processor_factory: None,
+            factory_presets: Vec::new(),
             window_title: "Audio Controller".to_string(),
             native_options: NativeOptions::default(),
         }
     }
 
+    /// Adds a parameter. For `Choice` the range is taken from the options and
+    /// `min`/`max` are ignored.
     pub fn add_param(mut self, name: &str, value: ParamValue, min: f32, max: f32) -> Self {
+        let (min, max) = match &value {
+            ParamValue::Choice { options, .. } => (0.0, options.len().saturating_sub(1) as f32),
+            _ => (min, max),
+        };
         self.params.push(AudioParam {
             name: name.to_string(),
-            value: Arc::new(Mutex::new(value)),
+            value: Arc::new(ParamCell::new(value)),
             min,
             max,
+            smoothing: Smoothing::None,
+            unit: ParamUnit::None,
+            scale: ParamScale::Linear,
+        });
+        self
+    }
+
+    /// Adds a read-only preset shipped with the module. Parameters not listed
+    /// keep their current value when it's loaded.
+    pub fn add_factory_preset(mut self, name: &str, values: &[(&str, ParamValue)]) -> Self {
+        self.factory_presets.push(Preset {
+            name: name.to_string(),
+            module: String::new(),
+            params: values
+                .iter()
+                .map(|(param, value)| (param.to_string(), PresetValue::from(value)))
+                .collect(),
         });
         self
     }
 
+    /// Sets the display unit of the last added parameter.
+    pub fn with_unit(mut self, unit: ParamUnit) -> Self {
+        if let Some(param) = self.params.last_mut() {
+            param.unit = unit;
+        }
+        self
+    }
+
+    /// Sets the slider mapping of the last added parameter.
+    pub fn with_scale(mut self, scale: ParamScale) -> Self {
+        if let Some(param) = self.params.last_mut() {
+            param.scale = scale;
+        }
+        self
+    }
+
+    /// Sets how the last added parameter glides to new values. Modules read
+    /// the result per sample through `ProcessContext::smoothed`.
+    pub fn with_smoothing(mut self, smoothing: Smoothing) -> Self {
+        if let Some(param) = self.params.last_mut() {
+            param.smoothing = smoothing;
+        }
+        self
+    }
+
+    /// Sets an interleaved `i16` process function. Kept for older modules; the
+    /// block is converted to and from `f32` around every call.
+    #[allow(dead_code)] // No module in this tree still uses it
     pub fn set_process_fn<F>(mut self, process_fn: F) -> Self
     where
         F: Fn(&mut [i16], &[ParamValue]) + Send + Sync + 'static,
     {
-        self.process_fn = Some(Arc::new(process_fn));
+        self.processor_factory = Some(adapt_legacy_process_fn(Arc::new(process_fn)));
+        self
+    }
+
+    /// Sets a process function that receives planar `f32` channels together with
+    /// the sample rate, block length and transport position.
+    pub fn set_process_context_fn<F>(mut self, process_fn: F) -> Self
+    where
+        F: Fn(&mut ProcessContext) + Send + Sync + 'static,
+    {
+        self.processor_factory = Some(FnProcessor::factory(Arc::new(process_fn)));
+        self
+    }
+
+    /// Sets a stateful processor. `factory` is called for every playback so each
+    /// run starts from freshly prepared state.
+    pub fn set_processor<F, P>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> P + Send + Sync + 'static,
+        P: AudioProcessor + 'static,
+    {
+        self.processor_factory = Some(Arc::new(move || Box::new(factory()) as Box<dyn AudioProcessor>));
         self
     }
 
+    pub fn params(&self) -> &[AudioParam] {
+        &self.params
+    }
+
     pub fn set_window_title(mut self, title: &str) -> Self {
         self.window_title = title.to_string();
         self
     }
 
+    #[allow(dead_code)] // Part of the module-facing builder API
     pub fn set_native_options(mut self, options: NativeOptions) -> Self {
         self.native_options = options;
         self
     }
 
-    pub fn build(self, cpu_usage: Arc<Mutex<f32>>) -> Result<AudioApp, eframe::Error> {
-        let process_fn = self.process_fn.expect("Process function must be set");
-        let mut audio_app = AudioApp::new(self.params, process_fn, cpu_usage);
+    /// Turns the builder into a chain slot for the module called `module_name`.
+    pub fn into_slot(self, module_name: &str) -> Result<ChainSlot, DspError> {
+        let processor_factory = self.processor_factory.ok_or_else(|| DspError::NoProcessFn {
+            module: module_name.to_string(),
+        })?;
+        let factory_presets = self
+            .factory_presets
+            .into_iter()
+            .map(|preset| Preset { module: module_name.to_string(), ..preset })
+            .collect();
+        Ok(ChainSlot {
+            id: ChainSlot::next_id(),
+            module_name: module_name.to_string(),
+            title: self.window_title,
+            params: self.params,
+            processor_factory,
+            bypass: Arc::new(AtomicBool::new(false)),
+            presets: PresetBrowser::new(module_name, factory_presets),
+        })
+    }
+
+    /// Builds the app around this module. Failing to start the first audio
+    /// file isn't an error here; it shows up in the app's error banner.
+    pub fn build(self, module_name: &str, monitoring: Arc<Monitoring>) -> Result<AudioApp, DspError> {
+        let mut audio_app = AudioApp::new(vec![self.into_slot(module_name)?], monitoring);
 
-        // Automatically load and play the first audio file
-        if let Some(first_file) = audio_app.available_files.first().cloned() {
-            audio_app.selected_file = Some(first_file.clone());
-            audio_app.load_audio(&first_file);
-            println!("Automatically playing the first audio file: {}", first_file);
-        } else {
-            println!("No audio files found in src/assets.");
-        }
+        // Play the first audio file once the library scan has found it
+        audio_app.autoplay = true;
 
         Ok(audio_app)
     }
 }
 
 pub struct AudioApp {
-    params: Vec<AudioParam>,
+    chain: Vec<ChainSlot>, // Modules in processing order
     dsp_processor: Option<DspProcessor>,
     is_playing: Arc<AtomicBool>,
     bypass: Arc<AtomicBool>, // Bypass flag
-    available_files: Vec<String>,
-    selected_file: Option<String>,
-    process_fn: Arc<dyn Fn(&mut [i16], &[ParamValue]) + Send + Sync + 'static>,
+    library: Library,
+    autoplay: bool, // Play the first library file when the scan finishes
+    selected_file: Option<PathBuf>,
+    input: Option<InputSource>, // What's playing, replayed when the chain changes
+    generator: GeneratorPanel,
     available_block_sizes: Vec<usize>,
     selected_block_size: usize,
-    cpu_usage: Arc<Mutex<f32>>,
+    monitoring: Arc<Monitoring>,
+    spectrum: SpectrumAnalyzer,
+    scope: Oscilloscope,
+    spectrogram: Spectrogram,
+    stereo: StereoAnalyzer,
+    response: ResponseAnalyzer,
+    distortion: DistortionAnalyzer,
+    null_test: NullTestPanel,
+    render_path: String,
+    render_format: WavFormat,
+    render_status: Arc<Mutex<String>>,
+    error: Option<DspError>, // Shown in the banner until dismissed
+    transport: Arc<Transport>, // Position and loop region, kept across reloads
+    ab: Arc<AbSwitch>,
+    ab_reference: Option<ChainSnapshot>, // A side when comparing against other settings
+    abx: AbxPanel,
 }
 
 impl AudioApp {
     pub fn new(
-        params: Vec<AudioParam>,
-        process_fn: Arc<dyn Fn(&mut [i16], &[ParamValue]) + Send + Sync + 'static>,
-        cpu_usage: Arc<Mutex<f32>>,
+        chain: Vec<ChainSlot>,
+        monitoring: Arc<Monitoring>,
     ) -> Self {
         let is_playing = Arc::new(AtomicBool::new(false));
         let bypass = Arc::new(AtomicBool::new(false));
 
-        // Scan the src/assets directory for audio files
-        let assets_path = "src/assets";
-        let mut available_files = Vec::new();
-
-        if let Ok(entries) = fs::read_dir(assets_path) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.is_file() {
-                    if let Some(ext) = path.extension() {
-                        if ext == "wav" || ext == "mp3" || ext == "ogg" {
-                            if let Some(file_name) = path.file_name() {
-                                if let Some(name_str) = file_name.to_str() {
-                                    available_files.push(name_str.to_string());
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        available_files.sort_by(|a, b| a.to_lowercase().cmp(&b.to_lowercase()));
-
         // Define available block sizes
-        let available_block_sizes = vec![1024, 2048, 4096, 8192, 16384];
+        let available_block_sizes = BLOCK_SIZES.to_vec();
         let selected_block_size = 4096; // Default block size
 
         AudioApp {
-            params,
+            chain,
             dsp_processor: None,
             is_playing,
             bypass,
-            available_files,
+            library: Library::load(),
+            autoplay: false,
             selected_file: None,
-            process_fn,
+            input: None,
+            generator: GeneratorPanel::new(),
             available_block_sizes,
             selected_block_size,
-            cpu_usage,
+            monitoring,
+            spectrum: SpectrumAnalyzer::new(),
+            scope: Oscilloscope::new(),
+            spectrogram: Spectrogram::new(),
+            stereo: StereoAnalyzer::new(),
+            response: ResponseAnalyzer::new(),
+            distortion: DistortionAnalyzer::new(),
+            null_test: NullTestPanel::new(),
+            render_path: "render.wav".to_string(),
+            render_format: WavFormat::Int24,
+            render_status: Arc::new(Mutex::new(String::new())),
+            error: None,
+            transport: Arc::new(Transport::new()),
+            ab: Arc::new(AbSwitch::new()),
+            ab_reference: None,
+            abx: AbxPanel::new(),
         }
     }
 
-    pub fn load_audio(&mut self, file_name: &str) {
-        if let Some(ref dsp) = self.dsp_processor {
-            dsp.stop();
-        }
-
-        let process_fn = Arc::clone(&self.process_fn);
+    /// Starts playing `input` through the chain from `start_frame`, or from
+    /// the loop start when that's 0 and a loop is set. If the file or the
+    /// output device can't be opened the previous playback carries on.
+    pub fn load_input(&mut self, input: &InputSource, start_frame: u64) -> Result<(), DspError> {
+        let reference = self
+            .ab_reference
+            .as_ref()
+            .map(|snapshot| Box::new(ChainProcessor::from_snapshot(&self.chain, snapshot)) as Box<dyn AudioProcessor>);
+        let processor = Box::new(
+            AbProcessor::new(Box::new(ChainProcessor::from_slots(&self.chain)), reference, Arc::clone(&self.ab))
+                .with_cpu_meter(Arc::clone(&self.monitoring)),
+        );
         let bypass = Arc::clone(&self.bypass);
-        let block_size = self.selected_block_size;
-        let cpu_usage = self.cpu_usage.clone(); // Use the shared CPU usage
+        let monitoring = Arc::clone(&self.monitoring); // Shared CPU usage and taps
+        let control = ProcessorControl::new(self.selected_block_size);
+        let start_frame = match self.transport.loop_region() {
+            Some((loop_start, _)) if start_frame == 0 => loop_start,
+            _ => start_frame,
+        };
+        if start_frame > 0 {
+            // Applied before the first block, so nothing plays from the top
+            control.request_seek(start_frame);
+        }
 
-        let file_path = format!("src/assets/{}", file_name);
         let dsp_processor = DspProcessor::new(
-            &file_path,
-            Arc::clone(&self.is_playing),
+            input,
             bypass,
-            self.params.iter().map(|p| Arc::clone(&p.value)).collect(),
-            process_fn,
-            block_size,
-            cpu_usage, 
-        );
+            processor,
+            control,
+            monitoring,
+            Arc::clone(&self.transport),
+        )?;
 
+        if let InputSource::File(path) = input {
+            self.library.add_recent(path);
+        }
         self.is_playing.store(true, Ordering::SeqCst);
         dsp_processor.process();
 
-        self.dsp_processor = Some(dsp_processor);
+        // Each stream has its own playing flag, so this only stops the old one
+        if let Some(old) = self.dsp_processor.replace(dsp_processor) {
+            old.stop();
+        }
+        Ok(())
+    }
+
+    /// Seeks the running file, restarting it first if it has stopped.
+    fn seek(&mut self, frame: u64) {
+        let finished = self.dsp_processor.as_ref().is_none_or(|dsp| dsp.is_finished());
+        if finished {
+            if let Some(input @ InputSource::File(_)) = self.input.clone() {
+                self.play_from(input, frame);
+            }
+        } else if let Some(ref dsp) = self.dsp_processor {
+            dsp.seek(frame);
+        }
+    }
+
+    /// Plays `input`, or shows why it couldn't be played. Returns whether
+    /// playback started.
+    fn play(&mut self, input: InputSource) -> bool {
+        self.play_from(input, 0)
+    }
+
+    fn play_from(&mut self, input: InputSource, start_frame: u64) -> bool {
+        match self.load_input(&input, start_frame) {
+            Ok(()) => {
+                self.input = Some(input);
+                self.error = None;
+                true
+            }
+            Err(e) => {
+                self.error = Some(e);
+                false
+            }
+        }
+    }
+
+    /// Plays and selects `path`.
+    fn play_file(&mut self, path: PathBuf) {
+        if self.play(InputSource::File(path.clone())) {
+            self.selected_file = Some(path);
+        }
+    }
+
+    /// Plays the test signal set up in the generator panel.
+    fn play_generator(&mut self) {
+        let controls = Arc::clone(&self.generator.controls);
+        self.play(InputSource::Generator(self.generator.waveform, controls));
+    }
+
+    /// The chain, input, playback and analyzer settings, for saving as a session.
+    pub fn capture_session(&self) -> Session {
+        Session {
+            selected_module: None,
+            chain: self.chain.iter().map(SessionSlot::capture).collect(),
+            audio_file: self.selected_file.clone(),
+            play_generator: matches!(self.input, Some(InputSource::Generator(..))),
+            generator: self.generator.settings(),
+            loop_region: self.transport.loop_settings(),
+            block_size: self.selected_block_size,
+            bypass: self.bypass.load(Ordering::SeqCst),
+            ab: self.ab.settings(),
+            ab_reference: self
+                .ab_reference
+                .as_ref()
+                .map(|snapshot| Session::capture_reference(&self.chain, snapshot)),
+            spectrum: self.spectrum.settings.clone(),
+            scope: self.scope.settings.clone(),
+            spectrogram: self.spectrogram.settings.clone(),
+            stereo: self.stereo.settings.clone(),
+            response: self.response.settings.clone(),
+            distortion: self.distortion.settings.clone(),
+            null_test: self.null_test.settings.clone(),
+        }
+    }
+
+    /// Applies a session's settings and starts its input. The chain itself
+    /// is passed to `AudioApp::new`.
+    pub fn restore_session(&mut self, session: &Session) {
+        if self.available_block_sizes.contains(&session.block_size) {
+            self.selected_block_size = session.block_size;
+        }
+        self.bypass.store(session.bypass, Ordering::SeqCst);
+        self.generator.restore(&session.generator);
+        self.transport.restore_loop(&session.loop_region);
+        self.ab.restore(&session.ab);
+        self.ab_reference = session.reference(&self.chain);
+        self.spectrum.settings = session.spectrum.clone();
+        self.scope.settings = session.scope.clone();
+        self.spectrogram.settings = session.spectrogram.clone();
+        self.stereo.settings = session.stereo.clone();
+        self.response.settings = session.response.clone();
+        self.distortion.settings = session.distortion.clone();
+        self.null_test.settings = session.null_test.clone();
+
+        if let Some(file) = &session.audio_file {
+            match self.library.resolve(file) {
+                Some(path) if session.play_generator => self.selected_file = Some(path),
+                Some(path) => self.play_file(path),
+                None => {
+                    self.error = Some(DspError::OpenFile {
+                        path: file.clone(),
+                        source: io::ErrorKind::NotFound.into(),
+                    })
+                }
+            }
+        }
+        if session.play_generator {
+            self.play_generator();
+        }
+    }
+
+    /// Appends a module to the end of the chain.
+    pub fn add_module(&mut self, slot: ChainSlot) {
+        self.chain.push(slot);
+        self.ab_reference = None; // Snapshots only fit the chain they came from
+        self.restart_chain();
+    }
+
+    fn edit_chain(&mut self, edit: ChainEdit) {
+        edit.apply(&mut self.chain);
+        self.ab_reference = None;
+        self.restart_chain();
+    }
+
+    /// Rebuilds the running chain after a structural change. Playback
+    /// restarts from the same position since processor state can't be
+    /// carried across.
+    fn restart_chain(&mut self) {
+        if !self.is_playing.load(Ordering::SeqCst) {
+            return;
+        }
+        let position = self.transport.position();
+        if let Some(input) = self.input.clone() {
+            self.play_from(input, position);
+        }
+    }
+
+    /// A/B switch, reference selection, level matching and the ABX test.
+    fn show_ab_controls(&mut self, ui: &mut egui::Ui) {
+        if !self.abx.is_active() {
+            ui.horizontal(|ui| {
+                let b = self.ab.is_b_selected();
+                if ui.selectable_label(!b, "A").clicked() {
+                    self.ab.select_b(false);
+                }
+                if ui.selectable_label(b, "B").clicked() {
+                    self.ab.select_b(true);
+                }
+                ui.label(if self.ab_reference.is_some() {
+                    "A: parameter snapshot, B: current settings"
+                } else {
+                    "A: dry, B: processed"
+                });
+            });
+            ui.horizontal(|ui| {
+                let mut restart = false;
+                if ui.button("Snapshot settings as A").clicked() {
+                    self.ab_reference = Some(ChainSnapshot::capture(&self.chain));
+                    restart = true;
+                }
+                if ui.add_enabled(self.ab_reference.is_some(), egui::Button::new("Use dry as A")).clicked() {
+                    self.ab_reference = None;
+                    restart = true;
+                }
+                if restart {
+                    self.restart_chain();
+                }
+            });
+        }
+
+        ui.horizontal(|ui| {
+            let mut level_match = self.ab.level_match();
+            if ui.checkbox(&mut level_match, "Match B loudness to A").changed() {
+                self.ab.set_level_match(level_match);
+            }
+            if level_match && !self.abx.is_active() {
+                ui.label(format!("{:+.1} dB", self.ab.match_gain_db()));
+            }
+            let mut crossfade = self.ab.crossfade_ms();
+            if ui
+                .add(egui::DragValue::new(&mut crossfade).clamp_range(0.0..=500.0).suffix(" ms"))
+                .changed()
+            {
+                self.ab.set_crossfade_ms(crossfade);
+            }
+            ui.label("crossfade");
+        });
+
+        ui.separator();
+        self.abx.show(ui, &self.ab);
+    }
+
+    /// Renders the selected file offline on a background thread, using a
+    /// snapshot of the current parameter values, bypass state and block size.
+    pub fn start_render(&mut self) {
+        let Some(input) = self.selected_file.clone() else {
+            *self.render_status.lock().unwrap() = "No audio file selected.".to_string();
+            return;
+        };
+
+        let output = PathBuf::from(&self.render_path);
+        let processor = Box::new(ChainProcessor::from_snapshot(&self.chain, &ChainSnapshot::capture(&self.chain)));
+        let bypass = self.bypass.load(Ordering::SeqCst);
+        let block_size = self.selected_block_size;
+        let format = self.render_format;
+        let status = Arc::clone(&self.render_status);
+
+        *status.lock().unwrap() = "Rendering...".to_string();
+        thread::spawn(move || {
+            let message = match render_to_wav(&input, &output, processor, bypass, block_size, format) {
+                Ok(summary) => {
+                    let loudness = summary
+                        .output_loudness
+                        .map(|report| format!(", {:.1} LUFS integrated", report.integrated))
+                        .unwrap_or_default();
+                    format!(
+                        "Rendered {:.1}s to {} ({:.0}x real time{})",
+                        summary.audio_duration().as_secs_f64(),
+                        output.display(),
+                        summary.speed_factor(),
+                        loudness,
+                    )
+                }
+                Err(e) => format!("Render failed: {}", e),
+            };
+            *status.lock().unwrap() = message;
+        });
     }
 }
 
 impl App for AudioApp {
     /// The `update` method is called on each frame to update the UI.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if self.library.poll() && std::mem::take(&mut self.autoplay) && self.input.is_none() {
+            if let Some(first_file) = self.library.files().first().cloned() {
+                self.play_file(first_file);
+            }
+        }
+        if self.library.is_scanning() {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
+            show_error_banner(ui, &mut self.error);
+
             // Header Row
             ui.horizontal(|ui| {
                 // Left Side: Dropdowns and Play/Stop Buttons
@@ -189,15 +664,17 @@ impl App for AudioApp {
                         egui::ComboBox::from_label("Audio File")
                             .selected_text(
                                 self.selected_file
-                                    .clone()
+                                    .as_ref()
+                                    .and_then(|path| path.file_name())
+                                    .map(|name| name.to_string_lossy().to_string())
                                     .unwrap_or_else(|| "None".to_string()),
                             )
                             .show_ui(ui, |cb| {
-                                for file in &self.available_files {
+                                for file in self.library.files() {
                                     cb.selectable_value(
                                         &mut self.selected_file,
                                         Some(file.clone()),
-                                        file,
+                                        self.library.label(file),
                                     );
                                 }
                             });
@@ -205,7 +682,20 @@ impl App for AudioApp {
                         // Play Button
                         if ui.button("Play").clicked() {
                             if let Some(file) = self.selected_file.clone() {
-                                self.load_audio(&file);
+                                self.play_file(file);
+                            }
+                        }
+
+                        // Pause/Resume Button
+                        let paused = self.dsp_processor.as_ref().is_some_and(|dsp| dsp.is_paused());
+                        let pause_label = if paused { "Resume" } else { "Pause" };
+                        if ui.add_enabled(self.dsp_processor.is_some(), egui::Button::new(pause_label)).clicked() {
+                            if let Some(ref dsp) = self.dsp_processor {
+                                if paused {
+                                    dsp.resume();
+                                } else {
+                                    dsp.pause();
+                                }
                             }
                         }
 
@@ -236,6 +726,7 @@ impl App for AudioApp {
 
                     // Block Size Dropdown
                     ui.separator(); // Add some spacing
+                    let previous_block_size = self.selected_block_size;
                     egui::ComboBox::from_label("Block Size")
                         .selected_text(self.selected_block_size.to_string())
                         .show_ui(ui, |cb| {
@@ -247,63 +738,357 @@ impl App for AudioApp {
                                 );
                             }
                         });
+                    if self.selected_block_size != previous_block_size {
+                        if let Some(ref dsp) = self.dsp_processor {
+                            dsp.set_block_size(self.selected_block_size);
+                        }
+                    }
                 });
             });
 
             ui.separator();
 
+            // Transport: position, seek and A-B loop
+            let transport = Arc::clone(&self.transport);
+            let to_secs = |frames| transport.frames_to_secs(frames);
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{} / {}",
+                    format_time(to_secs(transport.position())),
+                    format_time(to_secs(transport.total_frames())),
+                ));
+                if let Some(frame) = show_position_bar(ui, &transport) {
+                    self.seek(frame);
+                }
+            });
+            ui.horizontal(|ui| {
+                let mut looping = transport.loop_enabled();
+                if ui.checkbox(&mut looping, "Loop A-B").changed() {
+                    transport.set_loop_enabled(looping);
+                }
+
+                let total = to_secs(transport.total_frames());
+                let (start, end) = transport.loop_points();
+                let (mut a, mut b) = (to_secs(start), to_secs(end));
+                let mut changed = false;
+                ui.label("A");
+                changed |= ui
+                    .add(egui::DragValue::new(&mut a).speed(0.01).clamp_range(0.0..=total).suffix(" s"))
+                    .changed();
+                if ui.small_button("Set A").clicked() {
+                    a = to_secs(transport.position());
+                    changed = true;
+                }
+                ui.label("B");
+                changed |= ui
+                    .add(egui::DragValue::new(&mut b).speed(0.01).clamp_range(0.0..=total).suffix(" s"))
+                    .changed();
+                if ui.small_button("Set B").clicked() {
+                    b = to_secs(transport.position());
+                    changed = true;
+                }
+                if changed {
+                    transport.set_loop_points(transport.secs_to_frames(a), transport.secs_to_frames(b));
+                }
+            });
+            egui::CollapsingHeader::new("Waveform").default_open(true).show(ui, |ui| {
+                if let Some(frame) = show_waveform_overview(ui, &transport) {
+                    self.seek(frame);
+                }
+            });
+            if self.is_playing.load(Ordering::SeqCst) {
+                ctx.request_repaint();
+            }
+            ui.separator();
+
+            // Library folders, path entry, recent files and drag-and-drop
+            let mut open_file = self.library.handle_dropped_files(ctx);
+            egui::CollapsingHeader::new("Library").show(ui, |ui| {
+                if let Some(path) = self.library.show(ui) {
+                    open_file = Some(path);
+                }
+            });
+            if let Some(path) = open_file {
+                self.play_file(path);
+            }
+
+            // Test signals in place of a file
+            egui::CollapsingHeader::new("Test Signal").show(ui, |ui| {
+                let generating = self.is_playing.load(Ordering::SeqCst)
+                    && matches!(self.input, Some(InputSource::Generator(..)));
+                if self.generator.show(ui, generating) {
+                    self.play_generator();
+                }
+            });
+            ui.separator();
+
+            // Offline render row
+            ui.horizontal(|ui| {
+                ui.label("Render to");
+                ui.add(egui::TextEdit::singleline(&mut self.render_path).desired_width(160.0));
+                egui::ComboBox::from_id_source("render_format")
+                    .selected_text(self.render_format.label())
+                    .show_ui(ui, |cb| {
+                        for format in WavFormat::ALL {
+                            cb.selectable_value(&mut self.render_format, format, format.label());
+                        }
+                    });
+                if ui.button("Render").clicked() {
+                    self.start_render();
+                }
+            });
+            let render_status = self.render_status.lock().unwrap().clone();
+            if !render_status.is_empty() {
+                ui.label(render_status);
+            }
+
             ui.add_space(20.0);
             // Plugin Parameters
+            let mut chain_edit = None;
             egui::ScrollArea::vertical().show(ui, |ui| {
-                let available_width = ui.available_width();
-                
-                
-                for param in &self.params {
-                    let mut value = param.value.lock().unwrap();
-                    ui.add_space(5.0);
-                    // Use a horizontal layout to contain the label and the slider
+                // Module chain, processed top to bottom
+                let slot_count = self.chain.len();
+                for (index, slot) in self.chain.iter_mut().enumerate() {
                     ui.horizontal(|ui| {
+                        ui.strong(format!("{}. {}", index + 1, slot.title));
 
-                         let label_width = 140.0;
-
-                        // Set a fixed width for the label so it doesn't affect slider width
-                        let label = egui::Label::new(
-                            egui::RichText::new(&param.name).text_style(egui::TextStyle::Body)
-                        )
-                        .wrap(false);
-            
-                        // Use add_sized to set the label size and ensure it takes up a fixed width space
-                        ui.add_sized([label_width, 10.0], label);
-            
-            
-                        // Use a spacer to manage spacing between label and slider
-                 
-
-                        // Apply the modified style back to the context
-                        let mut style = (*ctx.style()).clone();
-                        style.spacing.slider_width = 300.0; // Adjust the slider width as needed
-                        ctx.set_style(style);
-            
-                        match &mut *value {
-                            ParamValue::Number(ref mut v) => {
-                                // Add a slider that fills the remaining width of the horizontal layout
-                                ui.add(
-                                    egui::Slider::new(v, param.min..=param.max)
-                                        .text("") // Use an empty string for the slider text
-                                        .show_value(true)
-                                        
-                                       // Adjust width based on label width
-                                );
-                            }
-                            ParamValue::Boolean(ref mut v) => {
-                                ui.checkbox(v, "");
-                            }
+                        let mut bypass = slot.bypass.load(Ordering::SeqCst);
+                        if ui.checkbox(&mut bypass, "Bypass").changed() {
+                            slot.bypass.store(bypass, Ordering::SeqCst);
+                        }
+
+                        if ui.add_enabled(index > 0, egui::Button::new("Up").small()).clicked() {
+                            chain_edit = Some(ChainEdit::MoveUp(index));
+                        }
+                        if ui.add_enabled(index + 1 < slot_count, egui::Button::new("Down").small()).clicked() {
+                            chain_edit = Some(ChainEdit::MoveDown(index));
+                        }
+                        if ui.small_button("Remove").clicked() {
+                            chain_edit = Some(ChainEdit::Remove(index));
                         }
                     });
+
+                    ui.push_id(slot.id, |ui| {
+                        egui::CollapsingHeader::new("Presets").show(ui, |ui| {
+                            slot.presets.show(ui, &slot.params);
+                        });
+                    });
+
+                    for param in &slot.params {
+                        param_row(ui, ctx, param);
+                    }
+                    ui.separator();
+                }
+
+                if self.chain.is_empty() {
+                    ui.label("The chain is empty. Add a module from the bar below.");
                 }
+
+                ui.add_space(20.0);
+                egui::CollapsingHeader::new("A/B Compare").show(ui, |ui| {
+                    self.show_ab_controls(ui);
+                });
+
+                egui::CollapsingHeader::new("Spectrum Analyzer")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        if self.is_playing.load(Ordering::SeqCst) {
+                            self.spectrum.update(&self.monitoring);
+                            ctx.request_repaint();
+                        }
+                        self.spectrum.show(ui);
+                    });
+
+                egui::CollapsingHeader::new("Spectrogram").show(ui, |ui| {
+                    if self.is_playing.load(Ordering::SeqCst) {
+                        self.spectrogram.update(&self.monitoring);
+                    }
+                    self.spectrogram.show(ui);
+                });
+
+                egui::CollapsingHeader::new("Oscilloscope").show(ui, |ui| {
+                    if self.is_playing.load(Ordering::SeqCst) {
+                        self.scope.update(&self.monitoring);
+                    }
+                    self.scope.show(ui, self.monitoring.output_tap.channels());
+                });
+
+                egui::CollapsingHeader::new("Stereo (Vectorscope)").show(ui, |ui| {
+                    if self.is_playing.load(Ordering::SeqCst) {
+                        self.stereo.update(&self.monitoring);
+                    }
+                    self.stereo.show(ui);
+                });
+
+                egui::CollapsingHeader::new("Loudness (EBU R128)").show(ui, |ui| {
+                    show_loudness(ui, &self.monitoring);
+                });
+
+                egui::CollapsingHeader::new("Frequency Response").show(ui, |ui| {
+                    self.response.show(ui, &self.chain);
+                });
+
+                egui::CollapsingHeader::new("Distortion (THD+N)").show(ui, |ui| {
+                    self.distortion.show(ui, &self.chain);
+                });
+
+                egui::CollapsingHeader::new("Null Test").show(ui, |ui| {
+                    self.null_test.show(
+                        ui,
+                        &self.chain,
+                        self.ab_reference.as_ref(),
+                        self.input.as_ref(),
+                        self.selected_block_size,
+                    );
+                });
             });
 
+            if let Some(edit) = chain_edit {
+                self.edit_chain(edit);
+            }
+
 
         });
     }
 }
+
+/// One labelled slider or checkbox for a module parameter.
+fn param_row(ui: &mut egui::Ui, ctx: &egui::Context, param: &AudioParam) {
+    let mut value = param.value.load();
+    ui.add_space(5.0);
+    // Use a horizontal layout to contain the label and the slider
+    ui.horizontal(|ui| {
+        let label_width = 140.0;
+
+        // Set a fixed width for the label so it doesn't affect slider width
+        let label = egui::Label::new(
+            egui::RichText::new(&param.name).text_style(egui::TextStyle::Body)
+        )
+        .wrap(false);
+
+        // Use add_sized to set the label size and ensure it takes up a fixed width space
+        ui.add_sized([label_width, 10.0], label);
+
+        // Apply the modified style back to the context
+        let mut style = (*ctx.style()).clone();
+        style.spacing.slider_width = 300.0; // Adjust the slider width as needed
+        ctx.set_style(style);
+
+        let changed = match &mut value {
+            ParamValue::Number(ref mut v) => number_slider(ui, param, v),
+            ParamValue::Boolean(ref mut v) => {
+                ui.checkbox(v, "").changed()
+            }
+            ParamValue::Integer(ref mut v) => {
+                let mut slider = egui::Slider::new(v, param.min as i32..=param.max as i32)
+                    .text("")
+                    .show_value(true);
+                if param.unit != ParamUnit::None {
+                    let unit = param.unit;
+                    slider = slider.custom_formatter(move |v, _| unit.format(v as f32));
+                }
+                ui.add(slider).changed()
+            }
+            ParamValue::Choice { options, index } => {
+                let mut changed = false;
+                egui::ComboBox::from_id_source(Arc::as_ptr(&param.value))
+                    .selected_text(options.get(*index).map(String::as_str).unwrap_or(""))
+                    .show_ui(ui, |cb| {
+                        for (i, option) in options.iter().enumerate() {
+                            changed |= cb.selectable_value(index, i, option).changed();
+                        }
+                    });
+                changed
+            }
+        };
+        if changed {
+            param.value.store(&value);
+        }
+    });
+}
+
+/// Slider for a `Number` parameter, honouring its unit and scale.
+fn number_slider(ui: &mut egui::Ui, param: &AudioParam, value: &mut f32) -> bool {
+    let unit = param.unit;
+    let (min, max) = (param.min, param.max);
+
+    if let ParamScale::Skewed(skew) = param.scale {
+        if skew > 0.0 && max > min {
+            // Slide over a normalised position and map it through the skew
+            let to_value = move |t: f64| min + (max - min) * (t as f32).powf(skew);
+            let to_position = move |v: f32| ((v - min) / (max - min)).clamp(0.0, 1.0).powf(1.0 / skew);
+
+            let mut position = to_position(*value);
+            let changed = ui
+                .add(
+                    egui::Slider::new(&mut position, 0.0..=1.0)
+                        .text("")
+                        .show_value(true)
+                        .custom_formatter(move |t, _| unit.format(to_value(t)))
+                        .custom_parser(move |text| unit.parse(text).map(|v| to_position(v) as f64)),
+                )
+                .changed();
+            if changed {
+                *value = to_value(position as f64);
+            }
+            return changed;
+        }
+    }
+
+    let mut slider = egui::Slider::new(value, min..=max)
+        .text("") // Use an empty string for the slider text
+        .show_value(true)
+        .logarithmic(param.scale == ParamScale::Logarithmic);
+    if unit != ParamUnit::None {
+        slider = slider
+            .custom_formatter(move |v, _| unit.format(v as f32))
+            .custom_parser(move |text| unit.parse(text).map(f64::from));
+    }
+    ui.add(slider).changed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_accepts_values_with_and_without_units() {
+        assert_eq!(ParamUnit::Hz.parse("440"), Some(440.0));
+        assert_eq!(ParamUnit::Hz.parse("1.2k"), Some(1200.0));
+        assert_eq!(ParamUnit::Hz.parse(" 1.2 kHz "), Some(1200.0));
+        assert_eq!(ParamUnit::Hz.parse("80 Hz"), Some(80.0));
+        assert_eq!(ParamUnit::Db.parse("-6 dB"), Some(-6.0));
+        assert_eq!(ParamUnit::Ms.parse("250ms"), Some(250.0));
+        assert_eq!(ParamUnit::Ms.parse("0.5 s"), Some(500.0));
+        assert_eq!(ParamUnit::Percent.parse("50%"), Some(0.5));
+        assert_eq!(ParamUnit::None.parse("2.5"), Some(2.5));
+    }
+
+    #[test]
+    fn parse_rejects_garbage() {
+        assert_eq!(ParamUnit::Hz.parse("loud"), None);
+        assert_eq!(ParamUnit::Ms.parse("ms"), None);
+        assert_eq!(ParamUnit::Db.parse(""), None);
+    }
+
+    #[test]
+    fn parse_reads_back_formatted_values() {
+        for (unit, value) in [
+            (ParamUnit::Hz, 2500.0),
+            (ParamUnit::Hz, 80.0),
+            (ParamUnit::Db, -12.5),
+            (ParamUnit::Ms, 1500.0),
+            (ParamUnit::Percent, 0.25),
+        ] {
+            assert_eq!(unit.parse(&unit.format(value)), Some(value), "{:?}", unit);
+        }
+    }
+
+    #[test]
+    fn choice_clamps_index() {
+        match ParamValue::choice(&["a", "b"], 5) {
+            ParamValue::Choice { index, .. } => assert_eq!(index, 1),
+            _ => unreachable!(),
+        }
+    }
+}
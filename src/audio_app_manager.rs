@@ -1,9 +1,14 @@
 // src/audio_app_manager.rs
 
-use eframe::{egui, App, Frame, CreationContext};
-use std::sync::{Arc, Mutex};
+use eframe::{egui, App, Frame};
+use std::sync::Arc;
 use crate::dsp_module::DSPModule;
+use crate::analysis::{cpu, meter};
+use crate::analysis::Monitoring;
 use crate::audio_app::AudioApp;
+use crate::error::{show_error_banner, DspError};
+use crate::session::Session;
+use std::path::Path;
 
 
 
@@ -11,7 +16,10 @@ pub struct AudioAppManager {
     modules: Vec<Arc<dyn DSPModule>>,
     current_module_index: usize,
     current_audio_app: Option<AudioApp>,
-    cpu_usage: Arc<Mutex<f32>>, // Shared CPU usage field
+    monitoring: Arc<Monitoring>, // Shared CPU usage and analysis taps
+    session_path: String,
+    session_status: String,
+    error: Option<DspError>, // Module errors, shown above the app
 }
 
 impl AudioAppManager {
@@ -20,10 +28,66 @@ impl AudioAppManager {
             modules,
             current_module_index: 0,
             current_audio_app: None,
-            cpu_usage: Arc::new(Mutex::new(0.0)), // Initialize shared CPU usage
+            monitoring: Arc::new(Monitoring::new()), // Initialize shared CPU usage and taps
+            session_path: "session.json".to_string(),
+            session_status: String::new(),
+            error: None,
         }
     }
 
+    /// A manager already running the chain and settings of `session`.
+    pub fn from_session(modules: Vec<Arc<dyn DSPModule>>, session: &Session) -> Result<Self, String> {
+        let mut manager = Self::new(modules);
+        manager.open_session(session)?;
+        Ok(manager)
+    }
+
+    /// Replaces the current app with the state saved in `session`. Nothing
+    /// changes if any of its modules can't be found.
+    pub fn open_session(&mut self, session: &Session) -> Result<(), String> {
+        let chain = session.build_chain(&self.modules)?;
+        if let Some(name) = &session.selected_module {
+            if let Some(index) = self.modules.iter().position(|m| m.name() == name) {
+                self.current_module_index = index;
+            }
+        }
+
+        // Drop the old app first so its playback stops before the new one starts
+        self.current_audio_app = None;
+        let mut app = AudioApp::new(chain, self.monitoring.clone());
+        app.restore_session(session);
+        self.current_audio_app = Some(app);
+        Ok(())
+    }
+
+    pub fn capture_session(&self) -> Option<Session> {
+        let mut session = self.current_audio_app.as_ref()?.capture_session();
+        session.selected_module = self.modules.get(self.current_module_index).map(|m| m.name().to_string());
+        Some(session)
+    }
+
+    fn save_session(&mut self) {
+        let path = Path::new(&self.session_path);
+        self.session_status = match self.capture_session() {
+            Some(session) => match session.save(path) {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(e) => format!("Save failed: {}", e),
+            },
+            None => "Nothing to save.".to_string(),
+        };
+    }
+
+    fn load_session(&mut self) {
+        let path = self.session_path.clone();
+        let result = Session::load(Path::new(&path))
+            .map_err(|e| e.to_string())
+            .and_then(|session| self.open_session(&session));
+        self.session_status = match result {
+            Ok(()) => format!("Loaded {}", path),
+            Err(e) => format!("Load failed: {}", e),
+        };
+    }
+
     pub fn switch_module(&mut self, index: usize) {
         if index >= self.modules.len() {
             return;
@@ -32,19 +96,33 @@ impl AudioAppManager {
         self.current_audio_app = None; // Reset to load the new module
     }
 
-    fn initialize_current_app(&mut self, ctx: &egui::Context) {
+    /// Appends the selected module to the running chain instead of replacing it.
+    pub fn add_module_to_chain(&mut self, index: usize) {
+        let Some(module) = self.modules.get(index) else {
+            return;
+        };
+        match self.current_audio_app {
+            Some(ref mut app) => match module.initialize().into_slot(module.name()) {
+                Ok(slot) => app.add_module(slot),
+                Err(e) => self.error = Some(e),
+            },
+            None => self.switch_module(index),
+        }
+    }
+
+    fn initialize_current_app(&mut self) {
         if self.current_audio_app.is_some() {
             return;
         }
 
         if let Some(module) = self.modules.get(self.current_module_index) {
             let builder = module.initialize();
-            match builder.build(self.cpu_usage.clone()) { // Pass shared CPU usage
+            match builder.build(module.name(), self.monitoring.clone()) { // Pass shared CPU usage
                 Ok(app) => {
                     self.current_audio_app = Some(app);
                 }
                 Err(e) => {
-                    eprintln!("Failed to build AudioApp: {}", e);
+                    self.error = Some(e);
                 }
             }
         }
@@ -54,8 +132,9 @@ impl AudioAppManager {
 impl App for AudioAppManager {
     fn update(&mut self, ctx: &egui::Context, frame: &mut Frame) {
         // Initialize the current app if not already done
-        self.initialize_current_app(ctx);
-        let cpu_usage = *self.cpu_usage.lock().unwrap(); // Access the shared CPU usage
+        self.initialize_current_app();
+        let cpu_stats = self.monitoring.cpu.stats(); // Load of the module's process call
+        let chain_stats = self.monitoring.chain_cpu.stats();
         egui::TopBottomPanel::bottom("bottom_panel").show(ctx, |ui| {
 
             ui.ctx().set_pixels_per_point(2.4);
@@ -96,11 +175,56 @@ impl App for AudioAppManager {
                         self.switch_module(self.current_module_index);
                     }
 
+                    // Add Button: append to the chain
+                    if ui
+                        .add(egui::Button::new("Add"))
+                        .on_hover_text("Append to the current chain")
+                        .clicked()
+                    {
+                        self.add_module_to_chain(self.current_module_index);
+                    }
+
+                });
+
+                // Session file: the whole chain and playback state
+                ui.horizontal(|ui| {
+                    ui.label("Session");
+                    ui.add(egui::TextEdit::singleline(&mut self.session_path).desired_width(200.0));
+                    if ui.button("Save").clicked() {
+                        self.save_session();
+                    }
+                    if ui.button("Load").clicked() {
+                        self.load_session();
+                    }
+                    if !self.session_status.is_empty() {
+                        ui.label(&self.session_status);
+                    }
+                });
+
+                // DSP load over the last blocks
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "DSP load: {:.2}% avg, {:.2}% peak (chain under test {:.2}% avg)",
+                        cpu_stats.average, cpu_stats.peak, chain_stats.average
+                    ));
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                        ui.label(format!("CPU Usage: {:.2}%", cpu_usage));
+                        cpu::show_histogram(ui, &cpu_stats);
+                        let overrun_text = format!("Overruns: {}/{}", cpu_stats.overruns, cpu_stats.blocks);
+                        if cpu_stats.overruns > 0 {
+                            ui.colored_label(egui::Color32::from_rgb(230, 80, 80), overrun_text);
+                        } else {
+                            ui.label(overrun_text);
+                        }
                     });
                 });
 
+                // Input and output levels per channel
+                ui.horizontal(|ui| {
+                    ui.vertical(|ui| meter::show_meters(ui, "In", &self.monitoring.input_meter));
+                    ui.separator();
+                    ui.vertical(|ui| meter::show_meters(ui, "Out", &self.monitoring.output_meter));
+                });
+
                 // Optional: Add separators or additional UI elements inside the group
                 // ui.separator();
             });
@@ -110,6 +234,7 @@ impl App for AudioAppManager {
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
+            show_error_banner(ui, &mut self.error);
             if let Some(ref mut app) = self.current_audio_app {
                 app.update(ctx, frame);
             } else {
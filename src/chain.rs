@@ -0,0 +1,230 @@
+// src/chain.rs
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::audio_app::{AudioParam, ParamValue};
+use crate::dsp_module::{AudioProcessor, ProcessorFactory};
+use crate::param_cell::ParamCell;
+use crate::presets::PresetBrowser;
+use crate::process_context::ProcessContext;
+use crate::smoothing::{ParamSmoother, Smoothing};
+
+static NEXT_SLOT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// One module in the signal chain with its own parameters and bypass switch.
+pub struct ChainSlot {
+    /// Unique for the life of the process, so a slot can be told apart from
+    /// others, and from its own module added twice, as the chain is reordered.
+    pub id: u64,
+    /// Name of the `DSPModule` this slot was created from.
+    pub module_name: String,
+    pub title: String,
+    pub params: Vec<AudioParam>,
+    pub processor_factory: ProcessorFactory,
+    pub bypass: Arc<AtomicBool>,
+    pub presets: PresetBrowser,
+}
+
+impl ChainSlot {
+    pub fn next_id() -> u64 {
+        NEXT_SLOT_ID.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Shared handles to the live parameter values.
+    pub fn param_values(&self) -> Vec<Arc<ParamCell>> {
+        self.params.iter().map(|p| Arc::clone(&p.value)).collect()
+    }
+
+    /// Copies of the current parameter values that later edits won't affect.
+    pub fn param_snapshot(&self) -> Vec<Arc<ParamCell>> {
+        self.params
+            .iter()
+            .map(|p| Arc::new(ParamCell::new(p.value.load())))
+            .collect()
+    }
+
+    pub fn param_smoothing(&self) -> Vec<Smoothing> {
+        self.params.iter().map(|p| p.smoothing).collect()
+    }
+}
+
+/// Structural edits requested from the chain panel.
+#[derive(Clone, Copy)]
+pub enum ChainEdit {
+    MoveUp(usize),
+    MoveDown(usize),
+    Remove(usize),
+}
+
+impl ChainEdit {
+    pub fn apply(self, slots: &mut Vec<ChainSlot>) {
+        match self {
+            ChainEdit::MoveUp(index) if index > 0 && index < slots.len() => slots.swap(index - 1, index),
+            ChainEdit::MoveDown(index) if index + 1 < slots.len() => slots.swap(index, index + 1),
+            ChainEdit::Remove(index) if index < slots.len() => {
+                slots.remove(index);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Frozen parameter values and bypass states of every slot in a chain.
+/// Only valid for the chain it was captured from.
+pub struct ChainSnapshot {
+    params: Vec<Vec<Arc<ParamCell>>>,
+    bypass: Vec<bool>,
+}
+
+impl ChainSnapshot {
+    pub fn capture(slots: &[ChainSlot]) -> Self {
+        Self {
+            params: slots.iter().map(|slot| slot.param_snapshot()).collect(),
+            bypass: slots.iter().map(|slot| slot.bypass.load(Ordering::SeqCst)).collect(),
+        }
+    }
+
+    /// A snapshot made from saved values, one list and bypass state per slot.
+    pub fn from_parts(params: Vec<Vec<Arc<ParamCell>>>, bypass: Vec<bool>) -> Self {
+        Self { params, bypass }
+    }
+
+    /// Frozen values and bypass state of each slot, in chain order.
+    pub fn slots(&self) -> impl Iterator<Item = (&[Arc<ParamCell>], bool)> {
+        self.params.iter().map(Vec::as_slice).zip(self.bypass.iter().copied())
+    }
+}
+
+struct ChainStage {
+    processor: Box<dyn AudioProcessor>,
+    params: Vec<Arc<ParamCell>>,
+    values: Vec<ParamValue>,
+    smoothers: Vec<ParamSmoother>,
+    /// Per-sample output of `smoothers`, handed to the processor.
+    ramps: Vec<Vec<f32>>,
+    bypass: Arc<AtomicBool>,
+}
+
+/// Runs a series of processors on the same block, each with its own
+/// parameters. Bypassed stages are skipped but keep their state.
+pub struct ChainProcessor {
+    stages: Vec<ChainStage>,
+}
+
+impl ChainProcessor {
+    pub fn new() -> Self {
+        Self { stages: Vec::new() }
+    }
+
+    /// A chain bound to the live parameters and bypass switches of `slots`.
+    pub fn from_slots(slots: &[ChainSlot]) -> Self {
+        let mut chain = Self::new();
+        for slot in slots {
+            chain.add_stage(
+                (slot.processor_factory)(),
+                slot.param_values(),
+                slot.param_smoothing(),
+                Arc::clone(&slot.bypass),
+            );
+        }
+        chain
+    }
+
+    /// A chain of `slots` using the parameter values and bypass states in
+    /// `snapshot` rather than the live ones.
+    pub fn from_snapshot(slots: &[ChainSlot], snapshot: &ChainSnapshot) -> Self {
+        let mut chain = Self::new();
+        for ((slot, params), bypass) in slots.iter().zip(&snapshot.params).zip(&snapshot.bypass) {
+            chain.add_stage(
+                (slot.processor_factory)(),
+                params.clone(),
+                slot.param_smoothing(),
+                Arc::new(AtomicBool::new(*bypass)),
+            );
+        }
+        chain
+    }
+
+    pub fn add_stage(
+        &mut self,
+        processor: Box<dyn AudioProcessor>,
+        params: Vec<Arc<ParamCell>>,
+        smoothing: Vec<Smoothing>,
+        bypass: Arc<AtomicBool>,
+    ) {
+        let values: Vec<ParamValue> = params.iter().map(|p| p.load()).collect();
+        let smoothers = values
+            .iter()
+            .zip(smoothing)
+            .map(|(value, smoothing)| ParamSmoother::new(smoothing, value.as_f32()))
+            .collect();
+        let ramps = vec![Vec::new(); values.len()];
+        self.stages.push(ChainStage {
+            processor,
+            params,
+            values,
+            smoothers,
+            ramps,
+            bypass,
+        });
+    }
+}
+
+impl AudioProcessor for ChainProcessor {
+    fn prepare(&mut self, sample_rate: u32, max_block: usize, channels: usize) {
+        for stage in self.stages.iter_mut() {
+            stage.processor.prepare(sample_rate, max_block, channels);
+            for smoother in stage.smoothers.iter_mut() {
+                smoother.prepare(sample_rate);
+            }
+            for ramp in stage.ramps.iter_mut() {
+                ramp.resize(max_block, 0.0);
+            }
+        }
+    }
+
+    /// Each stage is handed its own parameters in place of `ctx.params`.
+    fn process(&mut self, ctx: &mut ProcessContext) {
+        for stage in self.stages.iter_mut() {
+            if stage.bypass.load(Ordering::SeqCst) {
+                continue;
+            }
+            for (value, param) in stage.values.iter_mut().zip(&stage.params) {
+                param.load_into(value);
+            }
+            for ((smoother, ramp), value) in stage.smoothers.iter_mut().zip(&mut stage.ramps).zip(&stage.values) {
+                smoother.set_target(value.as_f32());
+                smoother.fill(&mut ramp[..ctx.block_len]);
+            }
+
+            let mut stage_ctx = ProcessContext {
+                channels: &mut *ctx.channels,
+                sample_rate: ctx.sample_rate,
+                block_len: ctx.block_len,
+                position: ctx.position,
+                params: &stage.values,
+                smoothed: &stage.ramps,
+            };
+            stage.processor.process(&mut stage_ctx);
+        }
+    }
+
+    fn reset(&mut self) {
+        for stage in self.stages.iter_mut() {
+            stage.processor.reset();
+            for smoother in stage.smoothers.iter_mut() {
+                smoother.reset();
+            }
+        }
+    }
+
+    /// Sum of the stages that aren't bypassed.
+    fn latency(&self) -> usize {
+        self.stages
+            .iter()
+            .filter(|stage| !stage.bypass.load(Ordering::SeqCst))
+            .map(|stage| stage.processor.latency())
+            .sum()
+    }
+}
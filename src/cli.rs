@@ -0,0 +1,429 @@
+// src/cli.rs
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::analysis::null_test::{format_db, NullTestInput, NullTestResult, Verdict};
+use crate::audio_app::ParamValue;
+use crate::chain::{ChainProcessor, ChainSlot};
+use crate::dsp_module::DSPModule;
+use crate::render::{bench, render_to_wav, WavFormat};
+
+const USAGE: &str = "\
+Usage: dsp_tester [COMMAND] [OPTIONS]
+
+Runs the GUI when no command is given. `dsp_tester --session <FILE>` opens
+the GUI in a session saved from the module bar.
+
+Commands:
+  list-modules             List the available modules and their parameters
+  render                   Process --input through a module and write --output
+  bench                    Time processing of --input through a module
+  null-test                Subtract a reference rendering of --input from the
+                           module's and report the residual; fails when its
+                           peak is above --tolerance
+  help                     Show this message
+
+Options:
+  --module <NAME>          Module name as shown by list-modules
+  --preset <NAME>          Load a factory or saved preset before any --param
+  --param <NAME=VALUE>     Set a parameter, can be repeated. Numbers may carry
+                           their unit (1.2k, 250ms, 50%), choices take the
+                           option name
+  --block-size <FRAMES>    Processing block size (default 4096)
+  --input <FILE>           Audio file to process
+  --output <FILE>          WAV file to write (render, or the residual for
+                           null-test)
+  --format <16|24|f32>     Output sample format (render, default 24)
+  --bypass                 Skip the module's processing (render, bench)
+  --iterations <N>         Number of timed passes (bench, default 5)
+  --reference-module <NAME>
+                           Module to null against (null-test, default dry)
+  --reference-preset <NAME>
+  --reference-param <NAME=VALUE>
+                           Preset and parameters of the reference module, which
+                           defaults to --module when only these are given;
+                           --param only sets the module under test (null-test)
+  --tolerance <DB>         Highest residual peak in dBFS that passes
+                           (null-test, default -120)
+  --search                 Look for delay the modules don't report (null-test)
+";
+
+/// Options shared by the subcommands.
+struct Options {
+    module: Option<String>,
+    preset: Option<String>,
+    params: Vec<(String, String)>,
+    block_size: usize,
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    format: WavFormat,
+    bypass: bool,
+    iterations: usize,
+    reference_module: Option<String>,
+    reference_preset: Option<String>,
+    reference_params: Vec<(String, String)>,
+    tolerance_db: f32,
+    search: bool,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Options {
+            module: None,
+            preset: None,
+            params: Vec::new(),
+            block_size: 4096,
+            input: None,
+            output: None,
+            format: WavFormat::Int24,
+            bypass: false,
+            iterations: 5,
+            reference_module: None,
+            reference_preset: None,
+            reference_params: Vec::new(),
+            tolerance_db: -120.0,
+            search: false,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| {
+                args.next().cloned().ok_or_else(|| format!("{} needs a value", flag))
+            };
+
+            match arg.as_str() {
+                "--module" => options.module = Some(value(arg)?),
+                "--preset" => options.preset = Some(value(arg)?),
+                "--param" => options.params.push(parse_param(&value(arg)?, arg)?),
+                "--block-size" => options.block_size = parse_number(&value(arg)?, arg)?,
+                "--input" => options.input = Some(PathBuf::from(value(arg)?)),
+                "--output" => options.output = Some(PathBuf::from(value(arg)?)),
+                "--format" => {
+                    options.format = match value(arg)?.as_str() {
+                        "16" => WavFormat::Int16,
+                        "24" => WavFormat::Int24,
+                        "f32" | "32" => WavFormat::Float32,
+                        other => return Err(format!("unknown --format '{}', expected 16, 24 or f32", other)),
+                    }
+                }
+                "--bypass" => options.bypass = true,
+                "--iterations" => options.iterations = parse_number(&value(arg)?, arg)?,
+                "--reference-module" => options.reference_module = Some(value(arg)?),
+                "--reference-preset" => options.reference_preset = Some(value(arg)?),
+                "--reference-param" => options.reference_params.push(parse_param(&value(arg)?, arg)?),
+                "--tolerance" => options.tolerance_db = parse_number(&value(arg)?, arg)?,
+                "--search" => options.search = true,
+                other => return Err(format!("unknown option '{}'\n\n{}", other, USAGE)),
+            }
+        }
+
+        if options.block_size == 0 {
+            return Err("--block-size must be greater than zero".to_string());
+        }
+
+        Ok(options)
+    }
+
+    fn input(&self) -> Result<&PathBuf, String> {
+        self.input.as_ref().ok_or_else(|| "--input is required".to_string())
+    }
+}
+
+pub fn run(args: &[String], modules: &[Arc<dyn DSPModule>]) -> Result<(), String> {
+    let (command, rest) = args.split_first().expect("run needs at least one argument");
+
+    match command.as_str() {
+        "list-modules" => {
+            list_modules(modules);
+            Ok(())
+        }
+        "render" => run_render(&Options::parse(rest)?, modules),
+        "bench" => run_bench(&Options::parse(rest)?, modules),
+        "null-test" => run_null_test(&Options::parse(rest)?, modules),
+        "help" | "--help" | "-h" => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        other => Err(format!("unknown command '{}'\n\n{}", other, USAGE)),
+    }
+}
+
+fn list_modules(modules: &[Arc<dyn DSPModule>]) {
+    for module in modules {
+        println!("{}", module.name());
+        for param in module.initialize().params() {
+            let unit = param.unit;
+            match param.value.load() {
+                ParamValue::Number(v) => println!(
+                    "  {} = {} ({} to {})",
+                    param.name,
+                    unit.format(v),
+                    unit.format(param.min),
+                    unit.format(param.max)
+                ),
+                ParamValue::Boolean(v) => println!("  {} = {}", param.name, v),
+                ParamValue::Integer(v) => {
+                    println!("  {} = {} ({} to {})", param.name, v, param.min as i32, param.max as i32)
+                }
+                ParamValue::Choice { options, index } => println!(
+                    "  {} = {} ({})",
+                    param.name,
+                    options.get(index).map(String::as_str).unwrap_or("?"),
+                    options.join(" | ")
+                ),
+            }
+        }
+    }
+}
+
+fn run_render(options: &Options, modules: &[Arc<dyn DSPModule>]) -> Result<(), String> {
+    let input = options.input()?;
+    let output = options.output.as_ref().ok_or("--output is required")?;
+    let slot = configure_module(options, modules)?;
+
+    let summary = render_to_wav(
+        input,
+        output,
+        Box::new(ChainProcessor::from_slots(std::slice::from_ref(&slot))),
+        options.bypass,
+        options.block_size,
+        options.format,
+    )
+    .map_err(|e| format!("render failed: {}", e))?;
+
+    println!(
+        "Rendered {:.2}s ({} ch, {} Hz) to {} in {:.2}s ({:.1}x real time)",
+        summary.audio_duration().as_secs_f64(),
+        summary.channels,
+        summary.sample_rate,
+        output.display(),
+        summary.elapsed.as_secs_f64(),
+        summary.speed_factor(),
+    );
+    if let (Some(input), Some(output)) = (summary.input_loudness, summary.output_loudness) {
+        println!("Loudness in:  {}", input);
+        println!("Loudness out: {}", output);
+        let shift = output.integrated - input.integrated;
+        if shift.is_finite() {
+            println!("Integrated shift: {:+.1} LU", shift);
+        }
+    }
+    Ok(())
+}
+
+fn run_bench(options: &Options, modules: &[Arc<dyn DSPModule>]) -> Result<(), String> {
+    let input = options.input()?;
+    let slot = configure_module(options, modules)?;
+
+    let runs = bench(
+        input,
+        || Box::new(ChainProcessor::from_slots(std::slice::from_ref(&slot))),
+        options.bypass,
+        options.block_size,
+        options.iterations.max(1),
+    )
+    .map_err(|e| format!("bench failed: {}", e))?;
+
+    for (i, run) in runs.iter().enumerate() {
+        println!(
+            "Pass {}: {:.3} ms, {:.1}x real time",
+            i + 1,
+            run.elapsed.as_secs_f64() * 1000.0,
+            run.speed_factor(),
+        );
+    }
+
+    let best = runs.iter().map(|r| r.speed_factor()).fold(0.0, f64::max);
+    let average = runs.iter().map(|r| r.speed_factor()).sum::<f64>() / runs.len() as f64;
+    println!(
+        "Block size {}: average {:.1}x, best {:.1}x real time ({:.3}% / {:.3}% of one core)",
+        options.block_size,
+        average,
+        best,
+        100.0 / average,
+        100.0 / best,
+    );
+    Ok(())
+}
+
+fn run_null_test(options: &Options, modules: &[Arc<dyn DSPModule>]) -> Result<(), String> {
+    if options.bypass {
+        return Err("null-test doesn't take --bypass; leave out the --reference-* options to null against dry".to_string());
+    }
+    let input = NullTestInput::from_file(options.input()?).map_err(|e| e.to_string())?;
+    let slot = configure_module(options, modules)?;
+    let reference = if options.reference_module.is_none()
+        && options.reference_preset.is_none()
+        && options.reference_params.is_empty()
+    {
+        None
+    } else {
+        let name = options
+            .reference_module
+            .as_deref()
+            .or(options.module.as_deref())
+            .ok_or("--module is required")?;
+        Some(configure_named(name, options.reference_preset.as_deref(), &options.reference_params, modules)?)
+    };
+
+    let mut processed = ChainProcessor::from_slots(std::slice::from_ref(&slot));
+    let mut reference_processor = match reference {
+        Some(ref slot) => ChainProcessor::from_slots(std::slice::from_ref(slot)),
+        None => ChainProcessor::new(),
+    };
+    let result = NullTestResult::run(
+        &input,
+        &mut reference_processor,
+        &mut processed,
+        options.block_size,
+        options.search,
+    );
+
+    let describe = |slot: &ChainSlot, preset: Option<&String>| match preset {
+        Some(preset) => format!("{} ({})", slot.title, preset),
+        None => slot.title.clone(),
+    };
+    let against = reference
+        .as_ref()
+        .map_or("dry".to_string(), |reference| describe(reference, options.reference_preset.as_ref()));
+    println!("{} against {}: {}", describe(&slot, options.preset.as_ref()), against, result);
+    if result.latency_a != 0 || result.latency_b != 0 {
+        println!("Reported latency: {} / {} frames", result.latency_a, result.latency_b);
+    }
+    if let Some(output) = &options.output {
+        result.write_wav(output).map_err(|e| e.to_string())?;
+        println!("Residual written to {}", output.display());
+    }
+
+    match result.verdict(options.tolerance_db) {
+        Verdict::OutsideTolerance => Err(format!(
+            "residual peak {} dBFS is above the {} dBFS tolerance",
+            format_db(result.peak_db),
+            options.tolerance_db
+        )),
+        verdict => {
+            println!("{}", verdict.label());
+            Ok(())
+        }
+    }
+}
+
+/// Finds the requested module and applies `--preset` and `--param` to it.
+fn configure_module(options: &Options, modules: &[Arc<dyn DSPModule>]) -> Result<ChainSlot, String> {
+    let name = options.module.as_deref().ok_or("--module is required")?;
+    configure_named(name, options.preset.as_deref(), &options.params, modules)
+}
+
+/// Finds module `name` and applies `preset` and the `params` overrides to
+/// its defaults.
+fn configure_named(
+    name: &str,
+    preset: Option<&str>,
+    params: &[(String, String)],
+    modules: &[Arc<dyn DSPModule>],
+) -> Result<ChainSlot, String> {
+    let module = modules
+        .iter()
+        .find(|m| m.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("unknown module '{}', see list-modules", name))?;
+
+    let slot = module.initialize().into_slot(module.name()).map_err(|e| e.to_string())?;
+
+    if let Some(name) = preset {
+        let preset = slot
+            .presets
+            .find(name)
+            .map_err(|e| format!("can't load preset '{}' for '{}': {}", name, module.name(), e))?;
+        preset.apply(&slot.params);
+    }
+
+    for (name, value) in params {
+        let param = slot
+            .params
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("module '{}' has no parameter '{}'", module.name(), name))?;
+
+        let mut current = param.value.load();
+        match &mut current {
+            ParamValue::Number(v) => {
+                let parsed = param
+                    .unit
+                    .parse(value)
+                    .ok_or_else(|| format!("invalid value '{}' for {}", value, name))?;
+                if parsed < param.min || parsed > param.max {
+                    eprintln!(
+                        "warning: {}={} is outside {} to {}, clamping",
+                        param.name, parsed, param.min, param.max
+                    );
+                }
+                *v = parsed.clamp(param.min, param.max);
+            }
+            ParamValue::Boolean(v) => {
+                *v = match value.to_ascii_lowercase().as_str() {
+                    "true" | "on" | "1" => true,
+                    "false" | "off" | "0" => false,
+                    _ => return Err(format!("{} expects true or false, got '{}'", param.name, value)),
+                };
+            }
+            ParamValue::Integer(v) => {
+                let parsed: i32 = parse_number(value, name)?;
+                let (min, max) = (param.min as i32, param.max as i32);
+                if parsed < min || parsed > max {
+                    eprintln!("warning: {}={} is outside {} to {}, clamping", param.name, parsed, min, max);
+                }
+                *v = parsed.clamp(min, max);
+            }
+            ParamValue::Choice { options, index } => {
+                *index = options
+                    .iter()
+                    .position(|o| o.eq_ignore_ascii_case(value))
+                    .or_else(|| value.parse().ok().filter(|i| *i < options.len()))
+                    .ok_or_else(|| {
+                        format!("{} expects one of {}, got '{}'", param.name, options.join(", "), value)
+                    })?;
+            }
+        }
+        param.value.store(&current);
+    }
+
+    Ok(slot)
+}
+
+/// Splits a `NAME=VALUE` argument of `flag`.
+fn parse_param(param: &str, flag: &str) -> Result<(String, String), String> {
+    let (name, value) = param
+        .split_once('=')
+        .ok_or_else(|| format!("{} expects NAME=VALUE, got '{}'", flag, param))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for {}", value, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn reference_params_are_kept_apart_from_params() {
+        let options = Options::parse(&args(&["--param", "Gain=3", "--reference-param", " Gain = -3 "])).unwrap();
+        assert_eq!(options.params, [("Gain".to_string(), "3".to_string())]);
+        assert_eq!(options.reference_params, [("Gain".to_string(), "-3".to_string())]);
+        assert!(Options::parse(&args(&["--reference-param", "Gain"])).is_err());
+    }
+
+    #[test]
+    fn null_test_rejects_bypass() {
+        let error = run(&args(&["null-test", "--bypass", "--input", "missing.wav"]), &[]).unwrap_err();
+        assert!(error.contains("--bypass"), "{}", error);
+    }
+}
@@ -3,239 +3,361 @@
 use rodio::{OutputStream, Sink, Decoder, Source};
 use std::fs::File;
 use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use std::thread;
-use std::sync::atomic::{AtomicBool, Ordering};
-use crate::audio_app::ParamValue;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use crate::analysis::Monitoring;
+use crate::dsp_module::AudioProcessor;
+use crate::error::DspError;
+use crate::generators::{Generator, GeneratorControls, Waveform, GENERATOR_SAMPLE_RATE};
+use crate::process_context::ProcessContext;
+use crate::transport::{Transport, TransportSource, NO_SEEK};
 
-use std::time::Instant;
+/// Block sizes offered while playing. Processors are prepared for the
+/// largest up front so switching between them never allocates.
+pub const BLOCK_SIZES: [usize; 5] = [1024, 2048, 4096, 8192, 16384];
+pub const MAX_BLOCK_SIZE: usize = BLOCK_SIZES[BLOCK_SIZES.len() - 1];
 
+/// Requests from the GUI side that the `BlockProcessor` applies at the next
+/// block boundary, since the processor itself lives inside the sink.
+pub struct ProcessorControl {
+    block_size: AtomicUsize,
+    reset_requested: AtomicBool,
+    seek_to: AtomicU64,
+}
+
+impl ProcessorControl {
+    pub fn new(block_size: usize) -> Self {
+        Self {
+            block_size: AtomicUsize::new(block_size),
+            reset_requested: AtomicBool::new(false),
+            seek_to: AtomicU64::new(NO_SEEK),
+        }
+    }
+
+    pub fn request_reset(&self) {
+        self.reset_requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Seeks the transport and resets the processor together, so no block
+    /// mixes the old and new positions.
+    pub fn request_seek(&self, frame: u64) {
+        self.seek_to.store(frame, Ordering::SeqCst);
+    }
+}
+
+/// Opens and decodes an audio file.
+pub fn decode_file(path: &Path) -> Result<Decoder<BufReader<File>>, DspError> {
+    let file = File::open(path).map_err(|source| DspError::OpenFile {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    Decoder::new(BufReader::new(file)).map_err(|source| DspError::Decode {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// What a `DspProcessor` plays: an audio file or a built-in test signal.
+#[derive(Clone)]
+pub enum InputSource {
+    File(PathBuf),
+    Generator(Waveform, Arc<GeneratorControls>),
+}
+
+impl InputSource {
+    /// Opens the input for `BlockProcessor`. Files are decoded into memory
+    /// so `transport` can seek and loop them; generators run until stopped.
+    pub fn open(&self, transport: &Arc<Transport>) -> Result<Box<dyn Source<Item = f32> + Send>, DspError> {
+        match self {
+            InputSource::File(path) => {
+                let audio = transport.decode(path)?;
+                Ok(Box::new(TransportSource::new(audio, Arc::clone(transport))))
+            }
+            InputSource::Generator(waveform, controls) => {
+                transport.unload(GENERATOR_SAMPLE_RATE);
+                Ok(Box::new(Generator::new(*waveform, Arc::clone(controls))))
+            }
+        }
+    }
+}
 
 pub struct DspProcessor {
     sink: Arc<Mutex<Sink>>,
     _stream: OutputStream,
     is_playing: Arc<AtomicBool>,
     bypass: Arc<AtomicBool>, // Bypass flag
-    params: Vec<Arc<Mutex<ParamValue>>>,
-    process_fn: Arc<dyn Fn(&mut [i16], &[ParamValue]) + Send + Sync + 'static>,
-    block_size: usize, // Added block_size field
-    cpu_usage: Arc<Mutex<f32>>, // New field for storing CPU usage
+    control: Arc<ProcessorControl>,
+    monitoring: Arc<Monitoring>, // CPU usage and analysis taps shown by the GUI
+    transport: Arc<Transport>,
 }
 
 impl DspProcessor {
     pub fn new(
-        file_path: &str,
-        is_playing: Arc<AtomicBool>,
+        input: &InputSource,
         bypass: Arc<AtomicBool>, // Bypass flag
-        params: Vec<Arc<Mutex<ParamValue>>>,
-        process_fn: Arc<dyn Fn(&mut [i16], &[ParamValue]) + Send + Sync + 'static>,
-        block_size: usize, // Accept block_size parameter,
-        cpu_usage: Arc<Mutex<f32>>,
-    ) -> Self {
-        let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-        let sink = Sink::try_new(&stream_handle).unwrap();
-        println!("Audio output stream and sink created.");
-
-        let file = File::open(file_path).expect("Failed to open audio file");
-        println!("Audio file opened: {}", file_path);
-
-        let source = Decoder::new(BufReader::new(file)).expect("Failed to decode audio");
-        println!(
-            "Audio file decoded successfully. Sample rate: {}, channels: {}",
-            source.sample_rate(),
-            source.channels()
-        );
+        processor: Box<dyn AudioProcessor>, // Usually the whole module chain
+        control: ProcessorControl, // Block size, and a seek to start from
+        monitoring: Arc<Monitoring>,
+        transport: Arc<Transport>, // Position, seek and loop region
+    ) -> Result<Self, DspError> {
+        // Decode first so a bad file doesn't cost a device open
+        let source = input.open(&transport)?;
 
-        // Initialise CPU monitor
-     
+        let (_stream, stream_handle) = OutputStream::try_default()?;
+        let sink = Sink::try_new(&stream_handle)?;
 
         let dsp_processor = DspProcessor {
             sink: Arc::new(Mutex::new(sink)),
             _stream,
-            is_playing,
+            is_playing: Arc::new(AtomicBool::new(true)), // Cleared by `stop`, for this stream only
             bypass,
-            params,
-            process_fn,
-            block_size,
-            cpu_usage: Arc::clone(&cpu_usage),
+            control: Arc::new(control),
+            monitoring,
+            transport,
         };
 
-        let dsp_source = dsp_processor.apply_dsp(source);
+        let dsp_source = dsp_processor.apply_dsp(source, processor);
         dsp_processor.sink.lock().unwrap().append(dsp_source);
 
-        println!("DSP-processed audio appended to the sink.");
-
-        dsp_processor
+        Ok(dsp_processor)
     }
 
-    fn apply_dsp<S>(&self, source: S) -> BlockProcessor<S>
+    fn apply_dsp<S>(&self, source: S, processor: Box<dyn AudioProcessor>) -> BlockProcessor<S>
     where
-        S: Source<Item = i16> + Send + 'static,
+        S: Source<Item = f32> + Send + 'static,
     {
         BlockProcessor::new(
             source,
             Arc::clone(&self.is_playing),
             Arc::clone(&self.bypass), // Pass Bypass flag
-            self.params.clone(),
-            Arc::clone(&self.process_fn),
-            self.block_size, // Pass block_size
+            processor,
+            Arc::clone(&self.control),
         )
+        .with_monitoring(Arc::clone(&self.monitoring))
+        .with_transport(Arc::clone(&self.transport))
     }
     pub fn process(&self) {
-        let sink = Arc::clone(&self.sink);
-        let is_playing = Arc::clone(&self.is_playing);
-        let bypass = Arc::clone(&self.bypass);
-        let params = self.params.clone();
-        let process_fn = Arc::clone(&self.process_fn);
-        let block_size = self.block_size;
-        let cpu_usage = Arc::clone(&self.cpu_usage);
-        
-        // Assume a sample rate of 44100 Hz
-        let sample_rate = 48000.0;
-        // Calculate block duration in seconds based on block size and sample rate
-        let block_duration = block_size as f32 / sample_rate;
-    
-        thread::spawn(move || {
-            println!("DSP thread started");
-            
-            sink.lock().unwrap().play();
-            println!("Starting audio playback...");
-            
-            // Total processing time tracker
-            let mut total_elapsed = 0.0;
-            let mut processing_time = 0.0;
-            
-            while is_playing.load(Ordering::SeqCst) && !sink.lock().unwrap().empty() {
-                let start_time = Instant::now();
-                
-                // Measure the DSP processing time
-                let dsp_start = Instant::now();
-                if !bypass.load(Ordering::SeqCst) {
-                    let mut buffer = vec![0i16; block_size];
-                    let param_values: Vec<ParamValue> = params.iter()
-                        .map(|p| p.lock().unwrap().clone())
-                        .collect();
-                    (process_fn)(&mut buffer, &param_values);
-                }
-                processing_time += dsp_start.elapsed().as_secs_f32();
-                
-                // Update total elapsed time with the block duration
-                total_elapsed += block_duration;
-                
-                // Calculate DSP CPU usage
-                if total_elapsed > 0.0 {
-                    let dsp_cpu_usage = (processing_time / total_elapsed) * 100.0;
-                    println!("Estimated CPU Usage for DSP: {:.2}%", dsp_cpu_usage);
-                    
-                    // Update self.cpu_usage with the new value
-                    let mut cpu_usage_lock = cpu_usage.lock().unwrap();
-                    *cpu_usage_lock = dsp_cpu_usage;
-                }
-    
-                // Calculate the remaining time in the block and sleep if needed
-                let elapsed = start_time.elapsed();
-                let block_duration_in_millis = (block_duration * 1000.0) as u64;
-                if elapsed < Duration::from_millis(block_duration_in_millis) {
-                    thread::sleep(Duration::from_millis(block_duration_in_millis) - elapsed);
-                }
-            }
-            
-            sink.lock().unwrap().stop();
-            println!("DSP thread ending");
-        });
+        self.sink.lock().unwrap().play();
     }
 
     pub fn stop(&self) {
         self.is_playing.store(false, Ordering::SeqCst);
+        self.control.request_reset();
         self.sink.lock().unwrap().stop();
     }
-    pub fn get_cpu_usage(&self) -> f32 {
-        *self.cpu_usage.lock().unwrap()
+
+    pub fn pause(&self) {
+        self.sink.lock().unwrap().pause();
+    }
+
+    pub fn resume(&self) {
+        self.sink.lock().unwrap().play();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.sink.lock().unwrap().is_paused()
+    }
+
+    /// True once the file has played to the end. Generators never finish.
+    pub fn is_finished(&self) -> bool {
+        self.sink.lock().unwrap().empty()
+    }
+
+    /// Jumps to `frame` and clears the processor's state, so filter and delay
+    /// tails from the old position don't bleed into the new one.
+    pub fn seek(&self, frame: u64) {
+        self.control.request_seek(frame.min(self.transport.total_frames()));
+    }
+
+    /// Changes the block size of the running stream from the next block on,
+    /// up to the size the stream was prepared for.
+    pub fn set_block_size(&self, block_size: usize) {
+        self.control.block_size.store(block_size, Ordering::SeqCst);
     }
-    
 }
 
 pub struct BlockProcessor<S> {
     input: S,
-    block: Vec<i16>,
+    block: Vec<f32>, // Interleaved output of the last processed block
     block_pos: usize,
+    channel_buffers: Vec<Vec<f32>>, // Planar scratch buffers handed to the module
     is_playing: Arc<AtomicBool>,
     bypass: Arc<AtomicBool>, // Bypass flag
-    params: Vec<Arc<Mutex<ParamValue>>>,
-    process_fn: Arc<dyn Fn(&mut [i16], &[ParamValue]) + Send + Sync + 'static>,
-    samples_processed: usize,
-    block_size: usize, // Added block_size field
+    processor: Box<dyn AudioProcessor>,
+    control: Arc<ProcessorControl>,
+    monitoring: Option<Arc<Monitoring>>,
+    transport: Option<Arc<Transport>>, // Seeks and the file position, when playing a file
+    position: u64, // Frame index of the block being processed
+    block_size: usize, // Block size in frames
+    max_block_size: usize, // What the buffers and processor were sized for
 }
 
 impl<S> BlockProcessor<S>
 where
-    S: Source<Item = i16>,
+    S: Source<Item = f32>,
 {
     pub fn new(
         input: S,
         is_playing: Arc<AtomicBool>,
         bypass: Arc<AtomicBool>, // Accept Bypass flag
-        params: Vec<Arc<Mutex<ParamValue>>>,
-        process_fn: Arc<dyn Fn(&mut [i16], &[ParamValue]) + Send + Sync + 'static>,
-        block_size: usize, // Accept block_size parameter
+        mut processor: Box<dyn AudioProcessor>,
+        control: Arc<ProcessorControl>,
     ) -> Self {
-        println!("Creating new BlockProcessor with block size: {}", block_size);
+        let block_size = control.block_size.load(Ordering::SeqCst).max(1);
+        let max_block_size = block_size.max(MAX_BLOCK_SIZE);
+        let channels = input.channels().max(1) as usize;
+        processor.prepare(input.sample_rate(), max_block_size, channels);
         BlockProcessor {
             input,
-            block: Vec::with_capacity(block_size),
+            block: Vec::with_capacity(max_block_size * channels),
             block_pos: 0,
+            channel_buffers: vec![Vec::with_capacity(max_block_size); channels],
             is_playing,
             bypass,
-            params,
-            process_fn,
-            samples_processed: 0,
+            processor,
+            control,
+            monitoring: None,
+            transport: None,
+            position: 0,
             block_size,
+            max_block_size,
         }
     }
 
+    /// Publishes the input and output of every block to `monitoring`.
+    pub fn with_monitoring(mut self, monitoring: Arc<Monitoring>) -> Self {
+        monitoring.prepare(self.input.sample_rate(), self.channel_buffers.len());
+        self.monitoring = Some(monitoring);
+        self
+    }
+
+    /// Applies seeks through `transport` and takes block positions from it.
+    pub fn with_transport(mut self, transport: Arc<Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// The transport, if it's playing a file rather than an endless source.
+    fn file_transport(&self) -> Option<&Transport> {
+        self.transport.as_deref().filter(|transport| transport.total_frames() > 0)
+    }
+
+    /// Applies block size changes and reset requests made since the last block.
+    fn apply_control_changes(&mut self) {
+        // Everything is already sized for the largest block, so a new size
+        // only changes how much of it is used
+        self.block_size = self.control.block_size.load(Ordering::SeqCst).clamp(1, self.max_block_size);
+
+        let seek = self.control.seek_to.swap(NO_SEEK, Ordering::SeqCst);
+        if seek != NO_SEEK {
+            if let Some(transport) = self.file_transport() {
+                transport.seek(seek);
+            }
+        }
+        let reset = self.control.reset_requested.swap(false, Ordering::SeqCst);
+        if reset || seek != NO_SEEK {
+            self.processor.reset();
+        }
+    }
+
+    /// Pulls up to `block_size` frames from the input into `block`.
+    /// Returns the number of whole frames read.
+    fn fill_block(&mut self) -> usize {
+        let channels = self.channel_buffers.len();
+        self.block.clear();
+
+        'frames: for frame in 0..self.block_size {
+            for _ in 0..channels {
+                match self.input.next() {
+                    Some(sample) => self.block.push(sample),
+                    None => break 'frames,
+                }
+            }
+            // Follow seeks and loop jumps so `ProcessContext::position` is
+            // the file position of the block's first frame
+            if frame == 0 {
+                if let Some(transport) = self.file_transport() {
+                    self.position = transport.position();
+                }
+            }
+        }
+
+        // Drop a trailing partial frame so channels stay aligned
+        let frames = self.block.len() / channels;
+        self.block.truncate(frames * channels);
+        frames
+    }
+
     pub fn process_buffer(&mut self) {
-        if self.bypass.load(Ordering::SeqCst) {
-            // If bypass is active, skip processing
-            println!("Bypass is active. Skipping processing.");
-            return;
+        let channels = self.channel_buffers.len();
+        let frames = self.block.len() / channels;
+
+        // Deinterleave into the planar buffers
+        for (ch, buffer) in self.channel_buffers.iter_mut().enumerate() {
+            buffer.clear();
+            buffer.extend(self.block.iter().skip(ch).step_by(channels));
+        }
+
+        if let Some(ref monitoring) = self.monitoring {
+            monitoring.input_meter.record(&self.channel_buffers, frames);
+            monitoring.input_loudness.process(&self.channel_buffers, frames);
         }
 
-        let param_values: Vec<ParamValue> = self.params.iter()
-            .map(|p| p.lock().unwrap().clone())
-            .collect();
-        (self.process_fn)(&mut self.block, &param_values);
-        self.samples_processed += self.block.len();
+        // If bypass is active, skip processing and record the block as idle
+        let bypassed = self.bypass.load(Ordering::SeqCst);
+        let start = Instant::now();
+        if !bypassed {
+            let mut ctx = ProcessContext {
+                channels: &mut self.channel_buffers,
+                sample_rate: self.input.sample_rate(),
+                block_len: frames,
+                position: self.position,
+                params: &[],
+                smoothed: &[],
+            };
+            // The chain hands each stage its own parameters (see `ChainProcessor`)
+            self.processor.process(&mut ctx);
+        }
+        let elapsed = if bypassed { Duration::ZERO } else { start.elapsed() };
+
+        if let Some(ref monitoring) = self.monitoring {
+            monitoring.cpu.record(elapsed, frames, self.input.sample_rate());
+            // The block still holds the input until it's interleaved below
+            monitoring.push_taps(&self.block, &self.channel_buffers, frames);
+            monitoring.output_meter.record(&self.channel_buffers, frames);
+            monitoring.output_loudness.process(&self.channel_buffers, frames);
+        }
+
+        // Interleave the result back into the output block
+        for (ch, buffer) in self.channel_buffers.iter().enumerate() {
+            for (frame, sample) in buffer.iter().enumerate() {
+                self.block[frame * channels + ch] = *sample;
+            }
+        }
+
+        self.position += frames as u64;
     }
 }
 
 impl<S> Iterator for BlockProcessor<S>
 where
-    S: Source<Item = i16>,
+    S: Source<Item = f32>,
 {
-    type Item = i16;
+    type Item = f32;
 
-    fn next(&mut self) -> Option<i16> {
+    fn next(&mut self) -> Option<f32> {
         if !self.is_playing.load(Ordering::SeqCst) {
             return None;
         }
 
         if self.block_pos >= self.block.len() {
-            let mut new_block = Vec::with_capacity(self.block_size);
-            for _ in 0..self.block_size {
-                if let Some(sample) = self.input.next() {
-                    new_block.push(sample);
-                } else {
-                    break;
-                }
-            }
-
-            if new_block.is_empty() {
-                println!("End of audio stream reached. Total samples processed: {}", self.samples_processed);
+            self.apply_control_changes();
+            if self.fill_block() == 0 {
                 return None;
             }
 
-            self.block = new_block;
             self.process_buffer();
             self.block_pos = 0;
         }
@@ -252,21 +374,87 @@ where
 
 impl<S> Source for BlockProcessor<S>
 where
-    S: Source<Item = i16>,
+    S: Source<Item = f32>,
 {
     fn sample_rate(&self) -> u32 {
         self.input.sample_rate()
     }
 
     fn channels(&self) -> u16 {
-        self.input.channels()
+        self.channel_buffers.len() as u16
     }
 
     fn current_frame_len(&self) -> Option<usize> {
-        self.input.current_frame_len()
+        // Output is produced a block ahead of the input, so the input's frame
+        // boundaries don't line up with ours.
+        None
     }
 
-    fn total_duration(&self) -> Option<std::time::Duration> {
+    fn total_duration(&self) -> Option<Duration> {
         self.input.total_duration()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::ChainProcessor;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn bypassed_blocks_are_recorded_as_idle() {
+        let monitoring = Arc::new(Monitoring::new());
+        let blocks = BlockProcessor::new(
+            SamplesBuffer::new(2, 48000, vec![0.25f32; 2 * 4096]),
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(AtomicBool::new(true)),
+            Box::new(ChainProcessor::new()),
+            Arc::new(ProcessorControl::new(512)),
+        )
+        .with_monitoring(Arc::clone(&monitoring));
+        assert_eq!(blocks.count(), 2 * 4096);
+
+        let stats = monitoring.cpu.stats();
+        assert_eq!(stats.blocks, 8);
+        assert_eq!((stats.peak, stats.overruns), (0.0, 0));
+        assert_eq!(stats.histogram[0], 8);
+    }
+
+    /// Records how it's prepared and the length of every block.
+    struct Recorder {
+        prepared: Arc<Mutex<Vec<usize>>>,
+        blocks: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl AudioProcessor for Recorder {
+        fn prepare(&mut self, _sample_rate: u32, max_block: usize, _channels: usize) {
+            self.prepared.lock().unwrap().push(max_block);
+        }
+
+        fn process(&mut self, ctx: &mut ProcessContext) {
+            self.blocks.lock().unwrap().push(ctx.block_len);
+        }
+    }
+
+    #[test]
+    fn block_size_changes_dont_prepare_again() {
+        let (prepared, blocks) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(Vec::new())));
+        let control = Arc::new(ProcessorControl::new(1024));
+        let mut processor = BlockProcessor::new(
+            SamplesBuffer::new(1, 48000, vec![0.0f32; 8192]),
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(AtomicBool::new(false)),
+            Box::new(Recorder {
+                prepared: Arc::clone(&prepared),
+                blocks: Arc::clone(&blocks),
+            }),
+            Arc::clone(&control),
+        );
+        processor.by_ref().take(2048).count();
+        control.block_size.store(4096, Ordering::SeqCst);
+        processor.count();
+
+        assert_eq!(*prepared.lock().unwrap(), [MAX_BLOCK_SIZE]);
+        assert_eq!(*blocks.lock().unwrap(), [1024, 1024, 4096, 2048]);
+    }
+}
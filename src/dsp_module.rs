@@ -1,6 +1,8 @@
 // src/dsp_module.rs
 
-use crate::audio_app::{AudioAppBuilder, ParamValue};
+use std::sync::Arc;
+use crate::audio_app::AudioAppBuilder;
+use crate::process_context::{ProcessContext, ProcessFn};
 
 pub trait DSPModule {
     fn name(&self) -> &str;
@@ -8,3 +10,50 @@ pub trait DSPModule {
     /// Initializes the AudioAppBuilder with module-specific parameters and processing functions.
     fn initialize(&self) -> AudioAppBuilder;
 }
+
+/// A processor that owns its own state (filter memories, delay lines, envelopes).
+///
+/// `DspProcessor` creates a fresh instance for every loaded file, calls `prepare`
+/// before the first block and calls `reset` on Stop and seek. Blocks may be
+/// any length up to `max_block`, including after a block size change.
+pub trait AudioProcessor: Send {
+    /// Allocate and size internal state. `max_block` is the longest block
+    /// `process` will be handed, in frames; nothing may allocate past it.
+    fn prepare(&mut self, _sample_rate: u32, _max_block: usize, _channels: usize) {}
+
+    fn process(&mut self, ctx: &mut ProcessContext);
+
+    /// Clear internal state without reallocating.
+    fn reset(&mut self) {}
+
+    /// Delay the processor adds, in frames at the rate it was last prepared
+    /// with. Lookahead and linear-phase processors report it so offline
+    /// comparisons can line their output up with the input.
+    fn latency(&self) -> usize {
+        0
+    }
+}
+
+/// Creates a new processor instance for each playback or render.
+pub type ProcessorFactory = Arc<dyn Fn() -> Box<dyn AudioProcessor> + Send + Sync + 'static>;
+
+/// Stateless adapter so plain process functions can run as an `AudioProcessor`.
+pub struct FnProcessor {
+    process_fn: ProcessFn,
+}
+
+impl FnProcessor {
+    pub fn factory(process_fn: ProcessFn) -> ProcessorFactory {
+        Arc::new(move || {
+            Box::new(FnProcessor {
+                process_fn: Arc::clone(&process_fn),
+            }) as Box<dyn AudioProcessor>
+        })
+    }
+}
+
+impl AudioProcessor for FnProcessor {
+    fn process(&mut self, ctx: &mut ProcessContext) {
+        (self.process_fn)(ctx);
+    }
+}
@@ -0,0 +1,199 @@
+// src/dsp_modules/delay/mod.rs
+
+use std::f32::consts::PI;
+
+use crate::audio_app::{AudioAppBuilder, ParamScale, ParamUnit, ParamValue};
+use crate::dsp_module::{AudioProcessor, DSPModule};
+use crate::process_context::ProcessContext;
+use crate::smoothing::Smoothing;
+
+/// Longest delay time, and the range of the Time parameter.
+const MAX_TIME_MS: f32 = 2000.0;
+
+/// Parameter indices, in the order they're added in `initialize`.
+const TIME: usize = 0;
+const FEEDBACK: usize = 1;
+const DAMPING: usize = 2;
+const MIX: usize = 3;
+const PING_PONG: usize = 4;
+
+//  This is a library agnostic feedback delay with a one-pole low-pass in the loop.
+pub struct DelayProcessor {
+    sample_rate: f32,
+    /// One circular line per channel, all written at `write`.
+    lines: Vec<Vec<f32>>,
+    /// Low-pass memory of the feedback path, per channel.
+    damping: Vec<f32>,
+    /// Delayed samples of the current frame, so ping-pong can cross them.
+    taps: Vec<f32>,
+    write: usize,
+}
+
+impl DelayProcessor {
+    pub fn new() -> Self {
+        Self {
+            sample_rate: 48000.0,
+            lines: Vec::new(),
+            damping: Vec::new(),
+            taps: Vec::new(),
+            write: 0,
+        }
+    }
+
+    /// Linearly interpolated read `delay` samples behind the write position.
+    fn read(line: &[f32], write: usize, delay: f32) -> f32 {
+        let len = line.len();
+        let whole = delay as usize;
+        let frac = delay - whole as f32;
+        let a = line[(write + len - whole) % len];
+        let b = line[(write + 2 * len - whole - 1) % len];
+        a + frac * (b - a)
+    }
+}
+
+impl AudioProcessor for DelayProcessor {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize, channels: usize) {
+        self.sample_rate = sample_rate.max(1) as f32;
+        let len = (MAX_TIME_MS / 1000.0 * self.sample_rate) as usize + 2;
+        self.lines = vec![vec![0.0; len]; channels];
+        self.damping = vec![0.0; channels];
+        self.taps = vec![0.0; channels];
+        self.write = 0;
+    }
+
+    fn process(&mut self, ctx: &mut ProcessContext) {
+        let channels = ctx.num_channels().min(self.lines.len());
+        if channels == 0 {
+            return;
+        }
+        let len = self.lines[0].len();
+        let ping_pong = channels >= 2 && matches!(ctx.param(PING_PONG), Some(ParamValue::Boolean(true)));
+        let (params, smoothed) = (ctx.params, ctx.smoothed);
+        let at = |index: usize, i: usize| match smoothed.get(index) {
+            Some(ramp) => ramp[i],
+            None => params.get(index).map(ParamValue::as_f32).unwrap_or(0.0),
+        };
+        // Damping isn't smoothed, so one coefficient per block is enough
+        let damping_hz = at(DAMPING, 0).clamp(20.0, self.sample_rate * 0.49);
+        let damping = 1.0 - (-2.0 * PI * damping_hz / self.sample_rate).exp();
+
+        for i in 0..ctx.block_len {
+            let delay = (at(TIME, i) / 1000.0 * self.sample_rate).clamp(1.0, (len - 2) as f32);
+            let feedback = at(FEEDBACK, i);
+            let mix = at(MIX, i);
+
+            for ch in 0..channels {
+                let tap = Self::read(&self.lines[ch], self.write, delay);
+                self.damping[ch] += damping * (tap - self.damping[ch]);
+                self.taps[ch] = self.damping[ch];
+            }
+            for ch in 0..channels {
+                // Ping-pong feeds each channel's echo into its neighbour's line
+                let source = if ping_pong { (ch + 1) % channels } else { ch };
+                let dry = ctx.channels[ch][i];
+                self.lines[ch][self.write] = dry + feedback * self.taps[source];
+                ctx.channels[ch][i] = dry + mix * (self.taps[ch] - dry);
+            }
+            self.write = (self.write + 1) % len;
+        }
+    }
+
+    fn reset(&mut self) {
+        for line in self.lines.iter_mut() {
+            line.fill(0.0);
+        }
+        self.damping.fill(0.0);
+        self.write = 0;
+    }
+}
+
+
+
+//  This is an interface for the main audio app test app.
+pub struct DelayModule;
+
+impl DelayModule {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl DSPModule for DelayModule {
+    fn name(&self) -> &str {
+        "Delay"
+    }
+
+    fn initialize(&self) -> AudioAppBuilder {
+        AudioAppBuilder::new()
+            .add_param("Time", ParamValue::Number(250.0), 1.0, MAX_TIME_MS)
+            .with_unit(ParamUnit::Ms)
+            .with_scale(ParamScale::Logarithmic)
+            .with_smoothing(Smoothing::Linear { time_ms: 100.0 })
+            .add_param("Feedback", ParamValue::Number(0.35), 0.0, 0.95)
+            .with_unit(ParamUnit::Percent)
+            .with_smoothing(Smoothing::Linear { time_ms: 20.0 })
+            .add_param("Damping", ParamValue::Number(8000.0), 500.0, 20000.0)
+            .with_unit(ParamUnit::Hz)
+            .with_scale(ParamScale::Logarithmic)
+            .add_param("Mix", ParamValue::Number(0.3), 0.0, 1.0)
+            .with_unit(ParamUnit::Percent)
+            .with_smoothing(Smoothing::Linear { time_ms: 20.0 })
+            .add_param("Ping-pong", ParamValue::Boolean(false), 0.0, 1.0)
+            .add_factory_preset(
+                "Slapback",
+                &[
+                    ("Time", ParamValue::Number(90.0)),
+                    ("Feedback", ParamValue::Number(0.1)),
+                    ("Mix", ParamValue::Number(0.35)),
+                    ("Ping-pong", ParamValue::Boolean(false)),
+                ],
+            )
+            .add_factory_preset(
+                "Wide echo",
+                &[
+                    ("Time", ParamValue::Number(375.0)),
+                    ("Feedback", ParamValue::Number(0.5)),
+                    ("Damping", ParamValue::Number(4000.0)),
+                    ("Mix", ParamValue::Number(0.3)),
+                    ("Ping-pong", ParamValue::Boolean(true)),
+                ],
+            )
+            .set_processor(DelayProcessor::new)
+            .set_window_title("Delay")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn impulse_comes_back_after_delay_time() {
+        let mut delay = DelayProcessor::new();
+        delay.prepare(1000, 64, 1);
+        let mut channels = vec![vec![0.0f32; 64]];
+        channels[0][0] = 1.0;
+        let params = [
+            ParamValue::Number(10.0),
+            ParamValue::Number(0.5),
+            ParamValue::Number(20000.0),
+            ParamValue::Number(1.0),
+            ParamValue::Boolean(false),
+        ];
+        let mut ctx = ProcessContext {
+            channels: &mut channels,
+            sample_rate: 1000,
+            block_len: 64,
+            position: 0,
+            params: &params,
+            smoothed: &[],
+        };
+        delay.process(&mut ctx);
+        let out = &channels[0];
+        // Fully wet: the dry impulse is gone and echoes land every 10 samples
+        assert_eq!(out[0], 0.0);
+        assert!(out[10] > 0.3, "first echo {}", out[10]);
+        assert!(out[20] > 0.1 && out[20] < out[10], "second echo {}", out[20]);
+        assert!(out[5].abs() < 1e-6 && out[15].abs() < 1e-6);
+    }
+}
@@ -0,0 +1,290 @@
+// src/dsp_modules/filter/mod.rs
+
+use std::f32::consts::PI;
+
+use crate::audio_app::{AudioAppBuilder, ParamScale, ParamUnit, ParamValue};
+use crate::dsp_module::{AudioProcessor, DSPModule};
+use crate::process_context::ProcessContext;
+use crate::smoothing::Smoothing;
+
+/// Most biquads run in series, 48 dB/octave for the pass types.
+const MAX_STAGES: usize = 4;
+
+/// Samples between coefficient updates while a parameter glides.
+const UPDATE_INTERVAL: usize = 16;
+
+/// Parameter indices, in the order they're added in `initialize`.
+const TYPE: usize = 0;
+const CUTOFF: usize = 1;
+const Q: usize = 2;
+const GAIN: usize = 3;
+const ORDER: usize = 4;
+const MIX: usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FilterType {
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+    Peak,
+}
+
+impl FilterType {
+    pub const ALL: [FilterType; 5] = [
+        FilterType::LowPass,
+        FilterType::HighPass,
+        FilterType::BandPass,
+        FilterType::Notch,
+        FilterType::Peak,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FilterType::LowPass => "Low-pass",
+            FilterType::HighPass => "High-pass",
+            FilterType::BandPass => "Band-pass",
+            FilterType::Notch => "Notch",
+            FilterType::Peak => "Peak",
+        }
+    }
+
+    fn labels() -> Vec<&'static str> {
+        Self::ALL.iter().map(FilterType::label).collect()
+    }
+}
+
+/// Normalised biquad coefficients (`a0` divided out).
+#[derive(Clone, Copy, Debug)]
+pub struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    /// Audio EQ Cookbook (R. Bristow-Johnson) filters. `gain_db` only
+    /// affects `Peak`.
+    pub fn new(kind: FilterType, sample_rate: f32, cutoff: f32, q: f32, gain_db: f32) -> Self {
+        let cutoff = cutoff.clamp(1.0, sample_rate * 0.49);
+        let w0 = 2.0 * PI * cutoff / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q.max(0.01));
+        let a = 10f32.powf(gain_db / 40.0);
+
+        let (b0, b1, b2, a0, a1, a2) = match kind {
+            FilterType::LowPass => ((1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            FilterType::HighPass => ((1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            FilterType::BandPass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            FilterType::Notch => (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            FilterType::Peak => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+        };
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+}
+
+/// Memory of one biquad in transposed direct form II.
+#[derive(Clone, Copy, Default)]
+struct BiquadState {
+    z1: f32,
+    z2: f32,
+}
+
+impl BiquadState {
+    fn tick(&mut self, c: &Coefficients, x: f32) -> f32 {
+        let y = c.b0 * x + self.z1;
+        self.z1 = c.b1 * x - c.a1 * y + self.z2;
+        self.z2 = c.b2 * x - c.a2 * y;
+        y
+    }
+}
+
+//  This is a library agnostic filter: a cascade of identical biquads per channel.
+pub struct FilterProcessor {
+    sample_rate: f32,
+    /// `[channel][stage]`
+    state: Vec<[BiquadState; MAX_STAGES]>,
+}
+
+impl FilterProcessor {
+    pub fn new() -> Self {
+        Self {
+            sample_rate: 48000.0,
+            state: Vec::new(),
+        }
+    }
+
+    /// Runs `stages` biquads over `buffer`, blending `mix` of the result
+    /// back with the dry signal.
+    fn process_channel(&mut self, channel: usize, buffer: &mut [f32], c: &Coefficients, stages: usize, mix: f32) {
+        let state = &mut self.state[channel][..stages];
+        for sample in buffer.iter_mut() {
+            let dry = *sample;
+            let wet = state.iter_mut().fold(dry, |x, stage| stage.tick(c, x));
+            *sample = dry + mix * (wet - dry);
+        }
+    }
+}
+
+impl AudioProcessor for FilterProcessor {
+    fn prepare(&mut self, sample_rate: u32, _max_block: usize, channels: usize) {
+        self.sample_rate = sample_rate.max(1) as f32;
+        self.state = vec![[BiquadState::default(); MAX_STAGES]; channels];
+    }
+
+    fn process(&mut self, ctx: &mut ProcessContext) {
+        let kind = match ctx.param(TYPE) {
+            Some(ParamValue::Choice { index, .. }) => FilterType::ALL.get(*index).copied().unwrap_or(FilterType::LowPass),
+            _ => FilterType::LowPass,
+        };
+        let stages = match (kind, ctx.param(ORDER)) {
+            // Stacked peaks would multiply the gain, so they always use one
+            (FilterType::Peak, _) => 1,
+            (_, Some(ParamValue::Integer(order))) => (*order).clamp(1, MAX_STAGES as i32) as usize,
+            _ => 1,
+        };
+        let channels = ctx.num_channels().min(self.state.len());
+
+        let mut start = 0;
+        while start < ctx.block_len {
+            let end = (start + UPDATE_INTERVAL).min(ctx.block_len);
+            let at = |index: usize| match ctx.smoothed(index) {
+                Some(ramp) => ramp[start],
+                None => ctx.param(index).map(ParamValue::as_f32).unwrap_or(0.0),
+            };
+            let coefficients = Coefficients::new(kind, self.sample_rate, at(CUTOFF), at(Q), at(GAIN));
+            let mix = at(MIX);
+            for ch in 0..channels {
+                self.process_channel(ch, &mut ctx.channel_mut(ch)[start..end], &coefficients, stages, mix);
+            }
+            start = end;
+        }
+    }
+
+    fn reset(&mut self) {
+        for channel in self.state.iter_mut() {
+            *channel = [BiquadState::default(); MAX_STAGES];
+        }
+    }
+}
+
+
+
+//  This is an interface for the main audio app test app.
+pub struct FilterModule;
+
+impl FilterModule {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl DSPModule for FilterModule {
+    fn name(&self) -> &str {
+        "Filter"
+    }
+
+    fn initialize(&self) -> AudioAppBuilder {
+        let types = FilterType::labels();
+        AudioAppBuilder::new()
+            .add_param("Type", ParamValue::choice(&types, 0), 0.0, 0.0)
+            .add_param("Cutoff", ParamValue::Number(1000.0), 20.0, 20000.0)
+            .with_unit(ParamUnit::Hz)
+            .with_scale(ParamScale::Logarithmic)
+            .with_smoothing(Smoothing::Exponential { time_ms: 30.0 })
+            .add_param("Q", ParamValue::Number(0.707), 0.1, 20.0)
+            .with_scale(ParamScale::Skewed(2.5))
+            .with_smoothing(Smoothing::Linear { time_ms: 20.0 })
+            .add_param("Gain", ParamValue::Number(0.0), -24.0, 24.0)
+            .with_unit(ParamUnit::Db)
+            .with_smoothing(Smoothing::Linear { time_ms: 20.0 })
+            .add_param("Order", ParamValue::Integer(1), 1.0, MAX_STAGES as f32)
+            .add_param("Mix", ParamValue::Number(1.0), 0.0, 1.0)
+            .with_unit(ParamUnit::Percent)
+            .with_smoothing(Smoothing::Linear { time_ms: 20.0 })
+            .add_factory_preset(
+                "Rumble cut",
+                &[
+                    ("Type", ParamValue::choice(&types, 1)),
+                    ("Cutoff", ParamValue::Number(80.0)),
+                    ("Q", ParamValue::Number(0.707)),
+                    ("Order", ParamValue::Integer(2)),
+                    ("Mix", ParamValue::Number(1.0)),
+                ],
+            )
+            .add_factory_preset(
+                "Telephone",
+                &[
+                    ("Type", ParamValue::choice(&types, 2)),
+                    ("Cutoff", ParamValue::Number(1200.0)),
+                    ("Q", ParamValue::Number(0.9)),
+                    ("Order", ParamValue::Integer(2)),
+                    ("Mix", ParamValue::Number(1.0)),
+                ],
+            )
+            .add_factory_preset(
+                "Presence +6 dB",
+                &[
+                    ("Type", ParamValue::choice(&types, 4)),
+                    ("Cutoff", ParamValue::Number(3000.0)),
+                    ("Q", ParamValue::Number(1.0)),
+                    ("Gain", ParamValue::Number(6.0)),
+                    ("Mix", ParamValue::Number(1.0)),
+                ],
+            )
+            .set_processor(FilterProcessor::new)
+            .set_window_title("Filter")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Steady-state RMS of a sine at `freq` through one filter.
+    fn sine_gain(kind: FilterType, cutoff: f32, stages: usize, freq: f32) -> f32 {
+        let rate = 48000.0;
+        let c = Coefficients::new(kind, rate, cutoff, 0.707, 0.0);
+        let mut filter = FilterProcessor::new();
+        filter.prepare(48000, 4800, 1);
+        let mut buffer: Vec<f32> = (0..48000).map(|i| (2.0 * PI * freq * i as f32 / rate).sin()).collect();
+        filter.process_channel(0, &mut buffer, &c, stages, 1.0);
+        let tail = &buffer[24000..];
+        (tail.iter().map(|x| x * x).sum::<f32>() / tail.len() as f32).sqrt() * 2f32.sqrt()
+    }
+
+    #[test]
+    fn low_pass_is_3_db_down_at_cutoff() {
+        assert!((sine_gain(FilterType::LowPass, 1000.0, 1, 100.0) - 1.0).abs() < 0.01);
+        assert!((sine_gain(FilterType::LowPass, 1000.0, 1, 1000.0) - 0.5f32.sqrt()).abs() < 0.01);
+    }
+
+    #[test]
+    fn each_stage_adds_12_db_per_octave() {
+        let one = 20.0 * sine_gain(FilterType::LowPass, 500.0, 1, 2000.0).log10();
+        let two = 20.0 * sine_gain(FilterType::LowPass, 500.0, 2, 2000.0).log10();
+        assert!(one < -22.0 && one > -26.0, "one stage {} dB", one);
+        assert!((two - 2.0 * one).abs() < 1.0, "two stages {} dB", two);
+    }
+
+    #[test]
+    fn high_pass_blocks_low_frequencies() {
+        assert!(sine_gain(FilterType::HighPass, 1000.0, 1, 50.0) < 0.01);
+        assert!((sine_gain(FilterType::HighPass, 1000.0, 1, 10000.0) - 1.0).abs() < 0.02);
+    }
+}
@@ -1,6 +1,8 @@
 
 use crate::dsp_module::DSPModule;
 use crate::audio_app::{AudioAppBuilder, ParamValue};
+use crate::process_context::ProcessContext;
+use crate::smoothing::Smoothing;
 use std::sync::Arc;
 
 pub struct GainControlProcessor;
@@ -12,10 +14,16 @@ impl GainControlProcessor {
         Self
     }
 
-    pub fn process(&self, buffer: &mut [i16], gain: f32) {
+    pub fn process(&self, buffer: &mut [f32], gain: f32) {
         for sample in buffer.iter_mut() {
-            let new_sample = (*sample as f32 * gain).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
-            *sample = new_sample;
+            *sample *= gain;
+        }
+    }
+
+    /// Applies a per-sample gain, `gains` must be at least as long as `buffer`.
+    pub fn process_ramp(&self, buffer: &mut [f32], gains: &[f32]) {
+        for (sample, gain) in buffer.iter_mut().zip(gains) {
+            *sample *= gain;
         }
     }
 }
@@ -46,14 +54,27 @@ impl DSPModule for GainControlModule {
         // Clone the processor Arc to move into the closure
         let processor = Arc::clone(&self.processor);
 
-        let process_fn = move |buffer: &mut [i16], state: &[ParamValue]| {
-            let gain = if let ParamValue::Number(v) = state.get(0).unwrap_or(&ParamValue::Number(1.0)) { *v } else { 1.0 };
-            processor.process(buffer, gain);
+        let process_fn = move |ctx: &mut ProcessContext| {
+            if let Some(gains) = ctx.smoothed(0) {
+                for ch in 0..ctx.num_channels() {
+                    processor.process_ramp(ctx.channel_mut(ch), gains);
+                }
+                return;
+            }
+
+            let gain = if let Some(ParamValue::Number(v)) = ctx.param(0) { *v } else { 1.0 };
+            for ch in 0..ctx.num_channels() {
+                processor.process(ctx.channel_mut(ch), gain);
+            }
         };
 
         AudioAppBuilder::new()
             .add_param("Gain", ParamValue::Number(1.0), 0.0, 2.0)
-            .set_process_fn(Box::new(process_fn)) 
+            .with_smoothing(Smoothing::Linear { time_ms: 20.0 })
+            .add_factory_preset("Unity", &[("Gain", ParamValue::Number(1.0))])
+            .add_factory_preset("-6 dB", &[("Gain", ParamValue::Number(0.5))])
+            .add_factory_preset("+6 dB", &[("Gain", ParamValue::Number(1.995))])
+            .set_process_context_fn(process_fn)
             .set_window_title("Gain Control")
     }
 }
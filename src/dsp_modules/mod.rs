@@ -1,5 +1,22 @@
 // src/dsp_modules/mod.rs
 
+use std::sync::Arc;
+use crate::dsp_module::DSPModule;
+
+pub mod delay;
+pub mod filter;
 pub mod gain_control;
 
+pub use delay::DelayModule;
+pub use filter::FilterModule;
 pub use gain_control::GainControlModule;
+
+/// Every module available to the GUI and the command line.
+pub fn registry() -> Vec<Arc<dyn DSPModule>> {
+    vec![
+        Arc::new(GainControlModule::new()),
+        Arc::new(FilterModule::new()),
+        Arc::new(DelayModule::new()),
+        // Add more modules here
+    ]
+}
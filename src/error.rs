@@ -0,0 +1,78 @@
+// src/error.rs
+
+use eframe::egui;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong opening audio, starting playback or
+/// rendering. Shown in the error banner instead of taking the app down.
+#[derive(Debug)]
+pub enum DspError {
+    /// No usable audio output device.
+    OutputStream(rodio::StreamError),
+    /// The output device was found but a sink couldn't be created on it.
+    Sink(rodio::PlayError),
+    OpenFile { path: PathBuf, source: io::Error },
+    Decode { path: PathBuf, source: rodio::decoder::DecoderError },
+    WriteWav { path: PathBuf, source: hound::Error },
+    /// The module's builder never set a process function.
+    NoProcessFn { module: String },
+}
+
+impl fmt::Display for DspError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DspError::OutputStream(e) => write!(f, "no audio output device: {}", e),
+            DspError::Sink(e) => write!(f, "can't play on the audio output: {}", e),
+            DspError::OpenFile { path, source } => write!(f, "can't open {}: {}", path.display(), source),
+            DspError::Decode { path, source } => write!(f, "can't decode {}: {}", path.display(), source),
+            DspError::WriteWav { path, source } => write!(f, "can't write {}: {}", path.display(), source),
+            DspError::NoProcessFn { module } => write!(f, "module '{}' has no process function", module),
+        }
+    }
+}
+
+impl std::error::Error for DspError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DspError::OutputStream(e) => Some(e),
+            DspError::Sink(e) => Some(e),
+            DspError::OpenFile { source, .. } => Some(source),
+            DspError::Decode { source, .. } => Some(source),
+            DspError::WriteWav { source, .. } => Some(source),
+            DspError::NoProcessFn { .. } => None,
+        }
+    }
+}
+
+impl From<rodio::StreamError> for DspError {
+    fn from(e: rodio::StreamError) -> Self {
+        DspError::OutputStream(e)
+    }
+}
+
+impl From<rodio::PlayError> for DspError {
+    fn from(e: rodio::PlayError) -> Self {
+        DspError::Sink(e)
+    }
+}
+
+/// Red banner with the last error and a button to dismiss it.
+pub fn show_error_banner(ui: &mut egui::Ui, error: &mut Option<DspError>) {
+    let Some(message) = error.as_ref().map(|e| e.to_string()) else {
+        return;
+    };
+    egui::Frame::none()
+        .fill(egui::Color32::from_rgb(90, 25, 25))
+        .inner_margin(egui::Margin::same(6.0))
+        .rounding(3.0)
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(egui::Color32::from_rgb(255, 200, 200), format!("Error: {}", message));
+                if ui.small_button("Dismiss").clicked() {
+                    *error = None;
+                }
+            });
+        });
+}
@@ -0,0 +1,453 @@
+// src/generators.rs
+
+use eframe::egui;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Rate generators run at; the output device resamples if it differs.
+pub const GENERATOR_SAMPLE_RATE: u32 = 48000;
+
+const GENERATOR_CHANNELS: u16 = 2;
+
+/// Octave-spaced frequencies of the multitone signal.
+const MULTITONE_FREQS: [f64; 10] = [31.25, 62.5, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
+
+/// Every multitone frequency is a multiple of the lowest, so the sum repeats
+/// with its period.
+const MULTITONE_PERIOD_SECS: f64 = 1.0 / 31.25;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Waveform {
+    Sine,
+    LogSweep,
+    WhiteNoise,
+    PinkNoise,
+    Impulse,
+    Square,
+    Saw,
+    Multitone,
+}
+
+impl Waveform {
+    pub const ALL: [Waveform; 8] = [
+        Waveform::Sine,
+        Waveform::LogSweep,
+        Waveform::WhiteNoise,
+        Waveform::PinkNoise,
+        Waveform::Impulse,
+        Waveform::Square,
+        Waveform::Saw,
+        Waveform::Multitone,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Waveform::Sine => "Sine",
+            Waveform::LogSweep => "Log sweep",
+            Waveform::WhiteNoise => "White noise",
+            Waveform::PinkNoise => "Pink noise",
+            Waveform::Impulse => "Impulse",
+            Waveform::Square => "Square",
+            Waveform::Saw => "Saw",
+            Waveform::Multitone => "Multitone",
+        }
+    }
+
+    fn uses_frequency(&self) -> bool {
+        matches!(self, Waveform::Sine | Waveform::LogSweep | Waveform::Square | Waveform::Saw)
+    }
+}
+
+/// Generator settings shared with the running `Generator`, so frequency and
+/// level follow the panel while it plays.
+pub struct GeneratorControls {
+    /// Tone frequency, or the start of a sweep, in Hz.
+    frequency: AtomicU32,
+    /// Last frequency of a sweep, in Hz.
+    sweep_end: AtomicU32,
+    /// Length of one sweep, or the interval between impulses, in seconds.
+    period_secs: AtomicU32,
+    /// Peak level in dBFS.
+    level_db: AtomicU32,
+}
+
+fn load_f32(value: &AtomicU32) -> f32 {
+    f32::from_bits(value.load(Ordering::Relaxed))
+}
+
+fn store_f32(value: &AtomicU32, v: f32) {
+    value.store(v.to_bits(), Ordering::Relaxed);
+}
+
+impl GeneratorControls {
+    pub fn new() -> Self {
+        Self {
+            frequency: AtomicU32::new(1000.0f32.to_bits()),
+            sweep_end: AtomicU32::new(20000.0f32.to_bits()),
+            period_secs: AtomicU32::new(5.0f32.to_bits()),
+            level_db: AtomicU32::new((-12.0f32).to_bits()),
+        }
+    }
+
+    pub fn frequency(&self) -> f32 {
+        load_f32(&self.frequency)
+    }
+
+    pub fn sweep_end(&self) -> f32 {
+        load_f32(&self.sweep_end)
+    }
+
+    pub fn period_secs(&self) -> f32 {
+        load_f32(&self.period_secs)
+    }
+
+    pub fn level_db(&self) -> f32 {
+        load_f32(&self.level_db)
+    }
+}
+
+/// An endless test signal, the same on every channel. Plugs into
+/// `BlockProcessor` like a decoded file.
+pub struct Generator {
+    waveform: Waveform,
+    controls: Arc<GeneratorControls>,
+    sample_rate: u32,
+    channels: u16,
+    channel: u16,
+    frame: u64,
+    phase: f64,
+    tone_phases: [f64; MULTITONE_FREQS.len()],
+    /// Brings the multitone's actual peak to 1.0 for the chosen phases.
+    multitone_gain: f64,
+    pink: [f32; 7],
+    rng: StdRng,
+    current: f32,
+}
+
+impl Generator {
+    pub fn new(waveform: Waveform, controls: Arc<GeneratorControls>) -> Self {
+        let mut rng = StdRng::from_entropy();
+        // Random starting phases keep the multitone's crest factor down
+        let tone_phases = [0.0; MULTITONE_FREQS.len()].map(|_| rng.gen::<f64>());
+        let multitone_gain = 1.0 / multitone_peak(&tone_phases, GENERATOR_SAMPLE_RATE as f64);
+        Self {
+            waveform,
+            controls,
+            sample_rate: GENERATOR_SAMPLE_RATE,
+            channels: GENERATOR_CHANNELS,
+            channel: 0,
+            frame: 0,
+            phase: 0.0,
+            tone_phases,
+            multitone_gain,
+            pink: [0.0; 7],
+            rng,
+            current: 0.0,
+        }
+    }
+
+    fn next_value(&mut self) -> f32 {
+        let sample_rate = self.sample_rate as f64;
+        let frequency = self.controls.frequency().max(1.0) as f64;
+        let period_frames = ((self.controls.period_secs().max(0.01) as f64) * sample_rate) as u64;
+        let frame = self.frame;
+        self.frame += 1;
+
+        let value = match self.waveform {
+            Waveform::Sine => (TAU * self.advance(frequency / sample_rate)).sin(),
+            Waveform::Square => {
+                let step = (frequency / sample_rate).min(0.5);
+                let t = self.advance(step);
+                let naive = if t < 0.5 { 1.0 } else { -1.0 };
+                naive + poly_blep(t, step) - poly_blep((t + 0.5).fract(), step)
+            }
+            Waveform::Saw => {
+                let step = (frequency / sample_rate).min(0.5);
+                let t = self.advance(step);
+                2.0 * t - 1.0 - poly_blep(t, step)
+            }
+            Waveform::LogSweep => {
+                // Exponential sweep, restarted every period
+                let position = frame % period_frames;
+                if position == 0 {
+                    self.phase = 0.0;
+                }
+                let end = self.controls.sweep_end().max(1.0) as f64;
+                let t = position as f64 / period_frames as f64;
+                let current = frequency * (end / frequency).powf(t);
+                (TAU * self.advance(current / sample_rate)).sin()
+            }
+            Waveform::WhiteNoise => self.rng.gen_range(-1.0..=1.0),
+            Waveform::PinkNoise => self.pink_noise() as f64,
+            Waveform::Impulse => {
+                if frame.is_multiple_of(period_frames) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Waveform::Multitone => {
+                let sum = multitone_sum(&self.tone_phases, sample_rate, 0);
+                for (phase, freq) in self.tone_phases.iter_mut().zip(MULTITONE_FREQS) {
+                    *phase = (*phase + freq / sample_rate).fract();
+                }
+                // Peaks at the level, like the other waveforms
+                sum * self.multitone_gain
+            }
+        };
+
+        let gain = 10f32.powf(self.controls.level_db() / 20.0);
+        value as f32 * gain
+    }
+
+    /// Returns the current phase in 0..1 and moves it on by `step`.
+    fn advance(&mut self, step: f64) -> f64 {
+        let phase = self.phase;
+        self.phase = (self.phase + step).fract();
+        phase
+    }
+
+    /// Paul Kellett's refined pink filter over white noise.
+    fn pink_noise(&mut self) -> f32 {
+        let white: f32 = self.rng.gen_range(-1.0..=1.0);
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        // Roughly back to a -1..1 range
+        (pink * 0.11).clamp(-1.0, 1.0)
+    }
+}
+
+/// Correction that rounds off a unit step at phase 0 over the neighbouring
+/// samples (polyBLEP), so square and saw don't alias. `t` is the phase in
+/// 0..1 and `step` the phase increment per sample.
+fn poly_blep(t: f64, step: f64) -> f64 {
+    if t < step {
+        let t = t / step;
+        2.0 * t - t * t - 1.0
+    } else if t > 1.0 - step {
+        let t = (t - 1.0) / step;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Multitone value `frame` samples after `phases`, tones at or above
+/// Nyquist left out.
+fn multitone_sum(phases: &[f64], sample_rate: f64, frame: u64) -> f64 {
+    phases
+        .iter()
+        .zip(MULTITONE_FREQS)
+        .filter(|(_, freq)| *freq < sample_rate / 2.0)
+        .map(|(phase, freq)| (TAU * (phase + freq * frame as f64 / sample_rate)).sin())
+        .sum()
+}
+
+/// Largest magnitude of the multitone over one period.
+fn multitone_peak(phases: &[f64], sample_rate: f64) -> f64 {
+    let period = (MULTITONE_PERIOD_SECS * sample_rate).ceil() as u64;
+    (0..period)
+        .map(|frame| multitone_sum(phases, sample_rate, frame).abs())
+        .fold(0.0, f64::max)
+        .max(1e-9)
+}
+
+impl Iterator for Generator {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == 0 {
+            self.current = self.next_value();
+        }
+        self.channel = (self.channel + 1) % self.channels;
+        Some(self.current)
+    }
+}
+
+impl Source for Generator {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// The generator panel as saved in a session.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeneratorSettings {
+    pub waveform: Waveform,
+    pub frequency: f32,
+    pub sweep_end: f32,
+    pub period_secs: f32,
+    pub level_db: f32,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        GeneratorPanel::new().settings()
+    }
+}
+
+/// Test signal controls in the `AudioApp` panel.
+pub struct GeneratorPanel {
+    pub waveform: Waveform,
+    pub controls: Arc<GeneratorControls>,
+}
+
+impl GeneratorPanel {
+    pub fn new() -> Self {
+        Self {
+            waveform: Waveform::Sine,
+            controls: Arc::new(GeneratorControls::new()),
+        }
+    }
+
+    pub fn settings(&self) -> GeneratorSettings {
+        GeneratorSettings {
+            waveform: self.waveform,
+            frequency: self.controls.frequency(),
+            sweep_end: self.controls.sweep_end(),
+            period_secs: self.controls.period_secs(),
+            level_db: self.controls.level_db(),
+        }
+    }
+
+    /// Puts the panel back to saved `settings`.
+    pub fn restore(&mut self, settings: &GeneratorSettings) {
+        self.waveform = settings.waveform;
+        store_f32(&self.controls.frequency, settings.frequency);
+        store_f32(&self.controls.sweep_end, settings.sweep_end);
+        store_f32(&self.controls.period_secs, settings.period_secs);
+        store_f32(&self.controls.level_db, settings.level_db);
+    }
+
+    /// Returns true when the generator should (re)start, either because Play
+    /// was pressed or the waveform changed while `playing`.
+    pub fn show(&mut self, ui: &mut egui::Ui, playing: bool) -> bool {
+        let mut start = false;
+        ui.horizontal(|ui| {
+            let previous = self.waveform;
+            egui::ComboBox::from_id_source("generator_waveform")
+                .selected_text(self.waveform.label())
+                .show_ui(ui, |cb| {
+                    for waveform in Waveform::ALL {
+                        cb.selectable_value(&mut self.waveform, waveform, waveform.label());
+                    }
+                });
+            start |= playing && self.waveform != previous;
+            if ui.button("Play test signal").clicked() {
+                start = true;
+            }
+        });
+
+        let controls = &self.controls;
+        let mut level = controls.level_db();
+        if ui
+            .add(egui::Slider::new(&mut level, -60.0..=0.0).text("Level").suffix(" dBFS"))
+            .changed()
+        {
+            store_f32(&controls.level_db, level);
+        }
+
+        if self.waveform.uses_frequency() {
+            let label = if self.waveform == Waveform::LogSweep { "Start" } else { "Frequency" };
+            let mut frequency = controls.frequency();
+            if ui
+                .add(egui::Slider::new(&mut frequency, 10.0..=22000.0).logarithmic(true).text(label).suffix(" Hz"))
+                .changed()
+            {
+                store_f32(&controls.frequency, frequency);
+            }
+        }
+        if self.waveform == Waveform::LogSweep {
+            let mut end = controls.sweep_end();
+            if ui
+                .add(egui::Slider::new(&mut end, 10.0..=22000.0).logarithmic(true).text("End").suffix(" Hz"))
+                .changed()
+            {
+                store_f32(&controls.sweep_end, end);
+            }
+        }
+        if matches!(self.waveform, Waveform::LogSweep | Waveform::Impulse) {
+            let label = if self.waveform == Waveform::LogSweep { "Sweep length" } else { "Interval" };
+            let mut period = controls.period_secs();
+            if ui
+                .add(egui::Slider::new(&mut period, 0.1..=30.0).logarithmic(true).text(label).suffix(" s"))
+                .changed()
+            {
+                store_f32(&controls.period_secs, period);
+            }
+        }
+        start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(waveform: Waveform, frequency: f32, frames: usize) -> Vec<f32> {
+        let controls = Arc::new(GeneratorControls::new());
+        store_f32(&controls.frequency, frequency);
+        store_f32(&controls.level_db, 0.0);
+        let mut generator = Generator::new(waveform, controls);
+        (0..frames).map(|_| generator.next_value()).collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn multitone_peaks_at_the_level() {
+        let samples = render(Waveform::Multitone, 1000.0, 4 * 1536);
+        assert!((peak(&samples) - 1.0).abs() < 1e-3, "peak {}", peak(&samples));
+    }
+
+    #[test]
+    fn band_limited_saw_only_differs_near_the_reset() {
+        // 1 kHz at 48 kHz: 48 samples per cycle, corrected one sample either side
+        let samples = render(Waveform::Saw, 1000.0, 480);
+        for (i, sample) in samples.iter().enumerate() {
+            let t = (i as f64 * 1000.0 / 48000.0).fract();
+            let naive = (2.0 * t - 1.0) as f32;
+            let near_reset = !(1.0 / 48.0..=1.0 - 1.0 / 48.0).contains(&t);
+            if !near_reset {
+                assert!((sample - naive).abs() < 1e-4, "sample {}", i);
+            }
+        }
+        assert!(peak(&samples) <= 1.0);
+    }
+
+    #[test]
+    fn band_limited_square_has_no_dc() {
+        let samples = render(Waveform::Square, 1000.0, 4800);
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        assert!(mean.abs() < 1e-3, "mean {}", mean);
+        assert!(peak(&samples) <= 1.0 + 1e-6);
+    }
+}
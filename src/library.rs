@@ -0,0 +1,415 @@
+// src/library.rs
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use crate::presets::data_dir;
+
+/// Extensions the decoder is built with.
+pub const AUDIO_EXTENSIONS: [&str; 4] = ["wav", "mp3", "ogg", "flac"];
+
+const MAX_RECENT: usize = 10;
+
+/// Library folders and recent files, kept in `library.json` under the data dir.
+#[derive(Default, Serialize, Deserialize)]
+struct LibraryConfig {
+    folders: Vec<PathBuf>,
+    recent: Vec<PathBuf>,
+}
+
+/// Audio files found by recursively scanning a list of folders, plus the
+/// recently played files.
+pub struct Library {
+    config: LibraryConfig,
+    files: Vec<PathBuf>,
+    /// Scan running in the background; `files` is replaced when it finishes.
+    scan: Option<JoinHandle<Vec<PathBuf>>>,
+    browser: Option<FileBrowser>,
+    path_entry: String,
+    status: String,
+}
+
+pub fn is_audio_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+fn config_path() -> PathBuf {
+    data_dir().join("library.json")
+}
+
+/// The bundled test files, found relative to the source tree rather than the
+/// working directory.
+fn default_folder() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src").join("assets")
+}
+
+/// Audio files under `folders`, sorted. Each directory is visited once by
+/// its canonical path, so symlinks back up the tree don't loop.
+fn scan_folders(folders: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending: Vec<PathBuf> = folders.to_vec();
+    while let Some(folder) = pending.pop() {
+        let Ok(canonical) = fs::canonicalize(&folder) else {
+            continue;
+        };
+        if !visited.insert(canonical) {
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(&folder) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if is_audio_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort_by_key(|path| path.to_string_lossy().to_lowercase());
+    files.dedup();
+    files
+}
+
+impl Library {
+    /// Loads the saved folders, or the bundled assets folder on first run, and
+    /// starts scanning them in the background.
+    pub fn load() -> Self {
+        let config = fs::read_to_string(config_path())
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_else(|| LibraryConfig {
+                folders: vec![default_folder()],
+                recent: Vec::new(),
+            });
+
+        let mut library = Self {
+            config,
+            files: Vec::new(),
+            scan: None,
+            browser: None,
+            path_entry: String::new(),
+            status: String::new(),
+        };
+        library.rescan();
+        library
+    }
+
+    fn save(&mut self) {
+        let result = fs::create_dir_all(data_dir())
+            .and_then(|_| Ok(serde_json::to_string_pretty(&self.config)?))
+            .and_then(|text| fs::write(config_path(), text));
+        if let Err(e) = result {
+            self.status = format!("Couldn't save library settings: {}", e);
+        }
+    }
+
+    /// Starts a background scan of the library folders. A scan still running
+    /// is left to finish and its result dropped.
+    pub fn rescan(&mut self) {
+        let folders = self.config.folders.clone();
+        self.scan = Some(thread::spawn(move || scan_folders(&folders)));
+    }
+
+    pub fn is_scanning(&self) -> bool {
+        self.scan.is_some()
+    }
+
+    /// Takes the result of a finished scan. Returns true when `files` changed.
+    pub fn poll(&mut self) -> bool {
+        if !self.scan.as_ref().is_some_and(|scan| scan.is_finished()) {
+            return false;
+        }
+        self.finish_scan();
+        true
+    }
+
+    /// Blocks until the running scan, if any, is done.
+    pub fn wait_for_scan(&mut self) {
+        if self.scan.is_some() {
+            self.finish_scan();
+        }
+    }
+
+    fn finish_scan(&mut self) {
+        if let Some(scan) = self.scan.take() {
+            self.files = scan.join().unwrap_or_default();
+            self.status = format!("{} files in library.", self.files.len());
+        }
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn add_folder(&mut self, folder: PathBuf) {
+        if !self.config.folders.contains(&folder) {
+            self.config.folders.push(folder);
+            self.save();
+            self.rescan();
+        }
+    }
+
+    pub fn remove_folder(&mut self, index: usize) {
+        if index < self.config.folders.len() {
+            self.config.folders.remove(index);
+            self.save();
+            self.rescan();
+        }
+    }
+
+    /// Moves `path` to the top of the recent files.
+    pub fn add_recent(&mut self, path: &Path) {
+        self.config.recent.retain(|p| p != path);
+        self.config.recent.insert(0, path.to_path_buf());
+        self.config.recent.truncate(MAX_RECENT);
+        self.save();
+    }
+
+    /// Where the file browser opens: the folder of the most recent file, the
+    /// first library folder, or the home directory.
+    fn browse_start(&self) -> PathBuf {
+        self.config
+            .recent
+            .first()
+            .and_then(|path| path.parent())
+            .map(Path::to_path_buf)
+            .or_else(|| self.config.folders.first().cloned())
+            .or_else(|| std::env::var_os("HOME").map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from("/"))
+    }
+
+    /// `path` relative to the library folder it's in, or the full path.
+    pub fn label(&self, path: &Path) -> String {
+        self.config
+            .folders
+            .iter()
+            .find_map(|folder| path.strip_prefix(folder).ok())
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+
+    /// `path` if it exists, otherwise a library file with the same name. Lets
+    /// a session saved on another machine find its file on a different mount.
+    pub fn resolve(&mut self, path: &Path) -> Option<PathBuf> {
+        if path.is_file() {
+            return Some(path.to_path_buf());
+        }
+        let name = path.file_name()?;
+        self.wait_for_scan();
+        self.files.iter().find(|f| f.file_name() == Some(name)).cloned()
+    }
+
+    /// Folder list, path entry and recent files. Returns a file to play.
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<PathBuf> {
+        let mut chosen = None;
+
+        ui.label("Folders (scanned recursively)");
+        let mut remove = None;
+        for (index, folder) in self.config.folders.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(index);
+                }
+                ui.label(folder.display().to_string());
+            });
+        }
+        if let Some(index) = remove {
+            self.remove_folder(index);
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.path_entry)
+                    .hint_text("Folder or audio file path")
+                    .desired_width(260.0),
+            );
+            let path = PathBuf::from(self.path_entry.trim());
+            if ui.button("Add folder").clicked() {
+                if path.is_dir() {
+                    self.add_folder(path.clone());
+                } else {
+                    self.status = format!("Not a folder: {}", path.display());
+                }
+            }
+            if ui.button("Open file").clicked() {
+                if is_audio_file(&path) {
+                    chosen = Some(path.clone());
+                } else {
+                    self.status = format!("Not a supported audio file: {}", path.display());
+                }
+            }
+            if ui.button("Browse...").clicked() {
+                let start = if path.is_dir() { path.clone() } else { self.browse_start() };
+                self.browser = Some(FileBrowser::new(start));
+            }
+            if ui.add_enabled(!self.is_scanning(), egui::Button::new("Rescan")).clicked() {
+                self.rescan();
+            }
+        });
+
+        if let Some(browser) = self.browser.as_mut() {
+            match browser.show(ui) {
+                Some(BrowserAction::Open(path)) => {
+                    chosen = Some(path);
+                    self.browser = None;
+                }
+                Some(BrowserAction::AddFolder(folder)) => {
+                    self.add_folder(folder);
+                    self.browser = None;
+                }
+                Some(BrowserAction::Close) => self.browser = None,
+                None => {}
+            }
+        }
+
+        if !self.config.recent.is_empty() {
+            ui.label("Recent");
+            for path in &self.config.recent {
+                if ui.link(path.display().to_string()).clicked() {
+                    chosen = Some(path.clone());
+                }
+            }
+        }
+
+        ui.label("Drop files or folders onto the window to open or add them.");
+        if self.is_scanning() {
+            ui.label("Scanning library folders...");
+        } else if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+        chosen
+    }
+
+    /// Files and folders dropped onto the window this frame: folders are added
+    /// to the library, the first audio file is returned to play.
+    pub fn handle_dropped_files(&mut self, ctx: &egui::Context) -> Option<PathBuf> {
+        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+        let mut chosen = None;
+        for path in dropped.into_iter().filter_map(|file| file.path) {
+            if path.is_dir() {
+                self.add_folder(path);
+            } else if chosen.is_none() && is_audio_file(&path) {
+                chosen = Some(path);
+            }
+        }
+        chosen
+    }
+}
+
+enum BrowserAction {
+    Open(PathBuf),
+    AddFolder(PathBuf),
+    Close,
+}
+
+/// Minimal file picker: one directory at a time, listing its subfolders and
+/// the audio files the decoder supports.
+struct FileBrowser {
+    dir: PathBuf,
+    folders: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+impl FileBrowser {
+    fn new(dir: PathBuf) -> Self {
+        let mut browser = Self {
+            dir: PathBuf::new(),
+            folders: Vec::new(),
+            files: Vec::new(),
+        };
+        browser.enter(dir);
+        browser
+    }
+
+    /// Lists `dir`. Only done on navigation, not every frame.
+    fn enter(&mut self, dir: PathBuf) {
+        self.folders.clear();
+        self.files.clear();
+        for path in fs::read_dir(&dir).into_iter().flatten().flatten().map(|entry| entry.path()) {
+            let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if hidden {
+                continue;
+            }
+            if path.is_dir() {
+                self.folders.push(path);
+            } else if is_audio_file(&path) {
+                self.files.push(path);
+            }
+        }
+        let by_name = |path: &PathBuf| path.file_name().map(|n| n.to_string_lossy().to_lowercase());
+        self.folders.sort_by_key(by_name);
+        self.files.sort_by_key(by_name);
+        self.dir = dir;
+    }
+
+    fn show(&mut self, ui: &mut egui::Ui) -> Option<BrowserAction> {
+        let mut action = None;
+        let mut enter = None;
+        ui.group(|ui| {
+            ui.horizontal(|ui| {
+                if ui.add_enabled(self.dir.parent().is_some(), egui::Button::new("Up")).clicked() {
+                    enter = self.dir.parent().map(Path::to_path_buf);
+                }
+                if ui.button("Add this folder").clicked() {
+                    action = Some(BrowserAction::AddFolder(self.dir.clone()));
+                }
+                if ui.button("Close").clicked() {
+                    action = Some(BrowserAction::Close);
+                }
+            });
+            ui.label(self.dir.display().to_string());
+            egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                for folder in &self.folders {
+                    let name = folder.file_name().unwrap_or_default().to_string_lossy();
+                    if ui.selectable_label(false, format!("{}/", name)).clicked() {
+                        enter = Some(folder.clone());
+                    }
+                }
+                for file in &self.files {
+                    let name = file.file_name().unwrap_or_default().to_string_lossy();
+                    if ui.selectable_label(false, name).clicked() {
+                        action = Some(BrowserAction::Open(file.clone()));
+                    }
+                }
+                if self.folders.is_empty() && self.files.is_empty() {
+                    ui.label("No folders or audio files here.");
+                }
+            });
+        });
+        if let Some(dir) = enter {
+            self.enter(dir);
+        }
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn scan_survives_symlink_loops() {
+        let root = std::env::temp_dir().join(format!("dsp_tester_scan_{}", std::process::id()));
+        let nested = root.join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(nested.join("tone.wav"), b"").unwrap();
+        std::os::unix::fs::symlink(&root, nested.join("loop")).unwrap();
+
+        let files = scan_folders(&[root.clone(), root.join("a")]);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(files, vec![nested.join("tone.wav")]);
+    }
+}
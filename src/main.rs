@@ -1,31 +1,58 @@
-use eframe::{egui, NativeOptions};
-use std::sync::Arc;
-use crate::dsp_module::DSPModule;
+use eframe::egui;
 use crate::audio_app_manager::AudioAppManager;
+use crate::session::Session;
+use std::path::Path;
 
 // Import DSP modules
+mod ab;
+mod analysis;
+mod chain;
 mod dsp;
 mod dsp_module;
 mod dsp_modules;
+mod error;
+mod generators;
 mod audio_app;
 mod audio_app_manager;
-
-// Bring DSP modules into scope
-use dsp_modules::gain_control::GainControlModule;
+mod library;
+mod param_cell;
+mod presets;
+mod process_context;
+mod render;
+mod session;
+mod smoothing;
+mod transport;
+mod cli;
 
 fn main() -> Result<(), eframe::Error> {
     // Initialize DSP modules
-    let gain_module = Arc::new(GainControlModule::new());
-    // Add more modules as needed
-
-    // Create a vector of DSP modules
-    let modules: Vec<Arc<dyn DSPModule>> = vec![
-        gain_module,
-        // Add more modules here
-    ];
+    let modules = dsp_modules::registry();
 
-    // Initialize the AudioAppManager with the modules
-    let manager = AudioAppManager::new(modules);
+    // `--session FILE` opens the GUI in a saved state, any other arguments
+    // run the headless command line instead of the GUI
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let manager = match args.as_slice() {
+        [] => AudioAppManager::new(modules),
+        [flag, path] if flag == "--session" => {
+            let opened = Session::load(Path::new(path))
+                .map_err(|e| e.to_string())
+                .and_then(|session| AudioAppManager::from_session(modules, &session));
+            match opened {
+                Ok(manager) => manager,
+                Err(e) => {
+                    eprintln!("error: can't open session '{}': {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => {
+            if let Err(e) = cli::run(&args, &modules) {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+    };
 
     // Configure the viewport (window) settings
     let native_options = eframe::NativeOptions {
@@ -37,6 +64,6 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "DSP Library Manager",
         native_options,
-        Box::new(|cc| Box::new(manager)),
+        Box::new(|_cc| Box::new(manager)),
     )
 }
@@ -0,0 +1,45 @@
+// src/param_cell.rs
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::audio_app::ParamValue;
+
+/// A parameter value shared between the GUI and the audio thread without
+/// locking. The value is kept as the bits of an `f32` (integers and choice
+/// indices are exact up to 2^24); the variant is fixed when the cell is
+/// created.
+pub struct ParamCell {
+    /// Initial value, only used for its variant.
+    kind: ParamValue,
+    bits: AtomicU32,
+}
+
+impl ParamCell {
+    pub fn new(value: ParamValue) -> Self {
+        let bits = AtomicU32::new(value.as_f32().to_bits());
+        Self { kind: value, bits }
+    }
+
+    pub fn load(&self) -> ParamValue {
+        let mut value = self.kind.clone();
+        self.load_into(&mut value);
+        value
+    }
+
+    /// Overwrites `value` in place, so the audio thread never allocates.
+    pub fn load_into(&self, value: &mut ParamValue) {
+        let raw = f32::from_bits(self.bits.load(Ordering::Relaxed));
+        match value {
+            ParamValue::Number(v) => *v = raw,
+            ParamValue::Boolean(v) => *v = raw >= 0.5,
+            ParamValue::Integer(v) => *v = raw.round() as i32,
+            ParamValue::Choice { options, index } => {
+                *index = (raw.round().max(0.0) as usize).min(options.len().saturating_sub(1))
+            }
+        }
+    }
+
+    pub fn store(&self, value: &ParamValue) {
+        self.bits.store(value.as_f32().to_bits(), Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,451 @@
+// src/presets.rs
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::audio_app::{AudioParam, ParamValue};
+use crate::param_cell::ParamCell;
+
+/// A parameter value as written to a preset file. Choices are stored by
+/// option name so presets survive options being reordered.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PresetValue {
+    Boolean(bool),
+    Number(f32),
+    Choice(String),
+}
+
+impl From<&ParamValue> for PresetValue {
+    fn from(value: &ParamValue) -> Self {
+        match value {
+            ParamValue::Number(v) => PresetValue::Number(*v),
+            ParamValue::Boolean(v) => PresetValue::Boolean(*v),
+            ParamValue::Integer(v) => PresetValue::Number(*v as f32),
+            ParamValue::Choice { options, index } => {
+                PresetValue::Choice(options.get(*index).cloned().unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// Named parameter values for one module.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub module: String,
+    pub params: BTreeMap<String, PresetValue>,
+}
+
+impl Preset {
+    /// The current values of `params`.
+    pub fn capture(name: &str, module: &str, params: &[AudioParam]) -> Self {
+        Self {
+            name: name.to_string(),
+            module: module.to_string(),
+            params: params
+                .iter()
+                .map(|p| (p.name.clone(), PresetValue::from(&p.value.load())))
+                .collect(),
+        }
+    }
+
+    /// Sets every parameter the preset has a value for. Unknown names and
+    /// values of the wrong kind are skipped, numbers are clamped to range.
+    pub fn apply(&self, params: &[AudioParam]) {
+        for param in params {
+            self.apply_to(param, &param.value);
+        }
+    }
+
+    /// Like `apply` for one parameter, but sets `cell` rather than the
+    /// parameter's own value.
+    pub fn apply_to(&self, param: &AudioParam, cell: &ParamCell) {
+        let Some(stored) = self.params.get(&param.name) else {
+            return;
+        };
+        let mut value = cell.load();
+        let matched = match (&mut value, stored) {
+            (ParamValue::Number(v), PresetValue::Number(n)) => {
+                *v = n.clamp(param.min, param.max);
+                true
+            }
+            (ParamValue::Integer(v), PresetValue::Number(n)) => {
+                *v = (n.round() as i32).clamp(param.min as i32, param.max as i32);
+                true
+            }
+            (ParamValue::Boolean(v), PresetValue::Boolean(b)) => {
+                *v = *b;
+                true
+            }
+            (ParamValue::Choice { options, index }, PresetValue::Choice(name)) => {
+                match options.iter().position(|o| o == name) {
+                    Some(position) => {
+                        *index = position;
+                        true
+                    }
+                    None => false,
+                }
+            }
+            _ => false,
+        };
+        if matched {
+            cell.store(&value);
+        }
+    }
+}
+
+/// Where presets and other user files live: `$DSP_TESTER_HOME`, or
+/// `~/.dsp_tester` when it isn't set.
+pub fn data_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("DSP_TESTER_HOME") {
+        return PathBuf::from(dir);
+    }
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    home.join(".dsp_tester")
+}
+
+fn module_dir(module: &str) -> PathBuf {
+    data_dir().join("presets").join(file_stem(module))
+}
+
+fn preset_path(module: &str, name: &str) -> PathBuf {
+    module_dir(module).join(format!("{}.json", file_stem(name)))
+}
+
+/// `name` with anything that isn't safe in a file name replaced by '_'.
+pub fn file_stem(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || " -_+.".contains(c) { c } else { '_' })
+        .collect()
+}
+
+/// Names of the saved presets for `module`, sorted.
+pub fn list(module: &str) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(module_dir(module))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .filter_map(|text| serde_json::from_str::<Preset>(&text).ok())
+        .map(|preset| preset.name)
+        .collect();
+    names.sort_by_key(|name| name.to_lowercase());
+    names
+}
+
+pub fn load(module: &str, name: &str) -> io::Result<Preset> {
+    let text = fs::read_to_string(preset_path(module, name))?;
+    Ok(serde_json::from_str(&text)?)
+}
+
+/// Fails with `AlreadyExists` when saving `name` would replace a file, unless
+/// it holds the preset of that same name and `overwrite` is set. Names that
+/// differ only in characters `file_stem` replaces share a file, so they
+/// always collide.
+fn check_free(module: &str, name: &str, overwrite: bool) -> io::Result<()> {
+    let path = preset_path(module, name);
+    if !path.exists() {
+        return Ok(());
+    }
+    let stored = fs::read_to_string(&path)
+        .ok()
+        .and_then(|text| serde_json::from_str::<Preset>(&text).ok())
+        .map(|preset| preset.name);
+    let message = match stored {
+        Some(stored) if stored == name && overwrite => return Ok(()),
+        Some(stored) if stored == name => format!("'{}' already exists", name),
+        Some(stored) => format!("'{}' would replace '{}', which uses the same file name", name, stored),
+        None => format!("{} already exists", path.display()),
+    };
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, message))
+}
+
+/// Writes `preset`. An existing preset of the same name is only replaced
+/// when `overwrite` is set.
+pub fn save(preset: &Preset, overwrite: bool) -> io::Result<()> {
+    check_free(&preset.module, &preset.name, overwrite)?;
+    fs::create_dir_all(module_dir(&preset.module))?;
+    let text = serde_json::to_string_pretty(preset)?;
+    fs::write(preset_path(&preset.module, &preset.name), text)
+}
+
+pub fn delete(module: &str, name: &str) -> io::Result<()> {
+    fs::remove_file(preset_path(module, name))
+}
+
+/// Renames a saved preset. Fails rather than replace another preset.
+pub fn rename(module: &str, old_name: &str, new_name: &str) -> io::Result<()> {
+    let mut preset = load(module, old_name)?;
+    let same_file = preset_path(module, old_name) == preset_path(module, new_name);
+    if !same_file {
+        check_free(module, new_name, false)?;
+    }
+    preset.name = new_name.to_string();
+    fs::write(preset_path(module, new_name), serde_json::to_string_pretty(&preset)?)?;
+    if !same_file {
+        delete(module, old_name)?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, PartialEq)]
+enum Selection {
+    Factory(usize),
+    User(String),
+}
+
+/// Preset controls for one module in the chain panel.
+pub struct PresetBrowser {
+    module: String,
+    factory: Vec<Preset>,
+    user: Vec<String>,
+    selected: Option<Selection>,
+    name: String,
+    status: String,
+    /// Name the user was asked to confirm overwriting.
+    confirm_overwrite: Option<String>,
+}
+
+impl PresetBrowser {
+    pub fn new(module: &str, factory: Vec<Preset>) -> Self {
+        Self {
+            module: module.to_string(),
+            factory,
+            user: list(module),
+            selected: None,
+            name: String::new(),
+            status: String::new(),
+            confirm_overwrite: None,
+        }
+    }
+
+    /// Factory preset `name`, or a saved one if there's no factory preset by that name.
+    pub fn find(&self, name: &str) -> io::Result<Preset> {
+        match self.factory.iter().find(|p| p.name.eq_ignore_ascii_case(name)) {
+            Some(preset) => Ok(preset.clone()),
+            None => load(&self.module, name),
+        }
+    }
+
+    /// Factory presets ship with the module; a saved preset can't take their name.
+    fn check_not_factory(&self, name: &str) -> io::Result<()> {
+        if self.factory.iter().any(|p| p.name.eq_ignore_ascii_case(name)) {
+            let message = format!("'{}' is a factory preset", name);
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, message));
+        }
+        Ok(())
+    }
+
+    fn selected_label(&self) -> String {
+        match &self.selected {
+            Some(Selection::Factory(index)) => format!("Factory: {}", self.factory[*index].name),
+            Some(Selection::User(name)) => name.clone(),
+            None => "Select preset".to_string(),
+        }
+    }
+
+    fn report(&mut self, action: &str, result: io::Result<()>) {
+        self.status = match result {
+            Ok(()) => format!("{} '{}'.", action, self.name.trim()),
+            Err(e) => format!("{} failed: {}", action, e),
+        };
+        self.user = list(&self.module);
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, params: &[AudioParam]) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("preset")
+                .width(180.0)
+                .selected_text(self.selected_label())
+                .show_ui(ui, |cb| {
+                    for (index, preset) in self.factory.iter().enumerate() {
+                        let label = format!("Factory: {}", preset.name);
+                        cb.selectable_value(&mut self.selected, Some(Selection::Factory(index)), label);
+                    }
+                    for name in &self.user {
+                        cb.selectable_value(&mut self.selected, Some(Selection::User(name.clone())), name);
+                    }
+                });
+
+            if ui.add_enabled(self.selected.is_some(), egui::Button::new("Load")).clicked() {
+                let preset = match &self.selected {
+                    Some(Selection::Factory(index)) => Some(Ok(self.factory[*index].clone())),
+                    Some(Selection::User(name)) => Some(load(&self.module, name)),
+                    None => None,
+                };
+                match preset {
+                    Some(Ok(preset)) => {
+                        preset.apply(params);
+                        self.name = preset.name.clone();
+                        self.status = format!("Loaded '{}'.", preset.name);
+                    }
+                    Some(Err(e)) => self.status = format!("Load failed: {}", e),
+                    None => {}
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.name).hint_text("Preset name").desired_width(180.0));
+            let has_name = !self.name.trim().is_empty();
+            let user_selected = match &self.selected {
+                Some(Selection::User(name)) => Some(name.clone()),
+                _ => None,
+            };
+
+            if ui.add_enabled(has_name, egui::Button::new("Save")).clicked() {
+                let name = self.name.trim().to_string();
+                // Saving over the selected preset is an update, not a collision
+                let overwrite = user_selected.as_deref() == Some(name.as_str());
+                self.save(&name, params, overwrite);
+            }
+            if ui
+                .add_enabled(has_name && user_selected.is_some(), egui::Button::new("Rename"))
+                .clicked()
+            {
+                let new_name = self.name.trim().to_string();
+                let old_name = user_selected.as_deref().unwrap_or_default();
+                let result = self.check_not_factory(&new_name).and_then(|()| rename(&self.module, old_name, &new_name));
+                if result.is_ok() {
+                    self.selected = Some(Selection::User(new_name));
+                }
+                self.report("Renamed to", result);
+            }
+            if ui.add_enabled(user_selected.is_some(), egui::Button::new("Delete")).clicked() {
+                let name = user_selected.unwrap_or_default();
+                let result = delete(&self.module, &name);
+                if result.is_ok() {
+                    self.selected = None;
+                    self.name = name;
+                }
+                self.report("Deleted", result);
+            }
+        });
+
+        if let Some(name) = self.confirm_overwrite.clone() {
+            ui.horizontal(|ui| {
+                ui.label(format!("Replace the saved preset '{}'?", name));
+                if ui.button("Replace").clicked() {
+                    self.save(&name, params, true);
+                }
+                if ui.button("Cancel").clicked() {
+                    self.confirm_overwrite = None;
+                    self.status.clear();
+                }
+            });
+        } else if !self.status.is_empty() {
+            ui.label(&self.status);
+        }
+    }
+
+    fn save(&mut self, name: &str, params: &[AudioParam], overwrite: bool) {
+        self.confirm_overwrite = None;
+        let result = self
+            .check_not_factory(name)
+            .and_then(|()| save(&Preset::capture(name, &self.module, params), overwrite));
+        match &result {
+            Ok(()) => self.selected = Some(Selection::User(name.to_string())),
+            // Only a plain name clash can be confirmed; a shared file name can't
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && self.user.iter().any(|n| n == name) => {
+                self.confirm_overwrite = Some(name.to_string());
+            }
+            Err(_) => {}
+        }
+        self.report("Saved", result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_app::{ParamScale, ParamUnit};
+    use crate::smoothing::Smoothing;
+    use std::sync::{Arc, Once};
+
+    /// Points the data directory at a scratch folder. Every test uses its own
+    /// module name, so they can share it while running in parallel.
+    fn scratch_home() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            let dir = std::env::temp_dir().join(format!("dsp_tester_presets_{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            std::env::set_var("DSP_TESTER_HOME", dir);
+        });
+    }
+
+    fn gain(value: f32) -> Vec<AudioParam> {
+        vec![AudioParam {
+            name: "Gain".to_string(),
+            value: Arc::new(ParamCell::new(ParamValue::Number(value))),
+            min: -24.0,
+            max: 24.0,
+            smoothing: Smoothing::None,
+            unit: ParamUnit::Db,
+            scale: ParamScale::Linear,
+        }]
+    }
+
+    fn gain_of(preset: &Preset) -> f32 {
+        let params = gain(0.0);
+        preset.apply(&params);
+        params[0].value.load().as_f32()
+    }
+
+    #[test]
+    fn saved_presets_load_back() {
+        scratch_home();
+        let params = gain(-6.0);
+        save(&Preset::capture("Quiet", "round trip", &params), false).unwrap();
+
+        assert_eq!(list("round trip"), vec!["Quiet".to_string()]);
+        let loaded = load("round trip", "Quiet").unwrap();
+        assert_eq!(loaded.name, "Quiet");
+        assert_eq!(gain_of(&loaded), -6.0);
+    }
+
+    #[test]
+    fn existing_presets_are_only_replaced_when_confirmed() {
+        scratch_home();
+        save(&Preset::capture("Quiet", "confirm", &gain(-6.0)), false).unwrap();
+        save(&Preset::capture("Loud", "confirm", &gain(6.0)), false).unwrap();
+
+        let clash = rename("confirm", "Quiet", "Loud").unwrap_err();
+        assert_eq!(clash.kind(), io::ErrorKind::AlreadyExists);
+        let clash = save(&Preset::capture("Loud", "confirm", &gain(0.0)), false).unwrap_err();
+        assert_eq!(clash.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(gain_of(&load("confirm", "Quiet").unwrap()), -6.0);
+        assert_eq!(gain_of(&load("confirm", "Loud").unwrap()), 6.0);
+
+        let mut browser = PresetBrowser::new("confirm", Vec::new());
+        browser.save("Loud", &gain(3.0), false);
+        assert_eq!(browser.confirm_overwrite.as_deref(), Some("Loud"));
+        assert_eq!(gain_of(&load("confirm", "Loud").unwrap()), 6.0);
+        browser.save("Loud", &gain(3.0), true);
+        assert_eq!(browser.confirm_overwrite, None);
+        assert_eq!(gain_of(&load("confirm", "Loud").unwrap()), 3.0);
+    }
+
+    #[test]
+    fn factory_presets_cant_be_overwritten() {
+        scratch_home();
+        let factory = vec![Preset::capture("Unity", "factory", &gain(0.0))];
+        let mut browser = PresetBrowser::new("factory", factory);
+
+        browser.save("unity", &gain(12.0), true);
+        assert_eq!(browser.confirm_overwrite, None);
+        assert!(list("factory").is_empty());
+        assert_eq!(browser.check_not_factory("Unity").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(gain_of(&browser.find("Unity").unwrap()), 0.0);
+    }
+}
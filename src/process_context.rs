@@ -0,0 +1,142 @@
+// src/process_context.rs
+
+use std::sync::Arc;
+use crate::audio_app::ParamValue;
+use crate::dsp_module::{AudioProcessor, ProcessorFactory};
+
+/// Everything a module gets to see for one block of audio.
+///
+/// Channels are planar `f32` buffers in the -1.0..=1.0 range, each exactly
+/// `block_len` frames long.
+pub struct ProcessContext<'a> {
+    pub channels: &'a mut [Vec<f32>],
+    pub sample_rate: u32,
+    pub block_len: usize,
+    /// Frame index of the first frame in this block, counted from the start of playback.
+    pub position: u64,
+    /// The module's parameter values for this block, in declaration order.
+    pub params: &'a [ParamValue],
+    /// Per-sample values of each parameter for this block, indexed like
+    /// `params`. Numeric parameters follow their declared smoothing.
+    pub smoothed: &'a [Vec<f32>],
+}
+
+impl<'a> ProcessContext<'a> {
+    pub fn num_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    pub fn channel_mut(&mut self, index: usize) -> &mut [f32] {
+        &mut self.channels[index][..self.block_len]
+    }
+
+    pub fn param(&self, index: usize) -> Option<&'a ParamValue> {
+        self.params.get(index)
+    }
+
+    /// The smoothed ramp for parameter `index`, `block_len` samples long.
+    /// `None` when the caller doesn't provide ramps.
+    pub fn smoothed(&self, index: usize) -> Option<&'a [f32]> {
+        self.smoothed.get(index).map(|ramp| &ramp[..self.block_len])
+    }
+}
+
+/// The processing callback every module is driven through.
+pub type ProcessFn = Arc<dyn Fn(&mut ProcessContext) + Send + Sync + 'static>;
+
+/// The original interleaved `i16` callback signature.
+pub type LegacyProcessFn = Arc<dyn Fn(&mut [i16], &[ParamValue]) + Send + Sync + 'static>;
+
+/// Runs an interleaved `i16` callback as an `AudioProcessor`. The block is
+/// quantised to `i16` before the call and converted back afterwards, through
+/// a scratch buffer sized in `prepare`.
+pub struct LegacyProcessor {
+    process_fn: LegacyProcessFn,
+    interleaved: Vec<i16>,
+}
+
+impl AudioProcessor for LegacyProcessor {
+    fn prepare(&mut self, _sample_rate: u32, max_block: usize, channels: usize) {
+        self.interleaved = vec![0; max_block * channels];
+    }
+
+    fn process(&mut self, ctx: &mut ProcessContext) {
+        let channels = ctx.num_channels();
+        let len = ctx.block_len * channels;
+        if self.interleaved.len() < len {
+            // Only if the host skipped `prepare`
+            self.interleaved.resize(len, 0);
+        }
+        let interleaved = &mut self.interleaved[..len];
+
+        for (ch, buffer) in ctx.channels.iter().enumerate() {
+            for (frame, sample) in buffer[..ctx.block_len].iter().enumerate() {
+                interleaved[frame * channels + ch] = f32_to_i16(*sample);
+            }
+        }
+
+        (self.process_fn)(interleaved, ctx.params);
+
+        for (ch, buffer) in ctx.channels.iter_mut().enumerate() {
+            for (frame, sample) in buffer[..ctx.block_len].iter_mut().enumerate() {
+                *sample = i16_to_f32(interleaved[frame * channels + ch]);
+            }
+        }
+    }
+}
+
+/// Wraps an interleaved `i16` callback so existing modules keep working.
+pub fn adapt_legacy_process_fn(legacy: LegacyProcessFn) -> ProcessorFactory {
+    Arc::new(move || {
+        Box::new(LegacyProcessor {
+            process_fn: Arc::clone(&legacy),
+            interleaved: Vec::new(),
+        }) as Box<dyn AudioProcessor>
+    })
+}
+
+fn i16_to_f32(sample: i16) -> f32 {
+    sample as f32 / 32768.0
+}
+
+fn f32_to_i16(sample: f32) -> i16 {
+    (sample * 32768.0).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_i16_closure_runs_through_the_adapter() {
+        let gain = |buffer: &mut [i16], params: &[ParamValue]| {
+            let gain = params[0].as_f32();
+            for sample in buffer.iter_mut() {
+                *sample = (*sample as f32 * gain) as i16;
+            }
+        };
+        let mut processor = adapt_legacy_process_fn(Arc::new(gain))();
+        processor.prepare(48000, 64, 2);
+
+        let input: Vec<f32> = (0..64).map(|i| (i as f32 / 64.0 - 0.5) * 1.5).collect();
+        let mut channels = vec![input.clone(), input.iter().map(|x| -x).collect()];
+        let params = [ParamValue::Number(0.5)];
+        let mut ctx = ProcessContext {
+            channels: &mut channels,
+            sample_rate: 48000,
+            block_len: 64,
+            position: 0,
+            params: &params,
+            smoothed: &[],
+        };
+        processor.process(&mut ctx);
+
+        // Two truncations to i16, one on the way in and one in the closure
+        let lsb = 1.0 / 32768.0;
+        for (i, x) in input.iter().enumerate() {
+            let expected = 0.5 * x.clamp(-1.0, 1.0);
+            assert!((channels[0][i] - expected).abs() <= 2.0 * lsb, "left {} at {}", channels[0][i], i);
+            assert!((channels[1][i] + expected).abs() <= 2.0 * lsb, "right {} at {}", channels[1][i], i);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+// src/render.rs
+
+use rodio::buffer::SamplesBuffer;
+use rodio::Source;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::analysis::loudness::LoudnessReport;
+use crate::analysis::Monitoring;
+use crate::dsp::{decode_file, BlockProcessor, ProcessorControl};
+use crate::dsp_module::AudioProcessor;
+use crate::error::DspError;
+use crate::process_context::ProcessContext;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WavFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl WavFormat {
+    pub const ALL: [WavFormat; 3] = [WavFormat::Int16, WavFormat::Int24, WavFormat::Float32];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WavFormat::Int16 => "16-bit",
+            WavFormat::Int24 => "24-bit",
+            WavFormat::Float32 => "32-bit float",
+        }
+    }
+
+    fn spec(&self, channels: u16, sample_rate: u32) -> hound::WavSpec {
+        let (bits_per_sample, sample_format) = match self {
+            WavFormat::Int16 => (16, hound::SampleFormat::Int),
+            WavFormat::Int24 => (24, hound::SampleFormat::Int),
+            WavFormat::Float32 => (32, hound::SampleFormat::Float),
+        };
+        hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+}
+
+/// What an offline render produced.
+pub struct RenderSummary {
+    pub frames: u64,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub elapsed: Duration,
+    /// Loudness before and after processing. Only measured by `render_to_wav`.
+    pub input_loudness: Option<LoudnessReport>,
+    pub output_loudness: Option<LoudnessReport>,
+}
+
+impl RenderSummary {
+    pub fn audio_duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / self.sample_rate.max(1) as f64)
+    }
+
+    /// How many times faster than real time the render ran.
+    pub fn speed_factor(&self) -> f64 {
+        self.audio_duration().as_secs_f64() / self.elapsed.as_secs_f64().max(1e-9)
+    }
+}
+
+/// Runs `input` through `processor` block by block, as fast as possible, and
+/// writes the result to `output`. `processor` is normally a `ChainProcessor`
+/// bound to the parameter values to render with.
+pub fn render_to_wav(
+    input: &Path,
+    output: &Path,
+    processor: Box<dyn AudioProcessor>,
+    bypass: bool,
+    block_size: usize,
+    format: WavFormat,
+) -> Result<RenderSummary, DspError> {
+    let source = decode_file(input)?;
+    let channels = source.channels();
+    let sample_rate = source.sample_rate();
+
+    // Taps and meters are fed as during playback, for the loudness report
+    let monitoring = Arc::new(Monitoring::new());
+    let blocks = BlockProcessor::new(
+        source.convert_samples::<f32>(),
+        Arc::new(AtomicBool::new(true)),
+        Arc::new(AtomicBool::new(bypass)),
+        processor,
+        Arc::new(ProcessorControl::new(block_size)),
+    )
+    .with_monitoring(Arc::clone(&monitoring));
+
+    let write_error = |source| DspError::WriteWav {
+        path: output.to_path_buf(),
+        source,
+    };
+    let mut writer = hound::WavWriter::create(output, format.spec(channels, sample_rate)).map_err(write_error)?;
+    let start = Instant::now();
+    let mut samples: u64 = 0;
+
+    for sample in blocks {
+        match format {
+            WavFormat::Int16 => writer.write_sample(quantize(sample, 16) as i16),
+            WavFormat::Int24 => writer.write_sample(quantize(sample, 24)),
+            WavFormat::Float32 => writer.write_sample(sample),
+        }
+        .map_err(write_error)?;
+        samples += 1;
+    }
+    writer.finalize().map_err(write_error)?;
+    let input_loudness = monitoring.input_loudness.report();
+    let output_loudness = monitoring.output_loudness.report();
+
+    Ok(RenderSummary {
+        frames: samples / channels.max(1) as u64,
+        channels,
+        sample_rate,
+        elapsed: start.elapsed(),
+        input_loudness: Some(input_loudness),
+        output_loudness: Some(output_loudness),
+    })
+}
+
+/// Decodes `input` into memory and then times `iterations` passes of it
+/// through fresh processors from `make_processor`, so decoding cost is not
+/// included in the figures.
+pub fn bench<F>(
+    input: &Path,
+    make_processor: F,
+    bypass: bool,
+    block_size: usize,
+    iterations: usize,
+) -> Result<Vec<RenderSummary>, DspError>
+where
+    F: Fn() -> Box<dyn AudioProcessor>,
+{
+    let source = decode_file(input)?;
+    let channels = source.channels();
+    let sample_rate = source.sample_rate();
+    let samples: Vec<f32> = source.convert_samples::<f32>().collect();
+
+    let mut runs = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let blocks = BlockProcessor::new(
+            SamplesBuffer::new(channels, sample_rate, samples.clone()),
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(AtomicBool::new(bypass)),
+            make_processor(),
+            Arc::new(ProcessorControl::new(block_size)),
+        );
+
+        let start = Instant::now();
+        let processed = blocks.count() as u64;
+        runs.push(RenderSummary {
+            frames: processed / channels.max(1) as u64,
+            channels,
+            sample_rate,
+            elapsed: start.elapsed(),
+            input_loudness: None,
+            output_loudness: None,
+        });
+    }
+
+    Ok(runs)
+}
+
+fn quantize(sample: f32, bits: u32) -> i32 {
+    let scale = (1i64 << (bits - 1)) as f32;
+    (sample * scale).round().clamp(-scale, scale - 1.0) as i32
+}
+
+/// Runs a mono `signal` through `processor` on every one of `channels`, in
+/// blocks of `block_size`, and returns what came out of the first channel.
+/// Used by the offline measurements, which need the processor's output for a
+/// known input rather than a file.
+pub fn process_signal(
+    processor: &mut dyn AudioProcessor,
+    signal: &[f32],
+    channels: usize,
+    sample_rate: u32,
+    block_size: usize,
+) -> Vec<f32> {
+    let channels = channels.max(1);
+    let interleaved: Vec<f32> = signal.iter().flat_map(|sample| std::iter::repeat_n(*sample, channels)).collect();
+    let output = process_interleaved(processor, &interleaved, channels, sample_rate, block_size);
+    output.into_iter().step_by(channels).collect()
+}
+
+/// Runs interleaved `samples` through `processor` in blocks of `block_size`
+/// and returns the interleaved result.
+pub fn process_interleaved(
+    processor: &mut dyn AudioProcessor,
+    samples: &[f32],
+    channels: usize,
+    sample_rate: u32,
+    block_size: usize,
+) -> Vec<f32> {
+    let block_size = block_size.max(1);
+    let channels = channels.max(1);
+    processor.prepare(sample_rate, block_size, channels);
+
+    let mut buffers = vec![Vec::with_capacity(block_size); channels];
+    // A trailing partial frame is dropped, as `BlockProcessor` does
+    let samples = &samples[..samples.len() / channels * channels];
+    let mut output = vec![0.0; samples.len()];
+    let mut position = 0u64;
+    for (block, out) in samples.chunks(block_size * channels).zip(output.chunks_mut(block_size * channels)) {
+        let frames = block.len() / channels;
+        for (ch, buffer) in buffers.iter_mut().enumerate() {
+            buffer.clear();
+            buffer.extend(block.iter().skip(ch).step_by(channels));
+        }
+        let mut ctx = ProcessContext {
+            channels: &mut buffers,
+            sample_rate,
+            block_len: frames,
+            position,
+            params: &[],
+            smoothed: &[],
+        };
+        processor.process(&mut ctx);
+        for (ch, buffer) in buffers.iter().enumerate() {
+            for (frame, sample) in buffer[..frames].iter().enumerate() {
+                out[frame * channels + ch] = *sample;
+            }
+        }
+        position += frames as u64;
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantize_rounds_and_clips_to_the_integer_range() {
+        assert_eq!(quantize(0.0, 16), 0);
+        assert_eq!(quantize(0.5, 16), 16384);
+        assert_eq!(quantize(-1.0, 16), -32768);
+        // Full scale positive has no code of its own, so it clips one below
+        assert_eq!(quantize(1.0, 16), 32767);
+        assert_eq!(quantize(2.0, 24), (1 << 23) - 1);
+        assert_eq!(quantize(-2.0, 24), -(1 << 23));
+        assert_eq!(quantize(1.4 / 32768.0, 16), 1);
+        assert_eq!(quantize(1.6 / 32768.0, 16), 2);
+    }
+}
@@ -0,0 +1,296 @@
+// src/session.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::ab::AbSettings;
+use crate::analysis::null_test::NullTestSettings;
+use crate::analysis::response::ResponseSettings;
+use crate::analysis::scope::ScopeSettings;
+use crate::analysis::spectrogram::SpectrogramSettings;
+use crate::analysis::spectrum::SpectrumSettings;
+use crate::analysis::stereo::StereoSettings;
+use crate::analysis::thd::DistortionSettings;
+use crate::chain::{ChainSlot, ChainSnapshot};
+use crate::dsp::BLOCK_SIZES;
+use crate::dsp_module::DSPModule;
+use crate::generators::GeneratorSettings;
+use crate::param_cell::ParamCell;
+use crate::presets::{Preset, PresetValue};
+use crate::transport::LoopSettings;
+
+/// One module of a saved chain.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionSlot {
+    pub module: String,
+    #[serde(default)]
+    pub bypass: bool,
+    #[serde(default)]
+    pub params: BTreeMap<String, PresetValue>,
+}
+
+impl SessionSlot {
+    pub fn capture(slot: &ChainSlot) -> Self {
+        Self {
+            module: slot.module_name.clone(),
+            bypass: slot.bypass.load(Ordering::SeqCst),
+            params: Preset::capture("", &slot.module_name, &slot.params).params,
+        }
+    }
+
+    /// `slot` with `values` and `bypass` in place of its live settings.
+    fn capture_frozen(slot: &ChainSlot, values: &[Arc<ParamCell>], bypass: bool) -> Self {
+        Self {
+            module: slot.module_name.clone(),
+            bypass,
+            params: slot
+                .params
+                .iter()
+                .zip(values)
+                .map(|(param, value)| (param.name.clone(), PresetValue::from(&value.load())))
+                .collect(),
+        }
+    }
+
+    fn preset(&self) -> Preset {
+        Preset {
+            name: String::new(),
+            module: self.module.clone(),
+            params: self.params.clone(),
+        }
+    }
+}
+
+/// Everything needed to bring the playground back to the same state: the
+/// chain with its parameters, the input and loop region, block size, bypass,
+/// the A/B comparison and the analyzer settings. Saved as JSON so it can be
+/// attached to a bug report.
+///
+/// Left out on purpose: the playback position, readings and measurement
+/// results, which are taken again once it plays, the ABX test, and the
+/// output paths of renders and exports. The level and loudness meters have
+/// no settings to save.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Session {
+    /// Module shown in the manager's module selector.
+    pub selected_module: Option<String>,
+    pub chain: Vec<SessionSlot>,
+    pub audio_file: Option<PathBuf>,
+    /// The test signal was playing rather than `audio_file`.
+    #[serde(default)]
+    pub play_generator: bool,
+    #[serde(default)]
+    pub generator: GeneratorSettings,
+    #[serde(default)]
+    pub loop_region: LoopSettings,
+    pub block_size: usize,
+    #[serde(default)]
+    pub bypass: bool,
+    #[serde(default)]
+    pub ab: AbSettings,
+    /// Parameter snapshot heard as A, one entry per chain slot, or `None`
+    /// when A is the dry signal.
+    #[serde(default)]
+    pub ab_reference: Option<Vec<SessionSlot>>,
+    #[serde(default)]
+    pub spectrum: SpectrumSettings,
+    #[serde(default)]
+    pub scope: ScopeSettings,
+    #[serde(default)]
+    pub spectrogram: SpectrogramSettings,
+    #[serde(default)]
+    pub stereo: StereoSettings,
+    #[serde(default)]
+    pub response: ResponseSettings,
+    #[serde(default)]
+    pub distortion: DistortionSettings,
+    #[serde(default)]
+    pub null_test: NullTestSettings,
+}
+
+impl Session {
+    /// Reads a session, refusing block sizes the playground doesn't offer.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let session: Self = serde_json::from_str(&text)?;
+        if !BLOCK_SIZES.contains(&session.block_size) {
+            let message = format!("block size {} isn't one of {:?}", session.block_size, BLOCK_SIZES);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        Ok(session)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Creates the saved chain from `modules` with its parameter values and
+    /// bypass switches restored.
+    pub fn build_chain(&self, modules: &[Arc<dyn DSPModule>]) -> Result<Vec<ChainSlot>, String> {
+        self.chain
+            .iter()
+            .map(|saved| {
+                let module = modules
+                    .iter()
+                    .find(|m| m.name() == saved.module)
+                    .ok_or_else(|| format!("unknown module '{}'", saved.module))?;
+                let slot = module.initialize().into_slot(module.name()).map_err(|e| e.to_string())?;
+                saved.preset().apply(&slot.params);
+                slot.bypass.store(saved.bypass, Ordering::SeqCst);
+                Ok(slot)
+            })
+            .collect()
+    }
+
+    /// `snapshot` of `slots` in the form it's saved in.
+    pub fn capture_reference(slots: &[ChainSlot], snapshot: &ChainSnapshot) -> Vec<SessionSlot> {
+        slots
+            .iter()
+            .zip(snapshot.slots())
+            .map(|(slot, (values, bypass))| SessionSlot::capture_frozen(slot, values, bypass))
+            .collect()
+    }
+
+    /// The saved A side as a snapshot of `slots`, the chain built from this
+    /// session. `None` if there is none or it was saved for another chain.
+    pub fn reference(&self, slots: &[ChainSlot]) -> Option<ChainSnapshot> {
+        let saved = self.ab_reference.as_ref()?;
+        let same_chain = saved.len() == slots.len() && saved.iter().zip(slots).all(|(s, slot)| s.module == slot.module_name);
+        if !same_chain {
+            return None;
+        }
+        let params = saved
+            .iter()
+            .zip(slots)
+            .map(|(saved, slot)| {
+                let values = slot.param_snapshot();
+                let preset = saved.preset();
+                for (param, value) in slot.params.iter().zip(&values) {
+                    preset.apply_to(param, value);
+                }
+                values
+            })
+            .collect();
+        Some(ChainSnapshot::from_parts(params, saved.iter().map(|s| s.bypass).collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::null_test::NullSide;
+    use crate::analysis::response::{ResponseView, Stimulus};
+    use crate::analysis::spectrogram::SpectrogramMode;
+    use crate::analysis::stereo::ScopeMode;
+    use crate::analysis::thd::DistortionView;
+    use crate::audio_app::ParamValue;
+    use crate::dsp_modules::registry;
+    use crate::generators::Waveform;
+
+    fn set(slot: &ChainSlot, name: &str, value: ParamValue) {
+        slot.params.iter().find(|p| p.name == name).unwrap().value.store(&value);
+    }
+
+    fn values(slot_params: &[Arc<ParamCell>]) -> Vec<f32> {
+        slot_params.iter().map(|cell| cell.load().as_f32()).collect()
+    }
+
+    /// A chain and settings that differ from the defaults wherever they can.
+    fn edited_session(chain: &[ChainSlot]) -> Session {
+        set(&chain[0], "Gain", ParamValue::Number(0.5));
+        set(&chain[1], "Feedback", ParamValue::Number(0.6));
+        let reference = ChainSnapshot::capture(chain);
+        set(&chain[0], "Gain", ParamValue::Number(1.5));
+        set(&chain[1], "Ping-pong", ParamValue::Boolean(true));
+        chain[1].bypass.store(true, Ordering::SeqCst);
+
+        Session {
+            selected_module: Some("Delay".to_string()),
+            chain: chain.iter().map(SessionSlot::capture).collect(),
+            audio_file: Some(PathBuf::from("drums.wav")),
+            play_generator: true,
+            generator: GeneratorSettings {
+                waveform: Waveform::LogSweep,
+                frequency: 40.0,
+                sweep_end: 16000.0,
+                period_secs: 2.5,
+                level_db: -20.0,
+            },
+            loop_region: LoopSettings { enabled: true, start: 48000, end: 96000 },
+            block_size: 2048,
+            bypass: true,
+            ab: AbSettings { b_selected: false, level_match: true, crossfade_ms: 50.0 },
+            ab_reference: Some(Session::capture_reference(chain, &reference)),
+            spectrum: SpectrumSettings { fft_size: 8192, peak_hold: true, ..Default::default() },
+            scope: ScopeSettings { ms_per_div: 5.0, channel: Some(1), zoom: 4.0, ..Default::default() },
+            spectrogram: SpectrogramSettings {
+                mode: SpectrogramMode::Difference,
+                floor_db: -90.0,
+                ..Default::default()
+            },
+            stereo: StereoSettings { mode: ScopeMode::Lissajous, show_input: true, ..Default::default() },
+            response: ResponseSettings {
+                stimulus: Stimulus::Impulse,
+                size: 8192,
+                target: Some(1),
+                view: ResponseView::Phase,
+            },
+            distortion: DistortionSettings { frequency: 100.0, view: DistortionView::Transfer, ..Default::default() },
+            null_test: NullTestSettings {
+                side_a: NullSide::Reference,
+                side_b: NullSide::Slot(1),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn scratch_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dsp_tester_{}_{}.json", name, std::process::id()))
+    }
+
+    fn gain_and_delay(modules: &[Arc<dyn DSPModule>]) -> Vec<ChainSlot> {
+        ["Gain Control", "Delay"]
+            .iter()
+            .map(|name| modules.iter().find(|m| m.name() == *name).unwrap())
+            .map(|module| module.initialize().into_slot(module.name()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn sessions_round_trip() {
+        let modules = registry();
+        let chain = gain_and_delay(&modules);
+        let session = edited_session(&chain);
+
+        let path = scratch_file("session_round_trip");
+        session.save(&path).unwrap();
+        let loaded = Session::load(&path);
+        let _ = fs::remove_file(&path);
+        let loaded = loaded.unwrap();
+        assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&session).unwrap());
+
+        let restored = loaded.build_chain(&modules).unwrap();
+        for (original, restored) in chain.iter().zip(&restored) {
+            assert_eq!(values(&restored.param_values()), values(&original.param_values()));
+            assert_eq!(restored.bypass.load(Ordering::SeqCst), original.bypass.load(Ordering::SeqCst));
+        }
+        let reference = loaded.reference(&restored).unwrap();
+        let frozen: Vec<_> = reference.slots().map(|(params, bypass)| (values(params), bypass)).collect();
+        assert_eq!(frozen, vec![(vec![0.5], false), (vec![250.0, 0.6, 8000.0, 0.3, 0.0], false)]);
+    }
+
+    #[test]
+    fn unknown_block_sizes_are_refused() {
+        let session = Session { block_size: 1000, ..edited_session(&gain_and_delay(&registry())) };
+        let path = scratch_file("session_block_size");
+        session.save(&path).unwrap();
+        let loaded = Session::load(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
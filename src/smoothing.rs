@@ -0,0 +1,190 @@
+// src/smoothing.rs
+
+/// How a numeric parameter moves towards a new value, declared per parameter
+/// with `AudioAppBuilder::with_smoothing`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Smoothing {
+    /// Jump to the new value at the next block boundary.
+    None,
+    /// Reach the new value in a straight line over `time_ms`.
+    Linear { time_ms: f32 },
+    /// One-pole glide with a time constant of `time_ms`.
+    Exponential { time_ms: f32 },
+}
+
+/// Distance from the target, relative to its size, at which an exponential
+/// glide snaps to it. Well above f32 rounding even for Hz-scale values.
+const SETTLED: f32 = 1e-6;
+
+/// Turns block-rate parameter changes into a per-sample ramp.
+pub struct ParamSmoother {
+    smoothing: Smoothing,
+    sample_rate: f32,
+    current: f32,
+    target: f32,
+    step: f32,
+    steps_left: usize,
+    coefficient: f32,
+}
+
+impl ParamSmoother {
+    pub fn new(smoothing: Smoothing, initial: f32) -> Self {
+        let mut smoother = Self {
+            smoothing,
+            sample_rate: 48000.0,
+            current: initial,
+            target: initial,
+            step: 0.0,
+            steps_left: 0,
+            coefficient: 0.0,
+        };
+        smoother.prepare(48000);
+        smoother
+    }
+
+    pub fn prepare(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate.max(1) as f32;
+        self.coefficient = match self.smoothing {
+            Smoothing::Exponential { time_ms } if time_ms > 0.0 => {
+                (-1.0 / (time_ms / 1000.0 * self.sample_rate)).exp()
+            }
+            _ => 0.0,
+        };
+        self.reset();
+    }
+
+    /// Snap to the target, dropping any ramp in progress.
+    pub fn reset(&mut self) {
+        self.current = self.target;
+        self.steps_left = 0;
+    }
+
+    pub fn set_target(&mut self, target: f32) {
+        if target == self.target {
+            return;
+        }
+        self.target = target;
+        match self.smoothing {
+            Smoothing::Linear { time_ms } if time_ms > 0.0 => {
+                self.steps_left = ((time_ms / 1000.0 * self.sample_rate) as usize).max(1);
+                self.step = (self.target - self.current) / self.steps_left as f32;
+            }
+            Smoothing::Exponential { .. } if self.coefficient > 0.0 => {}
+            _ => self.current = target,
+        }
+    }
+
+    /// Writes the next `out.len()` per-sample values.
+    pub fn fill(&mut self, out: &mut [f32]) {
+        match self.smoothing {
+            Smoothing::Linear { .. } => {
+                for value in out.iter_mut() {
+                    if self.steps_left > 0 {
+                        self.current += self.step;
+                        self.steps_left -= 1;
+                        if self.steps_left == 0 {
+                            self.current = self.target;
+                        }
+                    }
+                    *value = self.current;
+                }
+            }
+            Smoothing::Exponential { .. } if self.coefficient > 0.0 && self.current != self.target => {
+                let settled = SETTLED * self.target.abs().max(1.0);
+                for value in out.iter_mut() {
+                    let next = self.target + (self.current - self.target) * self.coefficient;
+                    // Settle instead of creeping towards the target forever. Near
+                    // a large target a step can round back to the same value, so
+                    // stalling counts as settled too
+                    self.current = if next == self.current || (next - self.target).abs() <= settled {
+                        self.target
+                    } else {
+                        next
+                    };
+                    *value = self.current;
+                }
+            }
+            _ => out.fill(self.current),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp(smoother: &mut ParamSmoother, len: usize) -> Vec<f32> {
+        let mut out = vec![0.0; len];
+        smoother.fill(&mut out);
+        out
+    }
+
+    #[test]
+    fn linear_reaches_target_in_time() {
+        let mut smoother = ParamSmoother::new(Smoothing::Linear { time_ms: 10.0 }, 0.0);
+        smoother.prepare(1000);
+        smoother.set_target(1.0);
+        let out = ramp(&mut smoother, 12);
+        for (i, value) in out.iter().take(10).enumerate() {
+            assert!((value - (i + 1) as f32 / 10.0).abs() < 1e-6, "sample {} is {}", i, value);
+        }
+        assert_eq!(&out[9..], &[1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn linear_retarget_starts_from_current_value() {
+        let mut smoother = ParamSmoother::new(Smoothing::Linear { time_ms: 10.0 }, 0.0);
+        smoother.prepare(1000);
+        smoother.set_target(1.0);
+        ramp(&mut smoother, 5);
+        smoother.set_target(0.0);
+        let out = ramp(&mut smoother, 10);
+        assert!((out[0] - 0.45).abs() < 1e-6);
+        assert_eq!(out[9], 0.0);
+    }
+
+    #[test]
+    fn exponential_follows_one_pole_curve() {
+        let mut smoother = ParamSmoother::new(Smoothing::Exponential { time_ms: 10.0 }, 0.0);
+        smoother.prepare(1000);
+        smoother.set_target(1.0);
+        let out = ramp(&mut smoother, 10);
+        // One time constant covers 1 - 1/e of the distance
+        assert!((out[9] - (1.0 - (-1.0f32).exp())).abs() < 1e-4, "got {}", out[9]);
+        assert!(out.windows(2).all(|w| w[1] > w[0]));
+        let tail = ramp(&mut smoother, 1000);
+        assert_eq!(*tail.last().unwrap(), 1.0);
+    }
+
+    #[test]
+    fn zero_time_jumps_immediately() {
+        for smoothing in [
+            Smoothing::None,
+            Smoothing::Linear { time_ms: 0.0 },
+            Smoothing::Exponential { time_ms: 0.0 },
+        ] {
+            let mut smoother = ParamSmoother::new(smoothing, 0.0);
+            smoother.prepare(48000);
+            smoother.set_target(0.5);
+            assert_eq!(ramp(&mut smoother, 4), vec![0.5; 4], "{:?}", smoothing);
+        }
+    }
+
+    #[test]
+    fn exponential_works_before_prepare() {
+        let mut smoother = ParamSmoother::new(Smoothing::Exponential { time_ms: 5.0 }, 0.0);
+        smoother.set_target(1.0);
+        let out = ramp(&mut smoother, 4);
+        assert!(out[0] > 0.0 && out[3] < 1.0);
+    }
+
+    #[test]
+    fn exponential_settles_exactly_on_large_targets() {
+        let mut smoother = ParamSmoother::new(Smoothing::Exponential { time_ms: 30.0 }, 20.0);
+        smoother.set_target(1000.0);
+        // 30 ms time constant: well within a second
+        let out = ramp(&mut smoother, 48000);
+        assert_eq!(out[out.len() - 1], 1000.0);
+        assert_eq!(smoother.current, smoother.target);
+    }
+}
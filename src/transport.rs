@@ -0,0 +1,326 @@
+// src/transport.rs
+
+use eframe::egui::{self, Color32, Pos2, Rect, Sense, Stroke};
+use parking_lot::{Mutex, MutexGuard};
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::analysis::scope::{waveform_overview, OVERVIEW_BUCKETS};
+use crate::dsp::decode_file;
+use crate::error::DspError;
+
+pub const NO_SEEK: u64 = u64::MAX;
+
+/// A file decoded into memory, shared by every `TransportSource` that plays
+/// it so restarting playback doesn't decode it again.
+pub struct DecodedAudio {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    samples: Vec<f32>,
+    channels: u16,
+    sample_rate: u32,
+    overview: Vec<(f32, f32)>,
+}
+
+impl DecodedAudio {
+    pub fn decode(path: &Path) -> Result<Self, DspError> {
+        let modified = modified_time(path);
+        let source = decode_file(path)?.convert_samples::<f32>();
+        let channels = source.channels().max(1);
+        let sample_rate = source.sample_rate();
+        let samples: Vec<f32> = source.collect();
+        let overview = waveform_overview(&samples, channels as usize, OVERVIEW_BUCKETS);
+        Ok(Self {
+            path: path.to_path_buf(),
+            modified,
+            samples,
+            channels,
+            sample_rate,
+            overview,
+        })
+    }
+
+    pub fn total_frames(&self) -> u64 {
+        (self.samples.len() / self.channels as usize) as u64
+    }
+
+    /// Whether this still holds what's in `path` on disk.
+    fn is_current(&self, path: &Path) -> bool {
+        self.path == path && self.modified.is_some() && modified_time(path) == self.modified
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// The loop region as saved in a session, in frames.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoopSettings {
+    pub enabled: bool,
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Playback position, seek requests and the A-B loop region, shared between
+/// the GUI and the `TransportSource` on the audio thread. All positions are
+/// in frames.
+pub struct Transport {
+    position: AtomicU64,
+    total_frames: AtomicU64,
+    sample_rate: AtomicU32,
+    seek_to: AtomicU64,
+    loop_enabled: AtomicBool,
+    loop_start: AtomicU64,
+    loop_end: AtomicU64,
+    /// Min/max peaks of the loaded file for the waveform view.
+    overview: Mutex<Vec<(f32, f32)>>,
+    /// The last file decoded, reused while it's unchanged on disk.
+    decoded: Mutex<Option<Arc<DecodedAudio>>>,
+}
+
+impl Transport {
+    pub fn new() -> Self {
+        Self {
+            position: AtomicU64::new(0),
+            total_frames: AtomicU64::new(0),
+            sample_rate: AtomicU32::new(0),
+            seek_to: AtomicU64::new(NO_SEEK),
+            loop_enabled: AtomicBool::new(false),
+            loop_start: AtomicU64::new(0),
+            loop_end: AtomicU64::new(0),
+            overview: Mutex::new(Vec::new()),
+            decoded: Mutex::new(None),
+        }
+    }
+
+    /// `path` decoded, reusing the last decode if it was the same file and
+    /// the file hasn't changed since.
+    pub fn decode(&self, path: &Path) -> Result<Arc<DecodedAudio>, DspError> {
+        if let Some(audio) = self.decoded.lock().as_ref().filter(|audio| audio.is_current(path)) {
+            return Ok(Arc::clone(audio));
+        }
+        let audio = Arc::new(DecodedAudio::decode(path)?);
+        *self.decoded.lock() = Some(Arc::clone(&audio));
+        Ok(audio)
+    }
+
+    /// Called when a new file starts. The loop region is kept, clamped to
+    /// the new length, so the same passage can be compared across files.
+    fn load(&self, audio: &DecodedAudio) {
+        let total_frames = audio.total_frames();
+        self.position.store(0, Ordering::Relaxed);
+        self.seek_to.store(NO_SEEK, Ordering::Relaxed);
+        self.total_frames.store(total_frames, Ordering::Relaxed);
+        self.sample_rate.store(audio.sample_rate, Ordering::Relaxed);
+        self.overview.lock().clone_from(&audio.overview);
+        self.loop_start.fetch_min(total_frames, Ordering::Relaxed);
+        self.loop_end.fetch_min(total_frames, Ordering::Relaxed);
+    }
+
+    /// Called when an endless source starts. There is nothing to seek in, and
+    /// the loop region is left as it was for the next file.
+    pub fn unload(&self, sample_rate: u32) {
+        self.position.store(0, Ordering::Relaxed);
+        self.seek_to.store(NO_SEEK, Ordering::Relaxed);
+        self.total_frames.store(0, Ordering::Relaxed);
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        self.overview.lock().clear();
+    }
+
+    /// Frame most recently read by the source. Runs ahead of what's audible
+    /// by up to one block plus the output buffer.
+    pub fn position(&self) -> u64 {
+        self.position.load(Ordering::Relaxed)
+    }
+
+    /// Min/max pairs covering the whole file in equal slices, empty when
+    /// nothing seekable is loaded.
+    pub fn overview(&self) -> MutexGuard<'_, Vec<(f32, f32)>> {
+        self.overview.lock()
+    }
+
+    pub fn total_frames(&self) -> u64 {
+        self.total_frames.load(Ordering::Relaxed)
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate.load(Ordering::Relaxed)
+    }
+
+    pub fn frames_to_secs(&self, frames: u64) -> f64 {
+        frames as f64 / self.sample_rate().max(1) as f64
+    }
+
+    pub fn secs_to_frames(&self, secs: f64) -> u64 {
+        ((secs.max(0.0) * self.sample_rate() as f64) as u64).min(self.total_frames())
+    }
+
+    /// Jumps to `frame` at the next frame the source reads. Called by
+    /// `BlockProcessor` at a block boundary; use `DspProcessor::seek` so the
+    /// processor is reset at the same point.
+    pub fn seek(&self, frame: u64) {
+        let frame = frame.min(self.total_frames());
+        self.position.store(frame, Ordering::Relaxed);
+        self.seek_to.store(frame, Ordering::Relaxed);
+    }
+
+    pub fn loop_enabled(&self) -> bool {
+        self.loop_enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_loop_enabled(&self, enabled: bool) {
+        self.loop_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Loop points in frames, whether or not looping is on.
+    pub fn loop_points(&self) -> (u64, u64) {
+        (self.loop_start.load(Ordering::Relaxed), self.loop_end.load(Ordering::Relaxed))
+    }
+
+    pub fn set_loop_points(&self, start: u64, end: u64) {
+        let total = self.total_frames();
+        self.loop_start.store(start.min(total), Ordering::Relaxed);
+        self.loop_end.store(end.min(total), Ordering::Relaxed);
+    }
+
+    pub fn loop_settings(&self) -> LoopSettings {
+        let (start, end) = self.loop_points();
+        LoopSettings { enabled: self.loop_enabled(), start, end }
+    }
+
+    /// Sets the loop region before its file is loaded; `load` clamps it to
+    /// the file's length once that is known.
+    pub fn restore_loop(&self, settings: &LoopSettings) {
+        self.loop_enabled.store(settings.enabled, Ordering::Relaxed);
+        self.loop_start.store(settings.start, Ordering::Relaxed);
+        self.loop_end.store(settings.end, Ordering::Relaxed);
+    }
+
+    /// The region being looped, if looping is on and the region isn't empty.
+    pub fn loop_region(&self) -> Option<(u64, u64)> {
+        let (start, end) = self.loop_points();
+        (self.loop_enabled() && end > start).then_some((start, end))
+    }
+}
+
+/// A decoded file held in memory and played under the control of a
+/// `Transport`, so it can seek and loop without touching the decoder.
+pub struct TransportSource {
+    audio: Arc<DecodedAudio>,
+    total_frames: u64,
+    frame: u64,
+    channel: usize,
+    transport: Arc<Transport>,
+}
+
+impl TransportSource {
+    pub fn new(audio: Arc<DecodedAudio>, transport: Arc<Transport>) -> Self {
+        transport.load(&audio);
+        Self {
+            total_frames: audio.total_frames(),
+            audio,
+            frame: 0,
+            channel: 0,
+            transport,
+        }
+    }
+}
+
+impl Iterator for TransportSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        // Seeks and loop jumps only happen between frames
+        if self.channel == 0 {
+            let seek = self.transport.seek_to.swap(NO_SEEK, Ordering::Relaxed);
+            if seek != NO_SEEK {
+                self.frame = seek.min(self.total_frames);
+            }
+            if let Some((start, end)) = self.transport.loop_region() {
+                if self.frame >= end {
+                    self.frame = start;
+                }
+            }
+            if self.frame >= self.total_frames {
+                return None;
+            }
+            self.transport.position.store(self.frame, Ordering::Relaxed);
+        }
+
+        let channels = self.audio.channels as usize;
+        let sample = self.audio.samples[self.frame as usize * channels + self.channel];
+        self.channel += 1;
+        if self.channel == channels {
+            self.channel = 0;
+            self.frame += 1;
+        }
+        Some(sample)
+    }
+}
+
+impl Source for TransportSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.audio.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.audio.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        // Unknown once seeking and looping are involved
+        None
+    }
+}
+
+/// "m:ss.s"
+pub fn format_time(secs: f64) -> String {
+    let minutes = (secs / 60.0).floor();
+    format!("{}:{:04.1}", minutes as u64, secs - minutes * 60.0)
+}
+
+/// Position bar with the loop region shaded. Click or drag to seek; returns
+/// the frame to seek to.
+pub fn show_position_bar(ui: &mut egui::Ui, transport: &Transport) -> Option<u64> {
+    let size = egui::vec2(ui.available_width(), 18.0);
+    let (response, painter) = ui.allocate_painter(size, Sense::click_and_drag());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+    let total = transport.total_frames();
+    if total == 0 {
+        return None;
+    }
+    let frame_to_x = |frame: u64| rect.left() + rect.width() * (frame as f32 / total as f32);
+
+    let (start, end) = transport.loop_points();
+    if end > start {
+        let alpha = if transport.loop_enabled() { 80 } else { 30 };
+        let region = Rect::from_min_max(Pos2::new(frame_to_x(start), rect.top()), Pos2::new(frame_to_x(end), rect.bottom()));
+        painter.rect_filled(region, 0.0, Color32::from_rgba_unmultiplied(90, 200, 255, alpha));
+    }
+
+    let x = frame_to_x(transport.position());
+    painter.line_segment(
+        [Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())],
+        Stroke::new(2.0, Color32::from_rgb(255, 210, 90)),
+    );
+
+    if response.clicked() || response.dragged() {
+        let pos = response.interact_pointer_pos()?;
+        let fraction = ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+        return Some((fraction as f64 * total as f64) as u64);
+    }
+    None
+}